@@ -4,54 +4,311 @@ mod astarfocal;
 pub(crate) use astar::{a_star_search, standard_a_star_search};
 pub(crate) use astarfocal::focal_a_star_search;
 
-use std::collections::{HashMap, HashSet};
+use astar::standard_a_star_search_segment;
+use astarfocal::standard_focal_a_star_search;
 
-use crate::common::{Agent, Constraint, Mdd, MddNode, Path};
-use crate::map::Map;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::common::{
+    Agent, Constraint, ConstraintIndex, EdgeId, FocalOrderWrapper, Mdd, MddEdge, MddNode, NodeId,
+    OpenOrderWrapper, Path,
+};
+use crate::map::{HeuristicTable, Map};
+use crate::stat::Stats;
 
 type Trace = HashMap<((usize, usize), usize), ((usize, usize), usize)>;
 
-// TODO: different kinds of hc
+// Low-level focal list tie-breaking heuristics, selected via
+// `Config::focal_heuristic`:
 // h1: Number of conflicts
 // h2: Number of conflicting agents
-// h3: Number of pairs
-// h4: Vertex Cover
-// h5: Alternating heuristic
-fn heuristic_focal(
-    agent: usize,
-    position: (usize, usize),
-    prev_position: (usize, usize),
-    time: usize,
-    paths: &[Path],
-) -> usize {
-    // Tricky: we never call this function when time step is 0.
-    assert_ne!(time, 0);
+// h3: Number of conflicting agent pairs at the landing vertex
+// h4: Minimum vertex cover of the conflicts (a star centered on this
+//     agent, so it's 1 iff there is any conflict, else 0)
+// h5: Alternating heuristic, a different low-level search strategy
+//     entirely (see `alternating_focal_a_star_search`), not a scalar
+//     computed here.
+//
+// `ConflictTable::heuristic_focal` computes the same counts a plain
+// per-expansion rescan of `paths` would, but without that rescan: finding
+// who occupies `position`/makes the reverse edge move at `time` by
+// re-scanning every other agent's entire path on every expansion dominates
+// low-level search time in dense scenarios. `ConflictTable` instead
+// indexes `paths` once up front into `position @ time -> agent ids` and
+// `(from, to) @ time -> agent ids` maps (plus a "settled at goal forever
+// after the path ends" index, matching `path.get(time).unwrap_or_else(||
+// path.last())`'s semantics), turning each expansion's lookup into O(1)
+// hashing instead of an O(paths.len()) scan.
+pub(crate) struct ConflictTable {
+    // Agents occupying `position` at `time`, for `time` within the path
+    // (i.e. `time <= ` that agent's final index).
+    vertex_moving: HashMap<((usize, usize), usize), Vec<usize>>,
+    // Agents permanently occupying `goal` once their path has ended,
+    // alongside the final index their path actually reaches `goal` at
+    // (`vertex_moving` already covers that exact time step; this only
+    // applies to times strictly after it).
+    vertex_settled: HashMap<(usize, usize), Vec<(usize, usize)>>,
+    // Agents moving from `from` to `to` exactly at `time`.
+    edge_moving: HashMap<((usize, usize), (usize, usize), usize), Vec<usize>>,
+}
 
-    let mut conflict_count = 0;
+impl ConflictTable {
+    pub(crate) fn build(paths: &[Path]) -> Self {
+        let mut vertex_moving: HashMap<_, Vec<usize>> = HashMap::new();
+        let mut vertex_settled: HashMap<_, Vec<(usize, usize)>> = HashMap::new();
+        let mut edge_moving: HashMap<_, Vec<usize>> = HashMap::new();
 
-    for (agent_id, path) in paths.iter().enumerate() {
-        if agent_id == agent {
-            continue; // Skip the current agent to avoid self-conflict.
+        for (agent_id, path) in paths.iter().enumerate() {
+            let Some(&goal) = path.last() else {
+                continue;
+            };
+            let last_index = path.len() - 1;
+
+            for (time, &position) in path.iter().enumerate() {
+                vertex_moving
+                    .entry((position, time))
+                    .or_default()
+                    .push(agent_id);
+                if time >= 1 {
+                    edge_moving
+                        .entry((path[time - 1], position, time))
+                        .or_default()
+                        .push(agent_id);
+                }
+            }
+
+            vertex_settled
+                .entry(goal)
+                .or_default()
+                .push((agent_id, last_index));
         }
 
-        let other_position = path.get(time).unwrap_or_else(|| path.last().unwrap());
+        ConflictTable {
+            vertex_moving,
+            vertex_settled,
+            edge_moving,
+        }
+    }
 
-        // Check for vertex conflict.
-        if *other_position == position {
+    /// Every agent other than `agent` occupying `position` at `time`,
+    /// matching `path.get(time).unwrap_or_else(|| path.last())`'s "sits at
+    /// its goal forever once its path ends" semantics.
+    fn occupants_at(
+        &self,
+        agent: usize,
+        position: (usize, usize),
+        time: usize,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let moving = self
+            .vertex_moving
+            .get(&(position, time))
+            .into_iter()
+            .flatten()
+            .copied();
+        let settled = self
+            .vertex_settled
+            .get(&position)
+            .into_iter()
+            .flatten()
+            .filter(move |&&(_, last_index)| time > last_index)
+            .map(|&(other_agent, _)| other_agent);
+        moving.chain(settled).filter(move |&other| other != agent)
+    }
+
+    fn heuristic_focal(
+        &self,
+        agent: usize,
+        position: (usize, usize),
+        prev_position: (usize, usize),
+        time: usize,
+        focal_heuristic: &str,
+    ) -> usize {
+        // Tricky: we never call this function when time step is 0.
+        assert_ne!(time, 0);
+
+        let mut conflict_count = 0;
+        let mut conflicting_agents = HashSet::new();
+        let mut colocated_agents = 1; // This agent itself.
+
+        for other_agent in self.occupants_at(agent, position, time) {
             conflict_count += 1;
+            conflicting_agents.insert(other_agent);
+            colocated_agents += 1;
         }
 
-        // Check for edge conflict.
-        if time >= path.len() {
-            continue;
+        if let Some(other_agents) = self.edge_moving.get(&(position, prev_position, time)) {
+            for &other_agent in other_agents {
+                if other_agent == agent {
+                    continue;
+                }
+                conflict_count += 1;
+                conflicting_agents.insert(other_agent);
+            }
         }
-        let other_prev_position = path.get(time - 1).unwrap();
-        if (*other_position == prev_position) && (*other_prev_position == position) {
-            conflict_count += 1;
+
+        match focal_heuristic {
+            "h1" => conflict_count,
+            "h2" => conflicting_agents.len(),
+            "h3" => colocated_agents * (colocated_agents - 1) / 2,
+            "h4" => usize::from(conflict_count > 0),
+            _ => unreachable!("focal heuristic should be validated by Config::validate"),
+        }
+    }
+}
+
+/// Low-level open-list ordering, selected via `Config::low_level_mode`:
+/// "astar": exact admissible f = g + h (the default; required for the
+///     optimality/sub-optimality guarantees every solver advertises).
+/// "weighted_astar": f = g + h * `low_level_weight`, inflating h to bias
+///     the search towards the goal at the cost of admissibility.
+/// "greedy": f = h, ignoring g entirely so the search always steps
+///     towards whichever neighbor looks closest to the goal.
+fn low_level_f_cost(mode: &str, weight: Option<f64>, g_cost: usize, h_cost: usize) -> usize {
+    match mode {
+        "astar" => g_cost + h_cost,
+        "weighted_astar" => g_cost + (h_cost as f64 * weight.unwrap()).round() as usize,
+        "greedy" => h_cost,
+        _ => unreachable!("low-level mode should be validated by Config::validate"),
+    }
+}
+
+/// Beam-width bound for the open list: once a g-cost layer has had all of
+/// its neighbors generated, drop all but the best `beam_width` entries in
+/// that layer by `f_open_cost`. Called right after expanding a node, so
+/// the next pop only ever sees the retained survivors; trades
+/// completeness for a bounded frontier on large maps. A no-op when the
+/// layer is already within budget. Returns the dropped nodes so a caller
+/// that also maintains a paired focal list / focal-cost map (see
+/// [`apply_paired_beam_width`]) can keep those in sync; single-list
+/// callers just discard the return value.
+fn apply_open_beam_width(
+    open_list: &mut BTreeSet<OpenOrderWrapper>,
+    g_cost: usize,
+    beam_width: usize,
+) -> Vec<OpenOrderWrapper> {
+    let mut at_layer: Vec<usize> = open_list
+        .iter()
+        .filter(|entry| entry.0.borrow().g_cost == g_cost)
+        .map(|entry| entry.0.borrow().f_open_cost)
+        .collect();
+    if at_layer.len() <= beam_width {
+        return Vec::new();
+    }
+    at_layer.sort_unstable();
+    let cutoff = at_layer[beam_width - 1];
+    let allowed_at_cutoff = beam_width - at_layer.partition_point(|&c| c < cutoff);
+
+    let mut kept_at_cutoff = 0;
+    let mut dropped = Vec::new();
+    open_list.retain(|entry| {
+        let node = entry.0.borrow();
+        if node.g_cost != g_cost {
+            return true;
+        }
+        let keep = match node.f_open_cost.cmp(&cutoff) {
+            Ordering::Less => true,
+            Ordering::Equal => {
+                kept_at_cutoff += 1;
+                kept_at_cutoff <= allowed_at_cutoff
+            }
+            Ordering::Greater => false,
+        };
+        if !keep {
+            dropped.push(OpenOrderWrapper::from_node(&entry.0));
+        }
+        keep
+    });
+    dropped
+}
+
+/// Same idea as [`apply_open_beam_width`], but for the focal list, ranked
+/// by `f_focal_cost` (the `BTreeSet`'s own `Ord` already breaks ties by
+/// `f_open_cost` then `g_cost`, so iterating it in order already yields
+/// that tie-break within a layer). Returns the dropped nodes, see
+/// [`apply_open_beam_width`].
+fn apply_focal_beam_width(
+    focal_list: &mut BTreeSet<FocalOrderWrapper>,
+    g_cost: usize,
+    beam_width: usize,
+) -> Vec<FocalOrderWrapper> {
+    let mut at_layer: Vec<usize> = focal_list
+        .iter()
+        .filter(|entry| entry.0.borrow().g_cost == g_cost)
+        .map(|entry| entry.0.borrow().f_focal_cost)
+        .collect();
+    if at_layer.len() <= beam_width {
+        return Vec::new();
+    }
+    at_layer.sort_unstable();
+    let cutoff = at_layer[beam_width - 1];
+
+    let mut kept_at_cutoff = 0;
+    let allowed_at_cutoff = beam_width - at_layer.partition_point(|&c| c < cutoff);
+    let mut dropped = Vec::new();
+    focal_list.retain(|entry| {
+        let node = entry.0.borrow();
+        if node.g_cost != g_cost {
+            return true;
         }
+        let keep = match node.f_focal_cost.cmp(&cutoff) {
+            Ordering::Less => true,
+            Ordering::Equal => {
+                kept_at_cutoff += 1;
+                kept_at_cutoff <= allowed_at_cutoff
+            }
+            Ordering::Greater => false,
+        };
+        if !keep {
+            dropped.push(FocalOrderWrapper::from_node(&entry.0));
+        }
+        keep
+    });
+    dropped
+}
+
+/// Beam-width bound shared by `standard_focal_a_star_search`/
+/// `alternating_focal_a_star_search`, whose `open_list` and `focal_list`
+/// track the same underlying nodes one-for-one (keyed by
+/// `(position, g_cost)` in `f_focal_cost_map`). Pruning either list on its
+/// own would break that invariant: a node dropped from `open_list` but
+/// left in `focal_list` makes the `assert!(open_list.remove(..))` on its
+/// later expansion panic, and a node dropped from `focal_list` but left in
+/// `open_list` gets silently re-admitted to focal by the f_min-increase
+/// rescan that follows, defeating the point of the beam. So every node
+/// either prune drops is also evicted from the other list and from
+/// `f_focal_cost_map`, keeping all three in lockstep.
+/// `stats.low_level_pruned_nodes` counts the drops: nonzero means this run
+/// traded the solver's suboptimality guarantee for a capped frontier, since
+/// a node within the bound may have been discarded before it expanded.
+fn apply_paired_beam_width(
+    open_list: &mut BTreeSet<OpenOrderWrapper>,
+    focal_list: &mut BTreeSet<FocalOrderWrapper>,
+    f_focal_cost_map: &mut HashMap<((usize, usize), usize), usize>,
+    g_cost: usize,
+    beam_width: usize,
+    stats: &mut Stats,
+) {
+    let dropped_from_open = apply_open_beam_width(open_list, g_cost, beam_width);
+    for node in &dropped_from_open {
+        focal_list.remove(&FocalOrderWrapper::from_node(&node.0));
     }
 
-    conflict_count
+    let dropped_from_focal = apply_focal_beam_width(focal_list, g_cost, beam_width);
+    for node in &dropped_from_focal {
+        open_list.remove(&OpenOrderWrapper::from_node(&node.0));
+    }
+
+    for node_rc in dropped_from_open
+        .iter()
+        .map(|w| &w.0)
+        .chain(dropped_from_focal.iter().map(|w| &w.0))
+    {
+        let node = node_rc.borrow();
+        f_focal_cost_map.remove(&(node.position, node.g_cost));
+    }
+
+    stats.low_level_pruned_nodes += dropped_from_open.len() + dropped_from_focal.len();
 }
 
 fn construct_path(trace: &Trace, mut current: ((usize, usize), usize)) -> Path {
@@ -64,57 +321,214 @@ fn construct_path(trace: &Trace, mut current: ((usize, usize), usize)) -> Path {
     path
 }
 
-fn construct_mdd(
+/// Builds the agent's multi-value decision diagram for its optimal cost
+/// under `constraints` in two passes: a forward pass reachable from
+/// `agent.start` (pruned by `map.heuristic` so no node further from the
+/// goal than the remaining depth survives), then a backward pass from the
+/// single goal node at layer `optimal_cost` that drops any node with no
+/// surviving child. The combination makes every layer exact: a node
+/// remains in layer `t` iff it lies on at least one optimal,
+/// constraint-respecting path of length `optimal_cost`, which is what
+/// `is_singleton_at_position` relies on for cardinal-conflict reasoning.
+/// The two passes double as the arena's top-down/bottom-up value
+/// assignment: every surviving node at depth `t` is exactly `t` steps from
+/// `agent.start` (`value`) and has a path to the goal node `optimal_cost -
+/// t` steps away (`value_bot`), since layers are unit-cost BFS depths and
+/// the backward pass only keeps nodes with a surviving route to the goal.
+pub(crate) fn construct_mdd(
     map: &Map,
     agent: &Agent,
     constraints: &HashSet<Constraint>,
     optimal_cost: usize,
 ) -> Mdd {
-    let mut mdd = vec![HashMap::new(); optimal_cost + 1];
+    let constraint_index = ConstraintIndex::build(constraints);
 
-    mdd[0].insert(
-        agent.start,
-        MddNode {
-            parents: HashSet::new(),
-            children: HashSet::new(),
-        },
-    );
+    // Candidate layers, kept as plain hashmaps while building (fast
+    // "have we seen this position at this depth" lookups); flattened into
+    // the arena once both passes have settled which nodes survive.
+    let mut forward: Vec<HashMap<(usize, usize), HashSet<(usize, usize)>>> =
+        vec![HashMap::new(); optimal_cost + 1];
+    forward[0].insert(agent.start, HashSet::new());
 
     // Forward pass
     for depth in 0..optimal_cost {
-        for (&pos, _) in mdd[depth].clone().iter() {
+        for pos in forward[depth].keys().copied().collect::<Vec<_>>() {
             for neighbor in map.get_neighbors(pos.0, pos.1, true) {
-                if constraints
-                    .iter()
-                    .any(|c| c.is_violated(pos, neighbor, depth + 1))
-                {
+                if constraint_index.is_violated(pos, neighbor, depth + 1) {
                     continue;
                 }
 
-                if map.heuristic[agent.id][neighbor.0][neighbor.1] <= optimal_cost - (depth + 1) {
-                    let next_node = mdd[depth + 1].entry(neighbor).or_insert(MddNode {
-                        parents: HashSet::new(),
-                        children: HashSet::new(),
-                    });
-                    next_node.parents.insert(pos);
-                    mdd[depth].get_mut(&pos).unwrap().children.insert(neighbor);
+                if map.heuristic[agent.id].get(neighbor) <= optimal_cost - (depth + 1) {
+                    forward[depth + 1].entry(neighbor).or_default();
+                    forward[depth].get_mut(&pos).unwrap().insert(neighbor);
                 }
             }
         }
     }
 
-    assert_eq!(mdd[optimal_cost].len(), 1);
-    assert!(mdd[optimal_cost].contains_key(&agent.goal));
+    assert_eq!(forward[optimal_cost].len(), 1);
+    assert!(forward[optimal_cost].contains_key(&agent.goal));
 
     // Backward pass
     for depth in (0..optimal_cost).rev() {
-        let next_layer = mdd[depth + 1].clone();
-        mdd[depth].retain(|_, node| {
-            node.children
+        let next_layer_positions: HashSet<(usize, usize)> =
+            forward[depth + 1].keys().copied().collect();
+        forward[depth].retain(|_, children| {
+            children
                 .iter()
-                .any(|child| next_layer.contains_key(child))
+                .any(|child| next_layer_positions.contains(child))
         });
+        for children in forward[depth].values_mut() {
+            children.retain(|child| next_layer_positions.contains(child));
+        }
+    }
+
+    // Flatten into the arena: each layer is appended in one contiguous
+    // chunk, so `layers[depth]` is just the `NodeId` range that chunk
+    // occupied.
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut layers = Vec::with_capacity(optimal_cost + 1);
+    let mut prev_layer_ids: HashMap<(usize, usize), NodeId> = HashMap::new();
+
+    for (depth, layer) in forward.iter().enumerate() {
+        let start = nodes.len();
+        let mut positions: Vec<_> = layer.keys().copied().collect();
+        positions.sort_unstable();
+
+        let mut layer_ids = HashMap::with_capacity(positions.len());
+        for position in positions {
+            let id = NodeId(nodes.len());
+            layer_ids.insert(position, id);
+            nodes.push(MddNode {
+                position,
+                in_edges: Vec::new(),
+                out_edges: Vec::new(),
+                value: depth,
+                value_bot: optimal_cost - depth,
+            });
+        }
+        layers.push(start..nodes.len());
+
+        if depth > 0 {
+            for (&from_position, &from_id) in &prev_layer_ids {
+                for child in &forward[depth - 1][&from_position] {
+                    if let Some(&to_id) = layer_ids.get(child) {
+                        let edge_id = EdgeId(edges.len());
+                        edges.push(MddEdge {
+                            from: from_id,
+                            to: to_id,
+                        });
+                        nodes[from_id.0].out_edges.push(edge_id);
+                        nodes[to_id.0].in_edges.push(edge_id);
+                    }
+                }
+            }
+        }
+
+        prev_layer_ids = layer_ids;
+    }
+
+    Mdd {
+        nodes,
+        edges,
+        layers,
+    }
+}
+
+/// Bounded-suboptimal low-level search that plans on `map`'s cluster/
+/// entrance abstraction (`Map::hierarchical_waypoints`) before refining at
+/// full resolution, instead of running `standard_focal_a_star_search` over
+/// the whole grid directly -- worthwhile on large maps where that full
+/// search dominates runtime. Each leg between consecutive waypoints is
+/// refined by `standard_a_star_search_segment`, the same per-segment search
+/// multi-stop agents use, with `constraints` checked against absolute time
+/// as usual by carrying the previous leg's final `g_cost`/time step
+/// forward. Because concatenated segments are only an upper bound on the
+/// true shortest path, the result is accepted only when it still respects
+/// `subopt_factor` and `path_length_constraint`; anything else -- no
+/// abstraction configured, no route found, or the concatenated path
+/// overshoots its bound -- falls back to the exact `standard_focal_a_star_search`
+/// so this shortcut can never be the reason CBS's suboptimality guarantee
+/// is violated.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn hierarchical_focal_a_star_search(
+    map: &Map,
+    agent: &Agent,
+    subopt_factor: f64,
+    constraints: &HashSet<Constraint>,
+    path_length_constraint: usize,
+    constraint_limit_time_step: usize,
+    paths: &[Path],
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
+    stats: &mut Stats,
+) -> Option<(Path, usize)> {
+    let fallback = |stats: &mut Stats| {
+        standard_focal_a_star_search(
+            map,
+            agent,
+            subopt_factor,
+            constraints,
+            path_length_constraint,
+            constraint_limit_time_step,
+            paths,
+            focal_heuristic,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
+            stats,
+        )
+    };
+
+    let Some(waypoints) = map.hierarchical_waypoints(agent.start, agent.goal) else {
+        return fallback(stats);
+    };
+    // Same chunk: the abstraction has nothing to offer over a direct search.
+    if waypoints.len() <= 2 {
+        return fallback(stats);
+    }
+
+    let mut full_path: Path = vec![agent.start];
+    let mut leg_start = agent.start;
+    let mut g_cost = 0usize;
+    for &leg_goal in &waypoints[1..] {
+        let leg_heuristic = HeuristicTable::Exact(map.heuristic_dji(leg_goal));
+        let time_step = g_cost.min(constraint_limit_time_step + 1);
+        let mut leg_closest_to_goal = None;
+        let Some((leg_path, _)) = standard_a_star_search_segment(
+            map,
+            leg_start,
+            leg_goal,
+            &leg_heuristic,
+            constraints,
+            0,
+            constraint_limit_time_step,
+            g_cost,
+            time_step,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
+            &mut leg_closest_to_goal,
+            stats,
+        ) else {
+            return fallback(stats);
+        };
+
+        full_path.extend(leg_path.into_iter().skip(1));
+        g_cost = full_path.len() - 1;
+        leg_start = leg_goal;
+    }
+
+    let hierarchical_cost = full_path.len() - 1;
+    let optimal_cost = map.heuristic[agent.id].get(agent.start);
+    if hierarchical_cost > path_length_constraint
+        && (hierarchical_cost as f64) <= subopt_factor * optimal_cost as f64
+    {
+        return Some((full_path, hierarchical_cost));
     }
 
-    mdd
+    fallback(stats)
 }
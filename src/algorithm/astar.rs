@@ -1,17 +1,27 @@
-use super::{construct_mdd, construct_path, heuristic_focal};
-use crate::common::{create_focal_node, create_open_node, Agent, Constraint, Path, SearchResult};
-use crate::map::Map;
+use super::{
+    apply_focal_beam_width, apply_open_beam_width, construct_mdd, construct_path, low_level_f_cost,
+    ConflictTable,
+};
+use crate::common::{
+    create_focal_node, create_open_node, Agent, Constraint, ConstraintIndex, ConstraintKind, Path,
+    SearchResult,
+};
+use crate::map::{HeuristicTable, Map};
 use crate::stat::Stats;
 
 use std::collections::{BTreeSet, HashMap, HashSet};
 use tracing::{debug, instrument, trace};
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn a_star_search(
     map: &Map,
     agent: &Agent,
     constraints: &HashSet<Constraint>,
     path_length_constraint: usize,
     build_mdd: bool,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> SearchResult {
     let constraint_limit_time_step = constraints
@@ -23,27 +33,76 @@ pub(crate) fn a_star_search(
         .max()
         .unwrap_or(0);
 
-    if !build_mdd {
-        return SearchResult::Standard(standard_a_star_search_open_cost(
+    if agent.waypoints.as_ref().is_some_and(|w| !w.is_empty()) {
+        if build_mdd {
+            debug!(
+                "agent {} has waypoints; MDD construction is not supported for multi-stop agents, planning a plain path instead",
+                agent.id
+            );
+        }
+        return SearchResult::Standard(standard_a_star_search_with_waypoints(
             map,
             agent,
             constraints,
             path_length_constraint,
             constraint_limit_time_step,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
             stats,
         ));
     }
 
+    if !build_mdd {
+        let mut closest_to_goal = None;
+        return match standard_a_star_search_open_cost(
+            map,
+            agent,
+            constraints,
+            path_length_constraint,
+            constraint_limit_time_step,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
+            &mut closest_to_goal,
+            stats,
+        ) {
+            Some((path, f_min)) => SearchResult::Standard(Some((path, f_min))),
+            None => match closest_to_goal {
+                Some((path, reached, h_remaining)) => SearchResult::Partial {
+                    path,
+                    reached,
+                    h_remaining,
+                },
+                None => SearchResult::Standard(None),
+            },
+        };
+    }
+
+    let mut closest_to_goal = None;
     let (path, f_min) = match standard_a_star_search_open_cost(
         map,
         agent,
         constraints,
         path_length_constraint,
         constraint_limit_time_step,
+        low_level_mode,
+        low_level_weight,
+        beam_width,
+        &mut closest_to_goal,
         stats,
     ) {
         Some((path, f_min)) => (path, f_min),
-        None => return SearchResult::WithMDD(None),
+        None => {
+            return match closest_to_goal {
+                Some((path, reached, h_remaining)) => SearchResult::Partial {
+                    path,
+                    reached,
+                    h_remaining,
+                },
+                None => SearchResult::WithMDD(None),
+            }
+        }
     };
 
     // f min should equal to cost.
@@ -57,6 +116,7 @@ pub(crate) fn a_star_search(
     )))
 }
 
+#[allow(clippy::too_many_arguments)]
 #[instrument(skip_all, name="standard_a_star_open_cost", fields(agent = agent.id, start = format!("{:?}", agent.start), goal = format!("{:?}", agent.goal)), level = "debug")]
 pub(crate) fn standard_a_star_search_open_cost(
     map: &Map,
@@ -64,6 +124,53 @@ pub(crate) fn standard_a_star_search_open_cost(
     constraints: &HashSet<Constraint>,
     path_length_constraint: usize,
     constraint_limit_time_step: usize,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
+    closest_to_goal: &mut Option<(Path, (usize, usize), usize)>,
+    stats: &mut Stats,
+) -> Option<(Path, usize)> {
+    standard_a_star_search_segment(
+        map,
+        agent.start,
+        agent.goal,
+        &map.heuristic[agent.id],
+        constraints,
+        path_length_constraint,
+        constraint_limit_time_step,
+        0,
+        0,
+        low_level_mode,
+        low_level_weight,
+        beam_width,
+        closest_to_goal,
+        stats,
+    )
+}
+
+/// One leg of a single-agent search: plans `start` -> `goal` under
+/// `heuristic`, seeding the root node with `start_g_cost`/`start_time_step`
+/// so that `constraints` -- always indexed by absolute time step -- are
+/// checked correctly even when this leg doesn't start at time zero. Shared
+/// by `standard_a_star_search_open_cost` (the single `agent.start` ->
+/// `agent.goal` leg) and `standard_a_star_search_with_waypoints` (one call
+/// per hop of a multi-stop route).
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, name="standard_a_star_segment", fields(start = format!("{:?}", start), goal = format!("{:?}", goal)), level = "debug")]
+pub(super) fn standard_a_star_search_segment(
+    map: &Map,
+    start: (usize, usize),
+    goal: (usize, usize),
+    heuristic: &HeuristicTable,
+    constraints: &HashSet<Constraint>,
+    path_length_constraint: usize,
+    constraint_limit_time_step: usize,
+    start_g_cost: usize,
+    start_time_step: usize,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
+    closest_to_goal: &mut Option<(Path, (usize, usize), usize)>,
     stats: &mut Stats,
 ) -> Option<(Path, usize)> {
     debug!("constraints: {constraints:?}, limit time step: {constraint_limit_time_step:?}");
@@ -72,9 +179,27 @@ pub(crate) fn standard_a_star_search_open_cost(
     let mut open_list = BTreeSet::new();
     let mut closed_list = HashSet::new();
     let mut trace = HashMap::new();
-
-    let start_h_open_cost = map.heuristic[agent.id][agent.start.0][agent.start.1];
-    let start_node = create_open_node(agent.start, start_h_open_cost, 0, 0);
+    let constraint_index = ConstraintIndex::build(constraints);
+
+    // Best-effort fallback, maintained for `closest_to_goal`: the smallest
+    // `h_remaining` seen on any expanded node, and its `(position, g_cost)`
+    // trace key, so that if the goal is never reached we can still hand the
+    // caller a path to the closest point the search got.
+    let mut closest_h_remaining = usize::MAX;
+    let mut closest_key = (start, start_g_cost);
+
+    let start_h_open_cost = heuristic.get(start);
+    let start_node = create_open_node(
+        start,
+        low_level_f_cost(
+            low_level_mode,
+            low_level_weight,
+            start_g_cost,
+            start_h_open_cost,
+        ),
+        start_g_cost,
+        start_time_step,
+    );
     open_list.insert(start_node);
 
     while let Some(current_wrapper) = open_list.pop_first() {
@@ -85,13 +210,19 @@ pub(crate) fn standard_a_star_search_open_cost(
         // Update stats.
         stats.low_level_expand_open_nodes += 1;
 
-        if current.position == agent.goal && current.g_cost > path_length_constraint {
+        if current.position == goal && current.g_cost > path_length_constraint {
             return Some((
                 construct_path(&trace, (current.position, current.g_cost)),
                 current.f_open_cost,
             ));
         }
 
+        let h_remaining = heuristic.get(current.position);
+        if h_remaining < closest_h_remaining {
+            closest_h_remaining = h_remaining;
+            closest_key = (current.position, current.g_cost);
+        }
+
         closed_list.insert((current.position, current.time_step));
 
         // Assuming uniform cost, which also indicates the current time
@@ -118,18 +249,21 @@ pub(crate) fn standard_a_star_search_open_cost(
             }
 
             // Check for constraints before exploring the neighbor
-            if constraints.iter().any(|constraint| {
-                constraint.is_violated(current.position, *neighbor, tentative_g_cost)
-            }) {
+            if constraint_index.is_violated(current.position, *neighbor, tentative_g_cost) {
                 continue; // This move is prohibited due to a constraint.
             }
 
-            let h_open_cost = map.heuristic[agent.id][neighbor.0][neighbor.1];
+            let h_open_cost = heuristic.get(*neighbor);
 
             // Create a new open node wrapper
             let neighbor_wrapper = create_open_node(
                 *neighbor,
-                tentative_g_cost + h_open_cost,
+                low_level_f_cost(
+                    low_level_mode,
+                    low_level_weight,
+                    tentative_g_cost,
+                    h_open_cost,
+                ),
                 tentative_g_cost,
                 tentative_time_step,
             );
@@ -142,13 +276,101 @@ pub(crate) fn standard_a_star_search_open_cost(
                 );
             }
         }
+
+        if let Some(beam_width) = beam_width {
+            stats.low_level_pruned_nodes +=
+                apply_open_beam_width(&mut open_list, tentative_g_cost, beam_width).len();
+        }
+
         trace!("open list {open_list:?}");
     }
 
     debug!("cannot find solution");
+    *closest_to_goal = Some((
+        construct_path(&trace, closest_key),
+        closest_key.0,
+        closest_h_remaining,
+    ));
     None
 }
 
+/// Plans `agent.start -> w1 -> ... -> wn -> agent.goal` as concatenated
+/// segments (`agent.resolve_waypoint_order` decides the stop order), each
+/// solved by `standard_a_star_search_segment` with the previous leg's final
+/// `g_cost`/`time_step` carried forward as the next leg's start, so
+/// `constraints` -- checked against absolute time steps -- apply correctly
+/// across the whole route. Intermediate legs target their waypoint with a
+/// fresh exact heuristic table (`Map::heuristic_dji`); only the final leg
+/// uses the agent's precomputed `map.heuristic[agent.id]` table, since that
+/// one is rooted at `agent.goal`. `path_length_constraint` only gates the
+/// final leg, matching the original meaning of "don't reach the goal before
+/// this g_cost".
+#[allow(clippy::too_many_arguments)]
+fn standard_a_star_search_with_waypoints(
+    map: &Map,
+    agent: &Agent,
+    constraints: &HashSet<Constraint>,
+    path_length_constraint: usize,
+    constraint_limit_time_step: usize,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
+    stats: &mut Stats,
+) -> Option<(Path, usize)> {
+    let stops = agent.resolve_waypoint_order(map);
+
+    let mut full_path: Path = vec![agent.start];
+    let mut leg_start = agent.start;
+    let mut g_cost = 0usize;
+
+    let legs = stops.len() + 1;
+    for (i, &leg_goal) in stops.iter().chain(std::iter::once(&agent.goal)).enumerate() {
+        let is_final_leg = i + 1 == legs;
+        let owned_heuristic;
+        let heuristic = if is_final_leg {
+            &map.heuristic[agent.id]
+        } else {
+            owned_heuristic = HeuristicTable::Exact(map.heuristic_dji(leg_goal));
+            &owned_heuristic
+        };
+        let leg_path_length_constraint = if is_final_leg {
+            path_length_constraint
+        } else {
+            0
+        };
+        let time_step = g_cost.min(constraint_limit_time_step + 1);
+
+        // A partial result for one leg of a multi-stop route isn't a
+        // meaningful "closest approach to the goal" for the whole agent, so
+        // it's discarded here; only the single-leg callers thread
+        // `closest_to_goal` through to `SearchResult::Partial`.
+        let mut leg_closest_to_goal = None;
+        let (leg_path, _) = standard_a_star_search_segment(
+            map,
+            leg_start,
+            leg_goal,
+            heuristic,
+            constraints,
+            leg_path_length_constraint,
+            constraint_limit_time_step,
+            g_cost,
+            time_step,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
+            &mut leg_closest_to_goal,
+            stats,
+        )?;
+
+        full_path.extend(leg_path.into_iter().skip(1));
+        g_cost = full_path.len() - 1;
+        leg_start = leg_goal;
+    }
+
+    let f_min = full_path.len() - 1;
+    Some((full_path, f_min))
+}
+
 #[allow(clippy::too_many_arguments)]
 #[instrument(skip_all, name="standard_a_star_focal_cost", fields(agent = agent.id, start = format!("{:?}", agent.start), goal = format!("{:?}", agent.goal)), level = "debug")]
 pub(crate) fn standard_a_star_search_focal_cost(
@@ -159,17 +381,30 @@ pub(crate) fn standard_a_star_search_focal_cost(
     paths: &[Path],
     constraint_limit_time_step: usize,
     opt_cost: f64,
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> Option<(Path, usize)> {
     debug!("constraints: {constraints:?}, limit time step: {constraint_limit_time_step:?}");
 
+    let conflict_table = ConflictTable::build(paths);
+
     #[allow(clippy::mutable_key_type)]
     let mut focal_list = BTreeSet::new();
     let mut closed_list = HashSet::new();
     let mut trace = HashMap::new();
-
-    let start_h_open_cost = map.heuristic[agent.id][agent.start.0][agent.start.1];
-    let start_node = create_focal_node(agent.start, start_h_open_cost, 0, 0, 0);
+    let constraint_index = ConstraintIndex::build(constraints);
+
+    let start_h_open_cost = map.heuristic[agent.id].get(agent.start);
+    let start_node = create_focal_node(
+        agent.start,
+        low_level_f_cost(low_level_mode, low_level_weight, 0, start_h_open_cost),
+        0,
+        0,
+        0,
+    );
     focal_list.insert(start_node);
 
     while let Some(current_wrapper) = focal_list.pop_first() {
@@ -207,7 +442,13 @@ pub(crate) fn standard_a_star_search_focal_cost(
             current.position.1,
             !exceed_constraints_limit_time_step,
         ) {
-            let f_open_cost = tentative_g_cost + map.heuristic[agent.id][neighbor.0][neighbor.1];
+            let h_open_cost = map.heuristic[agent.id].get(*neighbor);
+            let f_open_cost = low_level_f_cost(
+                low_level_mode,
+                low_level_weight,
+                tentative_g_cost,
+                h_open_cost,
+            );
 
             // Check if node has bounded cost.
             if f_open_cost as f64 > opt_cost {
@@ -220,19 +461,17 @@ pub(crate) fn standard_a_star_search_focal_cost(
             }
 
             // Check for constraints before exploring the neighbor
-            if constraints.iter().any(|constraint| {
-                constraint.is_violated(current.position, *neighbor, tentative_g_cost)
-            }) {
+            if constraint_index.is_violated(current.position, *neighbor, tentative_g_cost) {
                 continue; // This move is prohibited due to a constraint.
             }
 
             let f_focal_cost = current.f_focal_cost
-                + heuristic_focal(
+                + conflict_table.heuristic_focal(
                     agent.id,
                     *neighbor,
                     current.position,
                     tentative_g_cost,
-                    paths,
+                    focal_heuristic,
                 );
 
             // Create a new focal node wrapper
@@ -252,6 +491,12 @@ pub(crate) fn standard_a_star_search_focal_cost(
                 );
             }
         }
+
+        if let Some(beam_width) = beam_width {
+            stats.low_level_pruned_nodes +=
+                apply_focal_beam_width(&mut focal_list, tentative_g_cost, beam_width).len();
+        }
+
         trace!("open list {focal_list:?}");
     }
 
@@ -279,6 +524,7 @@ mod tests {
         match result {
             SearchResult::Standard(result) => result,
             SearchResult::WithMDD(result) => result.map(|(path, cost, _)| (path, cost)),
+            SearchResult::Partial { .. } => None,
         }
     }
 
@@ -288,7 +534,8 @@ mod tests {
         layer: usize,
         expected_positions: HashSet<(usize, usize)>,
     ) {
-        let actual_positions: HashSet<_> = mdd[layer].keys().cloned().collect();
+        let actual_positions: HashSet<_> =
+            mdd.layer(layer).iter().map(|node| node.position).collect();
         assert_eq!(actual_positions, expected_positions);
     }
 
@@ -299,15 +546,21 @@ mod tests {
     #[test]
     fn test_a_star_no_constraint_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let constraints = HashSet::new();
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 0, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 5);
@@ -316,20 +569,27 @@ mod tests {
     #[test]
     fn test_a_star_in_path_vertex_constraint_alternative_path_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 2),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 0, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 5);
@@ -338,25 +598,33 @@ mod tests {
     #[test]
     fn test_a_star_in_path_vertex_constraint_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 2),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         constraints.insert(Constraint::Vertex {
             position: (2, 0),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 0, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 6);
@@ -365,20 +633,27 @@ mod tests {
     #[test]
     fn test_a_star_path_length_constraint_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 0),
             time_step: 4,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 4, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            4,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 6);
@@ -387,18 +662,22 @@ mod tests {
     #[test]
     fn test_a_star_no_constraint_with_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let constraints = HashSet::new();
         let stats = &mut Stats::default();
 
-        if let SearchResult::WithMDD(Some((path, _, mdd))) =
-            a_star_search(&map, &agent, &constraints, 0, true, stats)
-        {
+        if let SearchResult::WithMDD(Some((path, _, mdd))) = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            true,
+            "astar",
+            None,
+            None,
+            stats,
+        ) {
             assert_eq!(path.len(), 5);
             debug!("{mdd:?}");
 
@@ -419,23 +698,28 @@ mod tests {
     #[test]
     fn test_a_star_in_path_vertex_constraint_alternative_path_with_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 2),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
 
-        if let SearchResult::WithMDD(Some((path, _, mdd))) =
-            a_star_search(&map, &agent, &constraints, 0, true, stats)
-        {
+        if let SearchResult::WithMDD(Some((path, _, mdd))) = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            true,
+            "astar",
+            None,
+            None,
+            stats,
+        ) {
             assert_eq!(path.len(), 5);
             debug!("{mdd:?}");
 
@@ -456,28 +740,34 @@ mod tests {
     #[test]
     fn test_a_star_in_path_vertex_constraint_with_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 2),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         constraints.insert(Constraint::Vertex {
             position: (2, 0),
             time_step: 2,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
 
-        if let SearchResult::WithMDD(Some((path, _, mdd))) =
-            a_star_search(&map, &agent, &constraints, 0, true, stats)
-        {
+        if let SearchResult::WithMDD(Some((path, _, mdd))) = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            true,
+            "astar",
+            None,
+            None,
+            stats,
+        ) {
             assert_eq!(path.len(), 6);
             debug!("{mdd:?}");
 
@@ -499,23 +789,28 @@ mod tests {
     #[test]
     fn test_a_star_path_length_constraint_with_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Vertex {
             position: (0, 0),
             time_step: 4,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
 
-        if let SearchResult::WithMDD(Some((path, _, mdd))) =
-            a_star_search(&map, &agent, &constraints, 4, true, stats)
-        {
+        if let SearchResult::WithMDD(Some((path, _, mdd))) = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            4,
+            true,
+            "astar",
+            None,
+            None,
+            stats,
+        ) {
             assert_eq!(path.len(), 6);
             debug!("{mdd:?}");
 
@@ -537,20 +832,27 @@ mod tests {
     #[test]
     fn test_a_star_edge_constraint_alternative_path_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Edge {
             from_position: (0, 2),
             to_position: (1, 2),
             to_time_step: 2,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 0, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 5);
@@ -559,27 +861,134 @@ mod tests {
     #[test]
     fn test_a_star_edge_constraint_without_mdd() {
         init_tracing();
-        let agent = Agent {
-            id: 0,
-            start: (2, 2),
-            goal: (0, 0),
-        };
+        let agent = Agent::new(0, (2, 2), (0, 0));
         let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
         let mut constraints = HashSet::new();
         constraints.insert(Constraint::Edge {
             from_position: (1, 2),
             to_position: (0, 2),
             to_time_step: 2,
+            kind: ConstraintKind::Negative,
         });
         constraints.insert(Constraint::Edge {
             from_position: (2, 0),
             to_position: (1, 0),
             to_time_step: 3,
+            kind: ConstraintKind::Negative,
         });
         let stats = &mut Stats::default();
-        let result = a_star_search(&map, &agent, &constraints, 0, false, stats);
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
         let (path, _) = get_path_from_result(result).unwrap();
         debug!("{path:?}");
         assert_eq!(path.len(), 6);
     }
+
+    #[test]
+    fn test_a_star_beam_width_still_finds_a_path() {
+        init_tracing();
+        let agent = Agent::new(0, (2, 2), (0, 0));
+        let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
+        let constraints = HashSet::new();
+        let stats = &mut Stats::default();
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            Some(1),
+            stats,
+        );
+        let (path, _) = get_path_from_result(result).unwrap();
+        debug!("{path:?}");
+        assert_eq!(path.len(), 5);
+    }
+
+    #[test]
+    fn test_a_star_weighted_mode_still_finds_a_path() {
+        init_tracing();
+        let agent = Agent::new(0, (2, 2), (0, 0));
+        let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
+        let constraints = HashSet::new();
+        let stats = &mut Stats::default();
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "weighted_astar",
+            Some(2.0),
+            None,
+            stats,
+        );
+        let (path, _) = get_path_from_result(result).unwrap();
+        debug!("{path:?}");
+        assert_eq!(path.len(), 5);
+    }
+
+    #[test]
+    fn test_a_star_greedy_mode_still_finds_a_path() {
+        init_tracing();
+        let agent = Agent::new(0, (2, 2), (0, 0));
+        let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
+        let constraints = HashSet::new();
+        let stats = &mut Stats::default();
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "greedy",
+            None,
+            None,
+            stats,
+        );
+        let (path, _) = get_path_from_result(result).unwrap();
+        debug!("{path:?}");
+        assert_eq!(path.len(), 5);
+    }
+
+    // Ordered waypoint: (2, 2) -> (0, 1) -> (0, 0), both hops on the grid's
+    // straight edges so the route is unambiguous.
+    #[test]
+    fn test_a_star_ordered_waypoint_visits_stop_before_goal() {
+        init_tracing();
+        let agent = Agent::with_waypoints(0, (2, 2), (0, 0), Some(vec![(0, 1)]), true);
+        let map = Map::from_file("map_file/test/test.map", &vec![agent.clone()]).unwrap();
+        let constraints = HashSet::new();
+        let stats = &mut Stats::default();
+        let result = a_star_search(
+            &map,
+            &agent,
+            &constraints,
+            0,
+            false,
+            "astar",
+            None,
+            None,
+            stats,
+        );
+        let (path, _) = get_path_from_result(result).unwrap();
+        debug!("{path:?}");
+        assert!(path.contains(&(0, 1)));
+        let stop_index = path.iter().position(|&p| p == (0, 1)).unwrap();
+        let goal_index = path.iter().position(|&p| p == (0, 0)).unwrap();
+        assert!(stop_index < goal_index);
+        assert_eq!(*path.first().unwrap(), agent.start);
+        assert_eq!(*path.last().unwrap(), agent.goal);
+    }
 }
@@ -1,18 +1,28 @@
 use super::{
-    construct_mdd, construct_path, heuristic_focal, standard_a_star_search_focal_cost,
-    standard_a_star_search_open_cost,
+    apply_paired_beam_width, construct_mdd, construct_path, hierarchical_focal_a_star_search,
+    low_level_f_cost, standard_a_star_search_focal_cost, standard_a_star_search_open_cost,
+    ConflictTable,
 };
 use crate::common::{
-    create_open_focal_node, Agent, Constraint, FocalOrderWrapper, OpenOrderWrapper, Path,
-    SearchResult,
+    create_open_focal_node, Agent, Constraint, ConstraintIndex, FocalOrderWrapper,
+    OpenOrderWrapper, Path, SearchResult,
 };
 use crate::map::Map;
 use crate::stat::Stats;
 
-use std::cmp::max;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::cmp::{max, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
 use tracing::{debug, instrument, trace};
 
+/// Bounded-suboptimal low-level search (ECBS-style): alongside the ordinary
+/// `open_list` ordered by `f_open_cost`, `standard_focal_a_star_search`/
+/// `standard_focal_double_search` maintain a FOCAL subset of every open node
+/// with `f_open_cost <= subopt_factor * f_min`, expanding the FOCAL node
+/// that minimizes `heuristic_focal`'s secondary score (conflicts against
+/// `paths`, per `focal_heuristic`) instead of the one minimizing f. FOCAL
+/// gains newly-qualifying nodes whenever OPEN's `f_min` rises. The returned
+/// path length is then only guaranteed to be within `subopt_factor` of
+/// optimal, not optimal itself.
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn focal_a_star_search(
     map: &Map,
@@ -23,6 +33,10 @@ pub(crate) fn focal_a_star_search(
     paths: &[Path],
     build_mdd: bool,
     solver: &str,
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> SearchResult {
     let constraint_limit_time_step = constraints
@@ -46,6 +60,10 @@ pub(crate) fn focal_a_star_search(
                     path_length_constraint,
                     constraint_limit_time_step,
                     paths,
+                    focal_heuristic,
+                    low_level_mode,
+                    low_level_weight,
+                    beam_width,
                     stats,
                 ) {
                     Some((sub_optimal_result, f_min)) => (sub_optimal_result, f_min),
@@ -66,6 +84,10 @@ pub(crate) fn focal_a_star_search(
                     path_length_constraint,
                     constraint_limit_time_step,
                     paths,
+                    focal_heuristic,
+                    low_level_mode,
+                    low_level_weight,
+                    beam_width,
                     stats,
                 ) {
                     Some((sub_optimal_result, f_min)) => (sub_optimal_result, f_min),
@@ -79,7 +101,7 @@ pub(crate) fn focal_a_star_search(
                 }
             }
         }
-        "lbcbs" | "bcbs" | "ecbs" => match standard_focal_a_star_search(
+        "lbcbs" | "bcbs" | "ecbs" => match hierarchical_focal_a_star_search(
             map,
             agent,
             subopt_factor,
@@ -87,6 +109,10 @@ pub(crate) fn focal_a_star_search(
             path_length_constraint,
             constraint_limit_time_step,
             paths,
+            focal_heuristic,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
             stats,
         ) {
             Some((sub_optimal_result, f_min)) => (sub_optimal_result, f_min),
@@ -106,6 +132,10 @@ pub(crate) fn focal_a_star_search(
             path_length_constraint,
             constraint_limit_time_step,
             paths,
+            focal_heuristic,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
             stats,
         ) {
             Some((sub_optimal_result, f_min)) => (sub_optimal_result, f_min),
@@ -151,6 +181,10 @@ pub(crate) fn standard_focal_a_star_search(
     path_length_constraint: usize,
     constraint_limit_time_step: usize,
     paths: &[Path],
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> Option<(Path, usize)> {
     debug!("constraints: {constraints:?}, limit time step: {constraint_limit_time_step:?}");
@@ -164,13 +198,41 @@ pub(crate) fn standard_focal_a_star_search(
     let mut focal_list = BTreeSet::new();
     let mut closed_list = HashSet::new();
     let mut trace = HashMap::new();
+    let constraint_index = ConstraintIndex::build(constraints);
 
     let mut f_focal_cost_map = HashMap::new();
 
-    let start_h_open_cost = map.heuristic[agent.id][agent.start.0][agent.start.1];
+    // Every node ever admitted to `open_list` is also pushed here (as a
+    // min-heap on `f_open_cost`, via `Reverse`), and never removed from it
+    // again. When `f_min` rises below, instead of the old O(open_list.len())
+    // rescan of every open node to find the newly-qualifying ones, we pop
+    // this heap while the next candidate's `f_open_cost` is within the new
+    // bound. A popped candidate may be a tombstone -- superseded by a
+    // cheaper-focal-cost replacement, or already expanded -- so it's only
+    // actually admitted into `focal_list` if `f_focal_cost_map` still
+    // reports it as the authoritative entry for its `(position, g_cost)`
+    // and it isn't in `closed_list`; `f_open_cost` itself never changes
+    // once computed, so no tombstone check is needed for that half of the
+    // bound. We deliberately do NOT replace `open_list`/`focal_list`
+    // themselves with heaps: their removal-on-improvement logic and
+    // `apply_paired_beam_width`'s per-layer truncation both rely on
+    // `BTreeSet`'s ordered removal/range behavior, which a heap can't give
+    // us without risking those carefully-verified invariants.
+    #[allow(clippy::mutable_key_type)]
+    let mut pending_admission: BinaryHeap<Reverse<OpenOrderWrapper>> = BinaryHeap::new();
+
+    let conflict_table = ConflictTable::build(paths);
+
+    let start_h_open_cost = map.heuristic[agent.id].get(agent.start);
 
-    let (start_open_node, start_focal_node) =
-        create_open_focal_node(agent.start, start_h_open_cost, 0, 0, 0);
+    let (start_open_node, start_focal_node) = create_open_focal_node(
+        agent.start,
+        low_level_f_cost(low_level_mode, low_level_weight, 0, start_h_open_cost),
+        0,
+        0,
+        0,
+    );
+    pending_admission.push(Reverse(OpenOrderWrapper::from_node(&start_open_node.0)));
     open_list.insert(start_open_node);
     focal_list.insert(start_focal_node);
 
@@ -224,21 +286,24 @@ pub(crate) fn standard_focal_a_star_search(
             }
 
             // Check for constraints before exploring the neighbor.
-            if constraints.iter().any(|constraint| {
-                constraint.is_violated(current.position, *neighbor, tentative_g_cost)
-            }) {
+            if constraint_index.is_violated(current.position, *neighbor, tentative_g_cost) {
                 continue; // This move is prohibited due to a constraint
             }
 
-            let h_open_cost = map.heuristic[agent.id][neighbor.0][neighbor.1];
-            let f_open_cost = tentative_g_cost + h_open_cost;
+            let h_open_cost = map.heuristic[agent.id].get(*neighbor);
+            let f_open_cost = low_level_f_cost(
+                low_level_mode,
+                low_level_weight,
+                tentative_g_cost,
+                h_open_cost,
+            );
             let f_focal_cost = current.f_focal_cost
-                + heuristic_focal(
+                + conflict_table.heuristic_focal(
                     agent.id,
                     *neighbor,
                     current.position,
                     tentative_g_cost,
-                    paths,
+                    focal_heuristic,
                 );
 
             let (open_node_wrapper, focal_node_wrapper) = create_open_focal_node(
@@ -269,18 +334,23 @@ pub(crate) fn standard_focal_a_star_search(
                     if focal_list.remove(&old_focal_node_wrapper) {
                         // If focal list has this node, then open list must also have
                         assert!(open_list.remove(&old_open_node_wrapper));
+                        pending_admission
+                            .push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                         open_list.insert(open_node_wrapper);
 
                         // Update old node in focal list
                         focal_list.insert(focal_node_wrapper);
                     } else if open_list.remove(&old_open_node_wrapper) {
                         // There still has possible only open list contain this old node
+                        pending_admission
+                            .push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                         open_list.insert(open_node_wrapper);
                     }
                 }
             } else {
                 // This node is never appeared before, update open list and trace
                 // Also means this node is new to focal history, update focal cost hashmap
+                pending_admission.push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                 assert!(open_list.insert(open_node_wrapper));
 
                 trace.insert(
@@ -296,19 +366,43 @@ pub(crate) fn standard_focal_a_star_search(
             }
         }
 
+        if let Some(beam_width) = beam_width {
+            apply_paired_beam_width(
+                &mut open_list,
+                &mut focal_list,
+                &mut f_focal_cost_map,
+                tentative_g_cost,
+                beam_width,
+                stats,
+            );
+        }
+
         if !open_list.is_empty() {
-            // Maintain the focal list, since we have changed the f min.
+            // Maintain the focal list, since we have changed the f min. Drain
+            // `pending_admission` (ascending `f_open_cost`) instead of
+            // rescanning every open node: once a candidate's `f_open_cost`
+            // exceeds the new bound, nothing further in the heap can
+            // qualify either, so we can stop rather than visit the rest.
             let new_f_min = open_list.first().unwrap().0.borrow().f_open_cost;
             if f_min < new_f_min {
-                open_list.iter().for_each(|open_wrapper| {
-                    let node_ref = &open_wrapper.0;
+                let admission_bound = new_f_min as f64 * subopt_factor;
+                while let Some(Reverse(candidate)) = pending_admission.peek() {
+                    if candidate.f_open_cost() as f64 > admission_bound {
+                        break;
+                    }
+                    let Reverse(candidate) = pending_admission.pop().unwrap();
+                    let node_ref = &candidate.0;
                     let node = node_ref.borrow();
-                    if node.f_open_cost as f64 > f_min as f64 * subopt_factor
-                        && node.f_open_cost as f64 <= new_f_min as f64 * subopt_factor
+                    let is_current = f_focal_cost_map.get(&(node.position, node.g_cost))
+                        == Some(&node.f_focal_cost);
+                    let is_expanded = closed_list.contains(&(node.position, node.time_step));
+                    if is_current
+                        && !is_expanded
+                        && node.f_open_cost as f64 > f_min as f64 * subopt_factor
                     {
                         focal_list.insert(FocalOrderWrapper::from_node(node_ref));
                     }
-                });
+                }
             }
         }
 
@@ -329,16 +423,29 @@ pub(crate) fn standard_focal_double_search(
     path_length_constraint: usize,
     constraint_limit_time_step: usize,
     paths: &[Path],
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> Option<(Path, usize)> {
     debug!("constraints: {constraints:?}, limit time step: {constraint_limit_time_step:?}");
 
+    // This pass only surfaces `SearchResult::Partial` for the plain
+    // cbs/hbcbs path (see `a_star_search` in astar.rs); the focal family
+    // still returns a bare `None` on failure, so this first-leg partial
+    // result is discarded rather than threaded further here.
+    let mut _closest_to_goal = None;
     if let Some((_, f_min)) = standard_a_star_search_open_cost(
         map,
         agent,
         constraints,
         path_length_constraint,
         constraint_limit_time_step,
+        low_level_mode,
+        low_level_weight,
+        beam_width,
+        &mut _closest_to_goal,
         stats,
     ) {
         standard_a_star_search_focal_cost(
@@ -349,6 +456,10 @@ pub(crate) fn standard_focal_double_search(
             paths,
             constraint_limit_time_step,
             f_min as f64 * subopt_factor,
+            focal_heuristic,
+            low_level_mode,
+            low_level_weight,
+            beam_width,
             stats,
         )
     } else {
@@ -367,6 +478,10 @@ pub(crate) fn alternating_focal_a_star_search(
     path_length_constraint: usize,
     constraint_limit_time_step: usize,
     paths: &[Path],
+    focal_heuristic: &str,
+    low_level_mode: &str,
+    low_level_weight: Option<f64>,
+    beam_width: Option<usize>,
     stats: &mut Stats,
 ) -> Option<(Path, usize)> {
     debug!("constraints: {constraints:?}, limit time step: {constraint_limit_time_step:?}");
@@ -380,13 +495,31 @@ pub(crate) fn alternating_focal_a_star_search(
     let mut focal_list = BTreeSet::new();
     let mut closed_list = HashSet::new();
     let mut trace = HashMap::new();
+    let constraint_index = ConstraintIndex::build(constraints);
 
     let mut f_focal_cost_map = HashMap::new();
 
-    let start_h_open_cost = map.heuristic[agent.id][agent.start.0][agent.start.1];
+    // See `standard_focal_a_star_search`'s `pending_admission` for the
+    // rationale: a min-heap mirror of every node ever admitted to
+    // `open_list`, drained instead of rescanning `open_list` whenever
+    // `f_min` rises, with a tombstone check against `f_focal_cost_map`/
+    // `closed_list` before a popped candidate is actually admitted into
+    // `focal_list`.
+    #[allow(clippy::mutable_key_type)]
+    let mut pending_admission: BinaryHeap<Reverse<OpenOrderWrapper>> = BinaryHeap::new();
+
+    let conflict_table = ConflictTable::build(paths);
+
+    let start_h_open_cost = map.heuristic[agent.id].get(agent.start);
 
-    let (start_open_node, start_focal_node) =
-        create_open_focal_node(agent.start, start_h_open_cost, 0, 0, 0);
+    let (start_open_node, start_focal_node) = create_open_focal_node(
+        agent.start,
+        low_level_f_cost(low_level_mode, low_level_weight, 0, start_h_open_cost),
+        0,
+        0,
+        0,
+    );
+    pending_admission.push(Reverse(OpenOrderWrapper::from_node(&start_open_node.0)));
     open_list.insert(start_open_node);
     focal_list.insert(start_focal_node);
 
@@ -483,21 +616,24 @@ pub(crate) fn alternating_focal_a_star_search(
             }
 
             // Check for constraints before exploring the neighbor
-            if constraints.iter().any(|constraint| {
-                constraint.is_violated(current.position, *neighbor, tentative_g_cost)
-            }) {
+            if constraint_index.is_violated(current.position, *neighbor, tentative_g_cost) {
                 continue; // This move is prohibited due to a constraint
             }
 
-            let h_open_cost = map.heuristic[agent.id][neighbor.0][neighbor.1];
-            let f_open_cost = tentative_g_cost + h_open_cost;
+            let h_open_cost = map.heuristic[agent.id].get(*neighbor);
+            let f_open_cost = low_level_f_cost(
+                low_level_mode,
+                low_level_weight,
+                tentative_g_cost,
+                h_open_cost,
+            );
             let f_focal_cost = current.f_focal_cost
-                + heuristic_focal(
+                + conflict_table.heuristic_focal(
                     agent.id,
                     *neighbor,
                     current.position,
                     tentative_g_cost,
-                    paths,
+                    focal_heuristic,
                 );
 
             let (open_node_wrapper, focal_node_wrapper) = create_open_focal_node(
@@ -528,18 +664,23 @@ pub(crate) fn alternating_focal_a_star_search(
                     if focal_list.remove(&old_focal_node_wrapper) {
                         // If focal list has this node, then open list must also have
                         assert!(open_list.remove(&old_open_node_wrapper));
+                        pending_admission
+                            .push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                         open_list.insert(open_node_wrapper);
 
                         // Update old node in focal list
                         focal_list.insert(focal_node_wrapper);
                     } else if open_list.remove(&old_open_node_wrapper) {
                         // There still has possible only open list contain this old node
+                        pending_admission
+                            .push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                         open_list.insert(open_node_wrapper);
                     }
                 }
             } else {
                 // This node is never appeared before, update open list and trace
                 // Also means this node is new to focal history, update focal cost hashmap
+                pending_admission.push(Reverse(OpenOrderWrapper::from_node(&open_node_wrapper.0)));
                 assert!(open_list.insert(open_node_wrapper));
 
                 trace.insert(
@@ -555,20 +696,42 @@ pub(crate) fn alternating_focal_a_star_search(
             }
         }
 
+        if let Some(beam_width) = beam_width {
+            apply_paired_beam_width(
+                &mut open_list,
+                &mut focal_list,
+                &mut f_focal_cost_map,
+                tentative_g_cost,
+                beam_width,
+                stats,
+            );
+        }
+
         if !open_list.is_empty() {
-            // Maintain the focal list, since we have changed the f min
+            // Maintain the focal list, since we have changed the f min. Drain
+            // `pending_admission` instead of rescanning every open node; see
+            // `standard_focal_a_star_search` for the full rationale.
             if let Some(first) = open_list.first() {
                 let new_f_min = first.0.borrow().f_open_cost;
                 if f_min < new_f_min {
-                    open_list.iter().for_each(|open_wrapper| {
-                        let node_ref = &open_wrapper.0;
+                    let admission_bound = new_f_min as f64 * subopt_factor;
+                    while let Some(Reverse(candidate)) = pending_admission.peek() {
+                        if candidate.f_open_cost() as f64 > admission_bound {
+                            break;
+                        }
+                        let Reverse(candidate) = pending_admission.pop().unwrap();
+                        let node_ref = &candidate.0;
                         let node = node_ref.borrow();
-                        if node.f_open_cost as f64 > f_min as f64 * subopt_factor
-                            && node.f_open_cost as f64 <= new_f_min as f64 * subopt_factor
+                        let is_current = f_focal_cost_map.get(&(node.position, node.g_cost))
+                            == Some(&node.f_focal_cost);
+                        let is_expanded = closed_list.contains(&(node.position, node.time_step));
+                        if is_current
+                            && !is_expanded
+                            && node.f_open_cost as f64 > f_min as f64 * subopt_factor
                         {
                             focal_list.insert(FocalOrderWrapper::from_node(node_ref));
                         }
-                    });
+                    }
                 }
                 f_min = new_f_min;
             }
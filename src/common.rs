@@ -1,11 +1,20 @@
+mod bitset;
+mod cache;
 mod highlevel;
 mod lowlevel;
+mod mutex;
 
-pub(crate) use highlevel::{CardinalType, Constraint, HighLevelOpenNode};
+pub(crate) use bitset::BitSet;
+pub(crate) use cache::{PairWeightCache, PairWeightKey, PathCache, PathCacheEntry, PathCacheKey};
+pub(crate) use highlevel::{
+    select_prioritized_conflict, CardinalType, Conflict, Constraint, ConstraintIndex,
+    ConstraintKind, HighLevelFocalNode, HighLevelOpenNode,
+};
 pub(crate) use lowlevel::{
     create_focal_node, create_open_focal_node, create_open_node, FocalOrderWrapper,
     OpenOrderWrapper,
 };
+pub(crate) use mutex::goal_mutex;
 
 use serde::{Deserialize, Serialize};
 use std::cmp::{max, min};
@@ -22,19 +31,293 @@ pub struct Agent {
     pub id: usize,
     pub start: (usize, usize),
     pub goal: (usize, usize),
+    /// Intermediate stops the agent must visit before `goal`, in file order.
+    /// Interpreted according to `waypoints_ordered`: visited as listed when
+    /// `true`, otherwise resolved into a distance-minimizing order by
+    /// `resolve_waypoint_order` at plan time.
+    #[serde(default)]
+    pub waypoints: Option<Vec<(usize, usize)>>,
+    #[serde(default)]
+    pub waypoints_ordered: bool,
 }
 
 impl Agent {
+    /// Builds a plain point-to-point agent (no waypoints). Most construction
+    /// sites (scenario/YAML loading, tests) don't care about waypoints, so
+    /// this is the default way to build an `Agent` rather than writing out
+    /// the full struct literal.
+    pub fn new(id: usize, start: (usize, usize), goal: (usize, usize)) -> Self {
+        Agent {
+            id,
+            start,
+            goal,
+            waypoints: None,
+            waypoints_ordered: false,
+        }
+    }
+
+    /// Builds an agent that must visit `waypoints` before `goal`.
+    /// `waypoints_ordered` says whether the list must be visited in the
+    /// given order (`true`) or may be reordered to minimize travel distance
+    /// (`false`, resolved later by `resolve_waypoint_order`).
+    pub fn with_waypoints(
+        id: usize,
+        start: (usize, usize),
+        goal: (usize, usize),
+        waypoints: Option<Vec<(usize, usize)>>,
+        waypoints_ordered: bool,
+    ) -> Self {
+        Agent {
+            id,
+            start,
+            goal,
+            waypoints,
+            waypoints_ordered,
+        }
+    }
+
     pub fn verify(&self, map: &Map) -> bool {
         map.is_passable(self.start.0, self.start.1) && map.is_passable(self.goal.0, self.goal.1)
     }
+
+    /// Resolves `waypoints` into the concrete stop order the low-level
+    /// search should visit: an ordered list is used as-is; an unordered list
+    /// is optimized exactly where that's tractable and approximated only
+    /// once it isn't. Up to 8 stops are optimized by exhaustive permutation
+    /// over exact `start -> ... -> goal` segment distance (via
+    /// `Map::heuristic_dji`, one reverse search per candidate stop); up to
+    /// `HELD_KARP_MAX_WAYPOINTS` stops fall back to the exact Held-Karp
+    /// subset DP instead, since `k!` stops being worth enumerating well
+    /// before `2^k` does; beyond that a greedy nearest-remaining-stop order
+    /// is used, since even `2^k` is no longer worth computing exactly.
+    /// Returns an empty vec when the agent has no waypoints.
+    pub(crate) fn resolve_waypoint_order(&self, map: &Map) -> Vec<(usize, usize)> {
+        let Some(waypoints) = &self.waypoints else {
+            return Vec::new();
+        };
+
+        if self.waypoints_ordered || waypoints.len() <= 1 {
+            return waypoints.clone();
+        }
+
+        if waypoints.len() > 8 {
+            if waypoints.len() <= HELD_KARP_MAX_WAYPOINTS {
+                return held_karp_waypoint_order(map, self.start, waypoints, self.goal);
+            }
+            return greedy_waypoint_order(map, self.start, waypoints);
+        }
+
+        let mut candidate = waypoints.clone();
+        candidate.sort();
+
+        let mut best_order = candidate.clone();
+        let mut best_distance = segment_distance(map, self.start, &candidate, self.goal);
+
+        while next_permutation(&mut candidate) {
+            let distance = segment_distance(map, self.start, &candidate, self.goal);
+            if distance < best_distance {
+                best_distance = distance;
+                best_order = candidate.clone();
+            }
+        }
+
+        best_order
+    }
+}
+
+/// Sum of exact shortest-path distances along `start -> stops[0] -> ... ->
+/// stops[n - 1] -> goal`, each leg resolved through a fresh
+/// `Map::heuristic_dji` table rooted at that leg's target.
+fn segment_distance(
+    map: &Map,
+    start: (usize, usize),
+    stops: &[(usize, usize)],
+    goal: (usize, usize),
+) -> usize {
+    let mut total = 0usize;
+    let mut from = start;
+    for &stop in stops.iter().chain(std::iter::once(&goal)) {
+        let table = map.heuristic_dji(stop);
+        total = total.saturating_add(table[from.0][from.1]);
+        from = stop;
+    }
+    total
+}
+
+/// Largest waypoint count `held_karp_waypoint_order` will solve exactly.
+/// `2^15 * 15^2` is a few million table updates, comfortably fast for a
+/// one-off per-agent computation; doubling `k` again would roughly square
+/// that, which is where `greedy_waypoint_order` takes over instead.
+const HELD_KARP_MAX_WAYPOINTS: usize = 15;
+
+/// Exact optimal visiting order for `waypoints` via the classic Held-Karp
+/// dynamic program over subsets: `dp[mask][last]` holds the cheapest cost of
+/// a `start -> ... -> last` path visiting exactly the waypoints in `mask`,
+/// transitions extend that path by one unvisited waypoint, and the answer
+/// picks the `mask == full` row that minimizes total cost plus the final
+/// `last -> goal` leg. Distances come from one `Map::heuristic_dji` table
+/// per waypoint/goal (same oracle `segment_distance` uses), so this is
+/// `O(2^k * k^2)` dp transitions plus `O(k)` reverse searches, not `O(2^k)`
+/// searches.
+fn held_karp_waypoint_order(
+    map: &Map,
+    start: (usize, usize),
+    waypoints: &[(usize, usize)],
+    goal: (usize, usize),
+) -> Vec<(usize, usize)> {
+    let k = waypoints.len();
+    let tables: Vec<_> = waypoints
+        .iter()
+        .map(|&stop| map.heuristic_dji(stop))
+        .collect();
+    let goal_table = map.heuristic_dji(goal);
+
+    let dist_from_start: Vec<usize> = tables.iter().map(|table| table[start.0][start.1]).collect();
+    let dist_to_goal: Vec<usize> = waypoints.iter().map(|&(x, y)| goal_table[x][y]).collect();
+    let mut pairwise = vec![vec![0usize; k]; k];
+    for (i, table) in tables.iter().enumerate() {
+        for (j, &(x, y)) in waypoints.iter().enumerate() {
+            if i != j {
+                pairwise[i][j] = table[x][y];
+            }
+        }
+    }
+
+    let full_mask = (1usize << k) - 1;
+    let mut dp = vec![vec![usize::MAX; k]; 1 << k];
+    let mut parent = vec![vec![usize::MAX; k]; 1 << k];
+
+    for i in 0..k {
+        dp[1 << i][i] = dist_from_start[i];
+    }
+
+    for mask in 1..=full_mask {
+        for last in 0..k {
+            if mask & (1 << last) == 0 || dp[mask][last] == usize::MAX {
+                continue;
+            }
+            let cost = dp[mask][last];
+            for next in 0..k {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << next);
+                let next_cost = cost.saturating_add(pairwise[last][next]);
+                if next_cost < dp[next_mask][next] {
+                    dp[next_mask][next] = next_cost;
+                    parent[next_mask][next] = last;
+                }
+            }
+        }
+    }
+
+    let mut last = (0..k)
+        .min_by_key(|&i| dp[full_mask][i].saturating_add(dist_to_goal[i]))
+        .expect("waypoints is non-empty");
+
+    let mut order = Vec::with_capacity(k);
+    let mut mask = full_mask;
+    loop {
+        order.push(waypoints[last]);
+        let prev = parent[mask][last];
+        mask &= !(1 << last);
+        if prev == usize::MAX {
+            break;
+        }
+        last = prev;
+    }
+    order.reverse();
+    order
+}
+
+/// Greedily visits whichever remaining stop is nearest (by
+/// `Map::heuristic_dji`), used once a waypoint set is too large to permute
+/// exhaustively. Not optimal, but linear in the number of stops.
+fn greedy_waypoint_order(
+    map: &Map,
+    start: (usize, usize),
+    waypoints: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let mut remaining = waypoints.to_vec();
+    let mut order = Vec::with_capacity(remaining.len());
+    let mut from = start;
+
+    while !remaining.is_empty() {
+        let table = map.heuristic_dji(from);
+        let (nearest_idx, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &stop)| table[stop.0][stop.1])
+            .expect("remaining is non-empty");
+        let nearest = remaining.remove(nearest_idx);
+        from = nearest;
+        order.push(nearest);
+    }
+
+    order
+}
+
+/// Advances `items` in place to its next lexicographic permutation and
+/// returns `true`, or leaves it at the final (descending) permutation and
+/// returns `false` once every permutation has been visited. Same idea as
+/// `permutohedron::LexicalPermutation`, implemented locally to avoid an
+/// extra dependency for what `resolve_waypoint_order` only needs once.
+fn next_permutation(items: &mut [(usize, usize)]) -> bool {
+    if items.len() < 2 {
+        return false;
+    }
+
+    let mut i = items.len() - 1;
+    while i > 0 && items[i - 1] >= items[i] {
+        i -= 1;
+    }
+    if i == 0 {
+        return false;
+    }
+
+    let mut j = items.len() - 1;
+    while items[j] <= items[i - 1] {
+        j -= 1;
+    }
+    items.swap(i - 1, j);
+    items[i..].reverse();
+    true
 }
 
 pub(crate) type Path = Vec<(usize, usize)>;
 
-#[derive(Debug, Clone)]
+/// Trivial cost lower bounds: each agent's own optimal number of moves,
+/// ignoring every other agent, summed for `soc_lb` and maxed for
+/// `makespan_lb`. Not a tight bound (it ignores inter-agent conflicts
+/// entirely), but it's exact and free to compute since `map.heuristic`
+/// already holds each agent's single-agent shortest distance; a tighter
+/// bound would need an LP relaxation of the conflict-constrained assignment
+/// problem, which is a much larger subsystem than this lower-bound
+/// reporting warrants on its own. Units are moves, matching `Stats::costs`/
+/// `HighLevelOpenNode::cost`'s `path.len() - 1` convention; callers working
+/// in `Solution::log_solution`'s node-count convention (`path.len()`) must
+/// add 1 per agent themselves.
+pub(crate) fn trivial_cost_lower_bounds(agents: &[Agent], map: &Map) -> (usize, usize) {
+    let mut soc_lb = 0;
+    let mut makespan_lb = 0;
+    for agent in agents {
+        let optimal_moves = map.heuristic[agent.id].get(agent.start);
+        soc_lb += optimal_moves;
+        makespan_lb = makespan_lb.max(optimal_moves);
+    }
+    (soc_lb, makespan_lb)
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Solution {
     pub paths: Vec<Path>,
+    /// Set when a solver returned this as a best-effort incumbent (e.g.
+    /// `CBS::solve_inner` exhausting `config.time_limit_ms`/
+    /// `config.high_level_node_limit`) rather than a search-complete result.
+    /// `paths` may then still contain unresolved conflicts, so callers must
+    /// not treat `verify` failing on a partial solution as a bug the way
+    /// they would for a non-partial one.
+    pub partial: bool,
 }
 
 impl Solution {
@@ -114,7 +397,7 @@ impl Solution {
             || (pos1.0 == pos2.0 && pos1.1 == pos2.1)
     }
 
-    pub fn log_solution(&self, config: &Config) {
+    pub fn log_solution(&self, config: &Config, agent_list: &[Agent], map: &Map) {
         let agents = self.paths.len();
         let mut soc = 0;
         let mut makespan = 0;
@@ -127,6 +410,17 @@ impl Solution {
             makespan = makespan.max(path.len());
         }
 
+        // Trivial lower bounds (see `trivial_cost_lower_bounds`) and the
+        // resulting loss; a tighter, LP-relaxation-based `soc_lb` is left as
+        // a possible future improvement rather than implemented here.
+        // `trivial_cost_lower_bounds` counts moves; convert to this
+        // function's node-count convention (`path.len()`) by adding 1 per
+        // agent, matching how `soc`/`makespan` are accumulated above.
+        let (moves_soc_lb, moves_makespan_lb) = trivial_cost_lower_bounds(agent_list, map);
+        let soc_lb = moves_soc_lb + agents;
+        let makespan_lb = moves_makespan_lb + 1;
+        let sum_of_loss = soc.saturating_sub(soc_lb);
+
         // Pad agent paths with final position to match makespan
         let mut padded_paths = Vec::with_capacity(agents);
         for path in &self.paths {
@@ -157,11 +451,14 @@ impl Solution {
         formatted.push_str(&format!("solver={}\n", config.solver));
         formatted.push_str("solved=1\n");
         formatted.push_str(&format!("soc={}\n", soc));
-        formatted.push_str("soc_lb=\n");
+        formatted.push_str(&format!("soc_lb={}\n", soc_lb));
         formatted.push_str(&format!("makespan={}\n", makespan));
-        formatted.push_str("makespan_lb=\n");
-        formatted.push_str("sum_of_loss=\n");
-        formatted.push_str("sum_of_loss_lb=\n");
+        formatted.push_str(&format!("makespan_lb={}\n", makespan_lb));
+        formatted.push_str(&format!("sum_of_loss={}\n", sum_of_loss));
+        // A lower bound on `sum_of_loss` itself: 0 is always valid (a
+        // solution could in principle realize `soc_lb` exactly), and
+        // tightening it further needs the same LP relaxation noted above.
+        formatted.push_str("sum_of_loss_lb=0\n");
         formatted.push_str("comp_time=\n");
         formatted.push_str("seed=\n");
         formatted.push_str("checkpoints=-1\n");
@@ -192,29 +489,124 @@ impl Solution {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct NodeId(pub(crate) usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct EdgeId(pub(crate) usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct MddEdge {
+    pub(crate) from: NodeId,
+    pub(crate) to: NodeId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct MddNode {
-    pub(crate) parents: HashSet<(usize, usize)>,
-    pub(crate) children: HashSet<(usize, usize)>,
+    pub(crate) position: (usize, usize),
+    pub(crate) in_edges: Vec<EdgeId>,
+    pub(crate) out_edges: Vec<EdgeId>,
+    /// Shortest g-cost from the source layer to this node, filled in by
+    /// `construct_mdd`'s top-down pass.
+    pub(crate) value: usize,
+    /// Remaining cost from this node to the single goal node, filled in by
+    /// `construct_mdd`'s bottom-up pass.
+    pub(crate) value_bot: usize,
+}
+
+/// Arena-backed multi-value decision diagram, built once per agent per cost
+/// bound by `construct_mdd`: `nodes`/`edges` are flat vectors (DDO-style
+/// vector diagrams) instead of one `HashMap` per layer with per-node
+/// `HashSet` parents/children, and `layers[t]` is the contiguous `NodeId`
+/// range `[start, end)` of `nodes` at time step `t` (valid because
+/// `construct_mdd` always appends one whole layer at a time). A node
+/// survives construction only while `value + value_bot` does not exceed
+/// the agent's cost bound, so every remaining node lies on some optimal,
+/// constraint-respecting path. The range layout turns
+/// `is_singleton_at_position` into an O(1) length check and lets
+/// cardinal-conflict classification compare two agents' layers by walking
+/// index ranges instead of hashing coordinate tuples. `HighLevelOpenNode`/
+/// `HighLevelFocalNode` hold these behind `Arc`, since every child node
+/// clones the whole `mdds` vector but replaces only the single replanned
+/// agent's slot -- an `Arc` clone of the other `n - 1` untouched MDDs is a
+/// refcount bump instead of a deep copy of the arena.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Mdd {
+    pub(crate) nodes: Vec<MddNode>,
+    pub(crate) edges: Vec<MddEdge>,
+    pub(crate) layers: Vec<std::ops::Range<usize>>,
 }
 
-pub(crate) type Mdd = Vec<HashMap<(usize, usize), MddNode>>;
+impl Mdd {
+    pub(crate) fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub(crate) fn layer(&self, time_step: usize) -> &[MddNode] {
+        match self.layers.get(time_step) {
+            Some(range) => &self.nodes[range.clone()],
+            None => &[],
+        }
+    }
+
+    pub(crate) fn node(&self, id: NodeId) -> &MddNode {
+        &self.nodes[id.0]
+    }
+
+    pub(crate) fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes[id.0]
+            .out_edges
+            .iter()
+            .map(move |&edge_id| self.edges[edge_id.0].to)
+    }
+
+    pub(crate) fn parents(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes[id.0]
+            .in_edges
+            .iter()
+            .map(move |&edge_id| self.edges[edge_id.0].from)
+    }
+
+    /// Bitset of linearized cell ids (`row * map.width + col`) occupied by
+    /// this layer. Lets callers that need to test many positions against a
+    /// layer -- e.g. `MddSat::encode_and_solve`'s pairwise conflict clauses --
+    /// do it with a word lookup instead of scanning the layer's nodes once
+    /// per candidate position.
+    pub(crate) fn layer_bitset(&self, time_step: usize, map: &Map) -> BitSet {
+        let mut bitset = BitSet::with_capacity(map.height * map.width);
+        for node in self.layer(time_step) {
+            bitset.set(node.position.0 * map.width + node.position.1);
+        }
+        bitset
+    }
+}
 
 pub(crate) fn is_singleton_at_position(
     mdd: &Mdd,
     time_step: usize,
     position: (usize, usize),
 ) -> bool {
-    if time_step >= mdd.len() {
+    if time_step >= mdd.layers.len() {
         // Only vertex and target conflicts will inqury extended time step,
         // when we see an extended time step, then it must be singleton (cost will increase).
         return true;
     }
-    let layer = &mdd[time_step];
-    layer.len() == 1 && layer.contains_key(&position)
+    let range = &mdd.layers[time_step];
+    range.len() == 1 && mdd.nodes[range.start].position == position
 }
 
 pub(crate) enum SearchResult {
     Standard(Option<(Path, usize)>),
     WithMDD(Option<(Path, usize, Mdd)>),
+    /// Best-effort result for a failed search: the path to whichever
+    /// expanded node had the smallest heuristic-to-goal estimate
+    /// (`h_remaining`) before `open_list` was exhausted, i.e. how close the
+    /// search got to `reached` before giving up. Lets a caller tell "truly
+    /// infeasible agent" apart from "blocked by the current constraint set"
+    /// instead of a bare failure.
+    Partial {
+        path: Path,
+        reached: (usize, usize),
+        h_remaining: usize,
+    },
 }
@@ -0,0 +1,45 @@
+/// Fixed-size bit vector over linearized cell ids (`row * width + col`),
+/// packed into `u64` words. Used to test MDD layer membership and count
+/// layer width without a linear scan over node positions -- see
+/// `Mdd::layer_bitset`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub(crate) fn with_capacity(bits: usize) -> Self {
+        let words = bits.div_ceil(64);
+        BitSet {
+            words: vec![0u64; words],
+        }
+    }
+
+    pub(crate) fn set(&mut self, bit: usize) {
+        self.words[bit / 64] |= 1u64 << (bit % 64);
+    }
+
+    pub(crate) fn test(&self, bit: usize) -> bool {
+        match self.words.get(bit / 64) {
+            Some(word) => word & (1u64 << (bit % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// Word-wise AND: `true` iff some bit is set in both vectors. Used to
+    /// check whether two agents' MDD layers share any cell without
+    /// comparing positions one at a time.
+    pub(crate) fn intersects(&self, other: &BitSet) -> bool {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .any(|(a, b)| a & b != 0)
+    }
+
+    pub(crate) fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+}
@@ -0,0 +1,214 @@
+use super::{Constraint, Mdd, Path};
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::BufReader;
+use tracing::{debug, warn};
+
+/// Identifies a single-agent low-level search by everything that determines
+/// its result: which agent, which map, what constraints it must respect, and
+/// the path-length floor it was asked to exceed. `constraints_hash` must
+/// incorporate every constraint that can affect the agent's replan -- the
+/// full set passed to `PathCacheKey::new`, not the node's own
+/// `prune_dead_constraints`-trimmed copy used for the actual search (see
+/// `replan_agent`) -- or a reused path could silently violate a constraint
+/// the original search respected. For the plain-cost solvers (`cbs`/
+/// `hbcbs`) two searches with equal keys are guaranteed to return the same
+/// `Path`/`f_min`/`Mdd`. For the focal-heuristic solvers
+/// (`lbcbs`/`bcbs`/`ecbs`/`decbs`/`acbs`) that guarantee is only approximate:
+/// `focal_a_star_search`'s conflict-count tie-break also depends on every
+/// other agent's current path, which isn't part of this key (including it
+/// would key every cache entry to one exact high-level node, making the
+/// cache useless). A cache hit there can replay a path chosen under a
+/// staler conflict landscape than a fresh search would pick -- still valid
+/// and within the suboptimality bound, just not necessarily the
+/// least-conflicted choice available right now.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct PathCacheKey {
+    map_fingerprint: u64,
+    agent_id: usize,
+    constraints_hash: u64,
+    path_length_constraint: usize,
+    // `f64` isn't `Hash`/`Eq`; stored as bits so two searches run under the
+    // same suboptimality factor (the common case within one solve) hit the
+    // same entry, while a cache persisted across runs with different
+    // factors doesn't cross-contaminate.
+    sub_optimal_bits: Option<u64>,
+}
+
+impl PathCacheKey {
+    pub(crate) fn new(
+        map_fingerprint: u64,
+        agent_id: usize,
+        constraints: &HashSet<Constraint>,
+        path_length_constraint: usize,
+        sub_optimal: Option<f64>,
+    ) -> Self {
+        let mut sorted: Vec<&Constraint> = constraints.iter().collect();
+        sorted.sort();
+
+        let mut hasher = DefaultHasher::new();
+        sorted.hash(&mut hasher);
+
+        PathCacheKey {
+            map_fingerprint,
+            agent_id,
+            constraints_hash: hasher.finish(),
+            path_length_constraint,
+            sub_optimal_bits: sub_optimal.map(f64::to_bits),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PathCacheEntry {
+    pub(crate) path: Path,
+    pub(crate) f_min: usize,
+    pub(crate) mdd: Option<Mdd>,
+}
+
+/// Memoizes single-agent low-level searches keyed by `PathCacheKey`, so CBS's
+/// high-level tree doesn't re-run `a_star_search`/`focal_a_star_search` for
+/// an agent against a constraint set it (or a sibling node) already solved.
+/// Optionally round-trips to disk so the cache survives across runs against
+/// the same map.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PathCache {
+    entries: HashMap<PathCacheKey, PathCacheEntry>,
+}
+
+impl PathCache {
+    pub(crate) fn new() -> Self {
+        PathCache::default()
+    }
+
+    /// Loads a previously saved cache from `path`. Any failure (missing
+    /// file, corrupt contents) is treated as a cold start rather than an
+    /// error, since the cache is purely a performance optimization.
+    pub(crate) fn load(path: &str) -> Self {
+        match File::open(path) {
+            Ok(file) => match serde_yaml::from_reader(BufReader::new(file)) {
+                Ok(cache) => {
+                    debug!("loaded low-level path cache from {path}");
+                    cache
+                }
+                Err(e) => {
+                    warn!("failed to parse low-level path cache at {path}, starting empty: {e}");
+                    PathCache::new()
+                }
+            },
+            Err(_) => PathCache::new(),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &str) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_yaml::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, key: &PathCacheKey) -> Option<&PathCacheEntry> {
+        self.entries.get(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: PathCacheKey, entry: PathCacheEntry) {
+        self.entries.insert(key, entry);
+    }
+
+    /// Folds entries discovered by a concurrently executed branch (e.g. a
+    /// parallel child expansion working off its own clone) into this cache.
+    pub(crate) fn merge(&mut self, other: PathCache) {
+        self.entries.extend(other.entries);
+    }
+}
+
+/// Identifies a WDG pairwise-weight computation by everything that
+/// determines its result: the map, the two agents (order-independent), and
+/// each agent's current constraints/path-length floor. Mirrors
+/// `PathCacheKey`'s shape for the same reason: two nodes reached via
+/// different branch orders but carrying the same constraints for this pair
+/// get the same weight, so the first computed can be reused for the rest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PairWeightKey {
+    map_fingerprint: u64,
+    agent_lo: usize,
+    agent_hi: usize,
+    constraints_hash: u64,
+}
+
+impl PairWeightKey {
+    pub(crate) fn new(
+        map_fingerprint: u64,
+        agent_1: usize,
+        agent_2: usize,
+        constraints_1: &HashSet<Constraint>,
+        constraints_2: &HashSet<Constraint>,
+        path_length_constraint_1: usize,
+        path_length_constraint_2: usize,
+    ) -> Self {
+        let (agent_lo, agent_hi) = (agent_1.min(agent_2), agent_1.max(agent_2));
+        let (constraints_lo, constraints_hi, length_lo, length_hi) = if agent_1 <= agent_2 {
+            (
+                constraints_1,
+                constraints_2,
+                path_length_constraint_1,
+                path_length_constraint_2,
+            )
+        } else {
+            (
+                constraints_2,
+                constraints_1,
+                path_length_constraint_2,
+                path_length_constraint_1,
+            )
+        };
+
+        let mut sorted_lo: Vec<&Constraint> = constraints_lo.iter().collect();
+        sorted_lo.sort();
+        let mut sorted_hi: Vec<&Constraint> = constraints_hi.iter().collect();
+        sorted_hi.sort();
+
+        let mut hasher = DefaultHasher::new();
+        sorted_lo.hash(&mut hasher);
+        length_lo.hash(&mut hasher);
+        sorted_hi.hash(&mut hasher);
+        length_hi.hash(&mut hasher);
+
+        PairWeightKey {
+            map_fingerprint,
+            agent_lo,
+            agent_hi,
+            constraints_hash: hasher.finish(),
+        }
+    }
+}
+
+/// Memoizes WDG pairwise weights (see
+/// `HighLevelOpenNode::apply_wdg_heuristic`) keyed by `PairWeightKey`, so
+/// sibling high-level nodes that happen to carry the same constraints for a
+/// given agent pair don't each re-run a joint replan to price it. Unlike
+/// `PathCache` this never round-trips to disk: a pairwise weight is cheap to
+/// recompute and only useful within one solve.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PairWeightCache {
+    entries: HashMap<PairWeightKey, usize>,
+}
+
+impl PairWeightCache {
+    pub(crate) fn get(&self, key: &PairWeightKey) -> Option<usize> {
+        self.entries.get(key).copied()
+    }
+
+    pub(crate) fn insert(&mut self, key: PairWeightKey, weight: usize) {
+        self.entries.insert(key, weight);
+    }
+
+    /// Folds entries discovered by a concurrently executed branch into this
+    /// cache; see `PathCache::merge`.
+    pub(crate) fn merge(&mut self, other: PairWeightCache) {
+        self.entries.extend(other.entries);
+    }
+}
@@ -1,13 +1,20 @@
-use super::{is_singleton_at_position, Agent, Mdd, Path, SearchResult};
+use super::{
+    goal_mutex, is_singleton_at_position, Agent, Mdd, PairWeightCache, PairWeightKey, Path,
+    PathCache, PathCacheEntry, PathCacheKey, SearchResult,
+};
 use crate::algorithm::{a_star_search, focal_a_star_search};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
 
 use std::cmp::{max, Ordering};
-use std::collections::HashSet;
-use std::hash::Hash;
-use tracing::debug;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use tracing::{debug, warn};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub(crate) enum ConflictType {
@@ -24,6 +31,28 @@ pub(crate) enum ConflictType {
         position: (usize, usize),
         time_step: usize,
     },
+    /// An open-space symmetry: both agents cross the axis-aligned rectangle
+    /// spanned by `rs` (entry corner) and `rg` (exit corner) diagonally, so
+    /// an ordinary single-cell split would just rediscover the same
+    /// conflict one step further along the diagonal. `time_step` is when
+    /// the barred agent would reach `rs`; `agent_order.0`/`.1` are which
+    /// agent's children get barred from the row band vs. the column band.
+    /// See `try_classify_rectangle`.
+    Rectangle {
+        rs: (usize, usize),
+        rg: (usize, usize),
+        time_step: usize,
+        agent_order: (usize, usize),
+    },
+    /// A single-file symmetry: both agents walk the same straight line of
+    /// cells in opposite directions. `entry`/`exit` are the two ends of the
+    /// mirrored run and `time_step` is when the barred agent would reach
+    /// `entry`. See `try_classify_corridor`.
+    Corridor {
+        entry: (usize, usize),
+        exit: (usize, usize),
+        time_step: usize,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -42,17 +71,29 @@ pub(crate) struct Conflict {
     pub(crate) cardinal_type: CardinalType, // Prioritize Conflicts
 }
 
+/// Whether a `Constraint::Vertex`/`Constraint::Edge` forbids a cell/timestep
+/// or move (the ordinary CBS split) or, for disjoint splitting, requires the
+/// agent to pass through it as a landmark while every other agent is
+/// forbidden from it instead.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, Ord, PartialOrd)]
+pub(crate) enum ConstraintKind {
+    Negative,
+    Positive,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Ord, PartialOrd)]
 pub(crate) enum Constraint {
     Vertex {
         position: (usize, usize),
         time_step: usize,
         is_permanent: bool,
+        kind: ConstraintKind,
     },
     Edge {
         from_position: (usize, usize),
         to_position: (usize, usize),
         to_time_step: usize,
+        kind: ConstraintKind,
     },
 }
 
@@ -68,6 +109,7 @@ impl Constraint {
                 position,
                 time_step,
                 is_permanent,
+                kind: ConstraintKind::Negative,
             } => {
                 if to_pos != *position {
                     return false;
@@ -78,13 +120,232 @@ impl Constraint {
                     to_tmstep == *time_step
                 }
             }
+            // A positive (landmark) constraint requires the agent to be at
+            // `position` at exactly `time_step`: every move that arrives at
+            // `time_step` elsewhere is forbidden instead.
+            Constraint::Vertex {
+                position,
+                time_step,
+                kind: ConstraintKind::Positive,
+                ..
+            } => to_tmstep == *time_step && to_pos != *position,
             Constraint::Edge {
                 from_position,
                 to_position,
                 to_time_step,
+                kind: ConstraintKind::Negative,
             } => from_pos == *from_position && to_pos == *to_position && to_tmstep == *to_time_step,
+            // A positive edge constraint requires the agent to make exactly
+            // the `from_position` -> `to_position` move at `to_time_step`:
+            // every move arriving at `to_time_step` via a different edge is
+            // forbidden instead, mirroring the positive vertex case above.
+            Constraint::Edge {
+                from_position,
+                to_position,
+                to_time_step,
+                kind: ConstraintKind::Positive,
+            } => {
+                to_tmstep == *to_time_step && (from_pos != *from_position || to_pos != *to_position)
+            }
+        }
+    }
+}
+
+/// A precomputed index over a `HashSet<Constraint>`, built once per low-level
+/// search call, replacing the `constraints.iter().any(|c| c.is_violated(...))`
+/// linear scan that would otherwise run for every neighbor of every expanded
+/// node. `is_violated` reproduces `Constraint::is_violated`'s exact semantics
+/// (negative vertex constraints exact or, if permanent, thresholded by time;
+/// positive vertex constraints excluding every other position at that time;
+/// negative edge constraints exact; positive edge constraints excluding
+/// every other edge arriving at that time) but each case is an O(1)-ish
+/// bucket probe instead of a scan over every constraint.
+pub(crate) struct ConstraintIndex {
+    vertex_negative: HashSet<((usize, usize), usize)>,
+    vertex_negative_permanent: std::collections::HashMap<(usize, usize), Vec<usize>>,
+    vertex_positive: std::collections::HashMap<usize, Vec<(usize, usize)>>,
+    edge_negative: HashSet<((usize, usize), (usize, usize), usize)>,
+    edge_positive: std::collections::HashMap<usize, Vec<((usize, usize), (usize, usize))>>,
+}
+
+impl ConstraintIndex {
+    pub(crate) fn build(constraints: &HashSet<Constraint>) -> Self {
+        let mut vertex_negative = HashSet::new();
+        let mut vertex_negative_permanent: std::collections::HashMap<(usize, usize), Vec<usize>> =
+            std::collections::HashMap::new();
+        let mut vertex_positive: std::collections::HashMap<usize, Vec<(usize, usize)>> =
+            std::collections::HashMap::new();
+        let mut edge_negative = HashSet::new();
+        let mut edge_positive: std::collections::HashMap<
+            usize,
+            Vec<((usize, usize), (usize, usize))>,
+        > = std::collections::HashMap::new();
+
+        for constraint in constraints {
+            match constraint {
+                Constraint::Vertex {
+                    position,
+                    time_step,
+                    is_permanent: true,
+                    kind: ConstraintKind::Negative,
+                } => {
+                    vertex_negative_permanent
+                        .entry(*position)
+                        .or_default()
+                        .push(*time_step);
+                }
+                Constraint::Vertex {
+                    position,
+                    time_step,
+                    is_permanent: false,
+                    kind: ConstraintKind::Negative,
+                } => {
+                    vertex_negative.insert((*position, *time_step));
+                }
+                Constraint::Vertex {
+                    position,
+                    time_step,
+                    kind: ConstraintKind::Positive,
+                    ..
+                } => {
+                    vertex_positive
+                        .entry(*time_step)
+                        .or_default()
+                        .push(*position);
+                }
+                Constraint::Edge {
+                    from_position,
+                    to_position,
+                    to_time_step,
+                    kind: ConstraintKind::Negative,
+                } => {
+                    edge_negative.insert((*from_position, *to_position, *to_time_step));
+                }
+                Constraint::Edge {
+                    from_position,
+                    to_position,
+                    to_time_step,
+                    kind: ConstraintKind::Positive,
+                } => {
+                    edge_positive
+                        .entry(*to_time_step)
+                        .or_default()
+                        .push((*from_position, *to_position));
+                }
+            }
+        }
+
+        ConstraintIndex {
+            vertex_negative,
+            vertex_negative_permanent,
+            vertex_positive,
+            edge_negative,
+            edge_positive,
+        }
+    }
+
+    pub(crate) fn is_violated(
+        &self,
+        from_pos: (usize, usize),
+        to_pos: (usize, usize),
+        to_tmstep: usize,
+    ) -> bool {
+        if self.vertex_negative.contains(&(to_pos, to_tmstep)) {
+            return true;
+        }
+        if let Some(time_steps) = self.vertex_negative_permanent.get(&to_pos) {
+            if time_steps.iter().any(|&time_step| to_tmstep >= time_step) {
+                return true;
+            }
+        }
+        if let Some(positions) = self.vertex_positive.get(&to_tmstep) {
+            if positions.iter().any(|&position| position != to_pos) {
+                return true;
+            }
+        }
+        if self.edge_negative.contains(&(from_pos, to_pos, to_tmstep)) {
+            return true;
+        }
+        if let Some(edges) = self.edge_positive.get(&to_tmstep) {
+            if edges
+                .iter()
+                .any(|&(from, to)| (from, to) != (from_pos, to_pos))
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Drops constraints from `constraints` that cannot affect `agent`'s
+/// low-level replan, returning a compacted clone -- the canonical node
+/// constraints passed in are left untouched, so sibling nodes and the
+/// `PathCache` key still see the full set. Two cases are provably safe to
+/// drop:
+///  - a non-permanent negative vertex constraint already covered by a
+///    permanent negative vertex constraint at the same position (the
+///    permanent one forbids every timestep `>=` its own, so it dominates);
+///  - a negative vertex/edge constraint whose timestep is earlier than
+///    `agent` could possibly reach that position at all: a grid move
+///    changes one coordinate by at most one cell per timestep, so the
+///    Manhattan distance from `agent.start` is a lower bound on arrival
+///    time, and a constraint timed before that bound can never trigger.
+/// Positive (landmark) constraints are never dropped here: disjoint
+/// splitting relies on the low-level search actually honoring a "must
+/// occupy" requirement, and a wrongly-pruned one would silently break that
+/// guarantee instead of just costing a slower search.
+pub(crate) fn prune_dead_constraints(
+    constraints: &HashSet<Constraint>,
+    agent: &Agent,
+) -> HashSet<Constraint> {
+    let mut permanent_from: std::collections::HashMap<(usize, usize), usize> =
+        std::collections::HashMap::new();
+    for constraint in constraints {
+        if let Constraint::Vertex {
+            position,
+            time_step,
+            is_permanent: true,
+            kind: ConstraintKind::Negative,
+        } = constraint
+        {
+            permanent_from
+                .entry(*position)
+                .and_modify(|t| *t = (*t).min(*time_step))
+                .or_insert(*time_step);
         }
     }
+
+    let min_reach = |position: (usize, usize)| manhattan(agent.start, position);
+
+    constraints
+        .iter()
+        .filter(|constraint| match constraint {
+            Constraint::Vertex {
+                position,
+                time_step,
+                is_permanent: false,
+                kind: ConstraintKind::Negative,
+            } => {
+                !permanent_from
+                    .get(position)
+                    .is_some_and(|&t| t <= *time_step)
+                    && *time_step >= min_reach(*position)
+            }
+            Constraint::Vertex {
+                kind: ConstraintKind::Negative,
+                ..
+            } => true,
+            Constraint::Edge {
+                to_position,
+                to_time_step,
+                kind: ConstraintKind::Negative,
+                ..
+            } => *to_time_step >= min_reach(*to_position),
+            _ => true,
+        })
+        .cloned()
+        .collect()
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -97,13 +358,20 @@ pub(crate) struct HighLevelOpenNode {
     pub(crate) paths: Vec<Path>, // Maps agent IDs to their paths
     pub(crate) cost: usize,      // Total cost for all paths under current constraints
     pub(crate) low_level_f_min_agents: Vec<usize>, // Agent's f_min, used for ECBS
-    pub(crate) mdds: Vec<Option<Mdd>>,
+    pub(crate) mdds: Vec<Option<Arc<Mdd>>>,
+    pub(crate) h_cardinal: usize, // Admissible MVC-over-cardinal-conflicts heuristic, added to `cost` for Ord
+    // MA-CBS: `meta_agent_of[a]` is the leader agent index of the meta-agent
+    // `a` currently belongs to (itself if unmerged); `conflict_counts[i][j]`
+    // is how many times `i`/`j` have conflicted across this branch of the
+    // CT. See `update_constraint`'s merge check and `merge_and_replan`.
+    pub(crate) meta_agent_of: Vec<usize>,
+    pub(crate) conflict_counts: Vec<Vec<usize>>,
 }
 
 impl Ord for HighLevelOpenNode {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.cost
-            .cmp(&other.cost)
+        (self.cost + self.h_cardinal)
+            .cmp(&(other.cost + other.h_cardinal))
             .then_with(|| self.conflicts.cmp(&other.conflicts))
             // We still need to compare the actual paths, since it will indeed
             // influence the optimal solution
@@ -117,6 +385,147 @@ impl PartialOrd for HighLevelOpenNode {
     }
 }
 
+/// Packed bit-matrix adjacency for the conflict graph CG/DG/WDG heuristics
+/// build over agents: one `u64`-word row per agent instead of a `HashSet`
+/// per agent, so membership tests and neighbor scans are O(words) rather
+/// than hashing, which matters once this runs on every node expansion.
+struct AdjacencyBits {
+    words_per_row: usize,
+    bits: Vec<u64>,
+}
+
+impl AdjacencyBits {
+    fn new(num_agents: usize) -> Self {
+        let words_per_row = (num_agents + 63) / 64;
+        AdjacencyBits {
+            words_per_row,
+            bits: vec![0u64; words_per_row * num_agents.max(1)],
+        }
+    }
+
+    fn set(&mut self, i: usize, j: usize) {
+        self.bits[i * self.words_per_row + j / 64] |= 1u64 << (j % 64);
+        self.bits[j * self.words_per_row + i / 64] |= 1u64 << (i % 64);
+    }
+
+    fn is_empty_row(&self, i: usize) -> bool {
+        let row = &self.bits[i * self.words_per_row..(i + 1) * self.words_per_row];
+        row.iter().all(|&word| word == 0)
+    }
+
+    /// Agents adjacent to `i`, found by scanning set bits word-by-word
+    /// (via `trailing_zeros`) instead of testing every other agent one by
+    /// one.
+    fn neighbors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        let row = &self.bits[i * self.words_per_row..(i + 1) * self.words_per_row];
+        row.iter().enumerate().flat_map(|(word_idx, &word)| {
+            let mut remaining = word;
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    None
+                } else {
+                    let bit = remaining.trailing_zeros() as usize;
+                    remaining &= remaining - 1;
+                    Some(word_idx * 64 + bit)
+                }
+            })
+        })
+    }
+}
+
+/// An admissible lower bound on the extra cost still required to reach a
+/// conflict-free solution: the minimum vertex cover of the graph whose
+/// vertices are agents and whose edges are pairs with a *cardinal* conflict
+/// (every cardinal conflict forces a cost increase on at least one of its two
+/// agents, so covering every edge lower-bounds the remaining cost). This is
+/// the unweighted "CG" heuristic; see `HighLevelOpenNode::apply_wdg_heuristic`
+/// for the weighted "WDG" variant.
+pub(crate) fn cardinal_conflict_heuristic(conflicts: &[Conflict], num_agents: usize) -> usize {
+    let mut adjacency = AdjacencyBits::new(num_agents);
+    for conflict in conflicts {
+        if conflict.cardinal_type == CardinalType::Cardinal {
+            adjacency.set(conflict.agent_1, conflict.agent_2);
+        }
+    }
+
+    // Disconnected components (including isolated, conflict-free agents) can
+    // be solved independently; sum their minimum vertex covers.
+    let mut visited = vec![false; num_agents];
+    let mut total = 0;
+    for start in 0..num_agents {
+        if visited[start] || adjacency.is_empty_row(start) {
+            visited[start] = true;
+            continue;
+        }
+
+        let mut component = Vec::new();
+        let mut stack = vec![start];
+        visited[start] = true;
+        while let Some(node) = stack.pop() {
+            component.push(node);
+            for next in adjacency.neighbors(node) {
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let edges: Vec<(usize, usize, usize)> = component
+            .iter()
+            .flat_map(|&u| adjacency.neighbors(u).map(move |v| (u, v, 1)))
+            .filter(|&(u, v, _)| u < v)
+            .collect();
+        total += weighted_vertex_cover(&edges);
+    }
+    total
+}
+
+/// Branch-and-bound weighted minimum vertex cover: each agent gets a
+/// non-negative `x_i`, each edge `(i, j, w)` requires `x_i + x_j >= w`, and
+/// the objective minimizes `sum x_i`. Picks an uncovered edge `(u, v, w)`
+/// and branches on satisfying it via `x_u = w` or `x_v = w` -- whichever
+/// endpoint is chosen absorbs `w` off every other edge incident to it
+/// (floored at zero, dropping edges that become fully covered) before
+/// recursing, and the cheaper branch wins. With every weight equal to 1
+/// this is exactly the unweighted minimum vertex cover `cardinal_conflict_
+/// heuristic` used before WDG support, since "absorb 1, drop if 0" is
+/// "remove every edge touching the chosen endpoint".
+fn weighted_vertex_cover(edges: &[(usize, usize, usize)]) -> usize {
+    let Some(&(u, v, w)) = edges.first() else {
+        return 0;
+    };
+
+    let absorb = |endpoint: usize| -> Vec<(usize, usize, usize)> {
+        edges
+            .iter()
+            .filter_map(|&(a, b, edge_w)| {
+                if a == endpoint || b == endpoint {
+                    let remaining = edge_w.saturating_sub(w);
+                    (remaining > 0).then_some((a, b, remaining))
+                } else {
+                    Some((a, b, edge_w))
+                }
+            })
+            .collect()
+    };
+
+    (w + weighted_vertex_cover(&absorb(u))).min(w + weighted_vertex_cover(&absorb(v)))
+}
+
+/// Turns a detected `Conflict` into the negative constraint its
+/// `agent_to_update` child gets. A `Vertex` conflict bars that agent from
+/// the cell/timestep; an `Edge` (swap) conflict bars it from traversing the
+/// directed edge it actually took (`resolve_first`) or the opposite
+/// direction the other agent took (`!resolve_first`) at that timestep,
+/// which is what lets `Constraint::is_violated` catch head-on swaps that a
+/// vertex-only constraint would miss; a `Target` conflict bars
+/// `agent_to_update` from the target cell at that timestep and, on the
+/// `resolve_first` branch (where `agent_to_update` is the target-holding
+/// `agent_1`), also raises its path-length floor to that timestep, or, on
+/// the `!resolve_first` branch under `target_reasoning`, permanently bars
+/// every agent other than `agent_1` from the target cell from that timestep
+/// onward instead.
 pub(crate) fn convert_conflict_to_constraint(
     conflict: &Conflict,
     resolve_first: bool,
@@ -134,6 +543,7 @@ pub(crate) fn convert_conflict_to_constraint(
                 position,
                 time_step,
                 is_permanent: false,
+                kind: ConstraintKind::Negative,
             });
         }
         ConflictType::Edge {
@@ -146,12 +556,14 @@ pub(crate) fn convert_conflict_to_constraint(
                     from_position,
                     to_position,
                     to_time_step,
+                    kind: ConstraintKind::Negative,
                 }
             } else {
                 Constraint::Edge {
                     from_position: to_position,
                     to_position: from_position,
                     to_time_step,
+                    kind: ConstraintKind::Negative,
                 }
             });
         }
@@ -169,6 +581,7 @@ pub(crate) fn convert_conflict_to_constraint(
                             position,
                             time_step,
                             is_permanent: true,
+                            kind: ConstraintKind::Negative,
                         });
                     });
             } else {
@@ -176,6 +589,7 @@ pub(crate) fn convert_conflict_to_constraint(
                     position,
                     time_step,
                     is_permanent: false,
+                    kind: ConstraintKind::Negative,
                 });
 
                 if resolve_first {
@@ -184,7 +598,649 @@ pub(crate) fn convert_conflict_to_constraint(
                 }
             }
         }
+        ConflictType::Rectangle { .. } | ConflictType::Corridor { .. } => {
+            unreachable!(
+                "rectangle/corridor conflicts are resolved via barrier constraints in \
+                 update_constraint before convert_conflict_to_constraint is called"
+            )
+        }
+    }
+}
+
+fn manhattan(a: (usize, usize), b: (usize, usize)) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+}
+
+/// Per-agent size of the connected component it sits in within the
+/// dependency graph over this node's conflicts: an edge `agent_1 <->
+/// agent_2` means resolving one's conflict (via `convert_conflict_to_
+/// constraint`) forces a replan that can change the other's cost or
+/// feasibility. Agents on a small component are tightly coupled -- hard to
+/// pull apart without touching every member -- while a large component is
+/// more loosely coupled; `select_prioritized_conflict` below uses this to break ties
+/// within a `CardinalType` tier.
+fn conflict_coupling_sizes(conflicts: &[Conflict], num_agents: usize) -> Vec<usize> {
+    let mut adjacency = AdjacencyBits::new(num_agents);
+    for conflict in conflicts {
+        adjacency.set(conflict.agent_1, conflict.agent_2);
+    }
+
+    let mut component_of = vec![usize::MAX; num_agents];
+    let mut component_sizes = Vec::new();
+    for start in 0..num_agents {
+        if component_of[start] != usize::MAX {
+            continue;
+        }
+        let component_id = component_sizes.len();
+        let mut queue = VecDeque::from([start]);
+        component_of[start] = component_id;
+        let mut size = 0;
+        while let Some(agent) = queue.pop_front() {
+            size += 1;
+            for neighbor in adjacency.neighbors(agent) {
+                if component_of[neighbor] == usize::MAX {
+                    component_of[neighbor] = component_id;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        component_sizes.push(size);
+    }
+
+    component_of
+        .into_iter()
+        .map(|component_id| component_sizes[component_id])
+        .collect()
+}
+
+/// Picks the conflict a node should split on next among the Cardinal,
+/// SemiCardinal and NonCardinal tiers (in that priority order), shared by
+/// every solver that honors `config.op_prioritize_conflicts`. Ties within a
+/// tier are broken by `conflict_coupling_sizes`, preferring the conflict
+/// sitting on the smallest (most tightly-coupled) dependency component --
+/// splitting there first is expected to prune the most branching from the
+/// high-level tree. Returns `None` if none of the three tiers have a match
+/// (e.g. every conflict is `CardinalType::Unknown`); callers fall back to
+/// their own default conflict in that case.
+pub(crate) fn select_prioritized_conflict(
+    conflicts: &[Conflict],
+    num_agents: usize,
+) -> Option<&Conflict> {
+    let coupling_size = conflict_coupling_sizes(conflicts, num_agents);
+    let best = |cardinal_type: CardinalType| {
+        conflicts
+            .iter()
+            .filter(|c| c.cardinal_type == cardinal_type)
+            .min_by_key(|c| coupling_size[c.agent_1])
+    };
+
+    best(CardinalType::Cardinal)
+        .or_else(|| best(CardinalType::SemiCardinal))
+        .or_else(|| best(CardinalType::NonCardinal))
+}
+
+/// True if every cell in the axis-aligned box spanning `rs`/`rg` (inclusive,
+/// in either corner order) is passable. `rectangle_barrier_constraints` bars
+/// an agent from the whole row/column band between the two corners, so an
+/// obstacle anywhere inside the box would make that barrier unsound: it
+/// could block the only valid route around the wall instead of merely the
+/// rectangle-symmetric ones. Mirrors `corridor_degree`'s role for the
+/// corridor case, just checked over an area instead of a single cell.
+fn rectangle_is_obstacle_free(map: &Map, rs: (usize, usize), rg: (usize, usize)) -> bool {
+    let (row_lo, row_hi) = (rs.0.min(rg.0), rs.0.max(rg.0));
+    let (col_lo, col_hi) = (rs.1.min(rg.1), rs.1.max(rg.1));
+    (row_lo..=row_hi).all(|row| (col_lo..=col_hi).all(|col| map.is_passable(row, col)))
+}
+
+/// Attempts to reclassify a plain vertex conflict at `position`/`step`
+/// between agents `i`/`j` as a rectangle symmetry. This is a deliberately
+/// simplified version of the rectangle reasoning in Li et al. 2019's
+/// "Symmetry Breaking for K-Robust Multi-Agent Path Finding": it only
+/// checks that each agent's overall path is Manhattan-optimal and that both
+/// move diagonally through the same quadrant, rather than testing MDD-level
+/// optimality inside the box. `rectangle_is_obstacle_free` is what keeps
+/// this sound on maps with walls inside the bounding rectangle (this
+/// series' benchmark maps very much included): without it, the barrier
+/// constraint below could exclude the only valid route around such a wall
+/// instead of merely the rectangle-symmetric detours.
+///
+/// Gated on both agents' MDDs being "fat" (more than one cell wide) at
+/// `position`/`step`: that is exactly the case where plain single-cell
+/// splitting would otherwise regenerate the same conflict one step further
+/// along the diagonal for many equal-cost detours, blowing up the high-level
+/// tree. When either MDD is missing or singleton there, a plain vertex split
+/// already pins the unique path through the cell, so there is nothing a
+/// barrier constraint would save.
+fn try_classify_rectangle(
+    agents: &[Agent],
+    paths: &[Path],
+    mdd1: Option<&Mdd>,
+    mdd2: Option<&Mdd>,
+    map: &Map,
+    i: usize,
+    j: usize,
+    position: (usize, usize),
+    step: usize,
+) -> Option<ConflictType> {
+    let (s1, g1) = (agents[i].start, agents[i].goal);
+    let (s2, g2) = (agents[j].start, agents[j].goal);
+
+    let is_optimal = |path: &Path, start: (usize, usize), goal: (usize, usize)| {
+        path.len() - 1 == manhattan(start, goal)
+    };
+    if !is_optimal(&paths[i], s1, g1) || !is_optimal(&paths[j], s2, g2) {
+        return None;
+    }
+
+    let both_fat = matches!(
+        (mdd1, mdd2),
+        (Some(mdd1), Some(mdd2))
+            if !is_singleton_at_position(mdd1, step, position)
+                && !is_singleton_at_position(mdd2, step, position)
+    );
+    if !both_fat {
+        return None;
+    }
+
+    let dy1 = (g1.0 as isize - s1.0 as isize).signum();
+    let dx1 = (g1.1 as isize - s1.1 as isize).signum();
+    let dy2 = (g2.0 as isize - s2.0 as isize).signum();
+    let dx2 = (g2.1 as isize - s2.1 as isize).signum();
+
+    // A rectangle requires genuine 2D diagonal motion, in the same
+    // quadrant, for both agents; purely-horizontal or -vertical agents
+    // produce an ordinary corridor/vertex conflict instead.
+    if dy1 == 0 || dx1 == 0 || dy1 != dy2 || dx1 != dx2 {
+        return None;
+    }
+
+    let rs = (
+        if dy1 > 0 {
+            s1.0.min(s2.0)
+        } else {
+            s1.0.max(s2.0)
+        },
+        if dx1 > 0 {
+            s1.1.min(s2.1)
+        } else {
+            s1.1.max(s2.1)
+        },
+    );
+    let rg = (
+        if dy1 > 0 {
+            g1.0.max(g2.0)
+        } else {
+            g1.0.min(g2.0)
+        },
+        if dx1 > 0 {
+            g1.1.max(g2.1)
+        } else {
+            g1.1.min(g2.1)
+        },
+    );
+    if rs == rg {
+        return None;
+    }
+
+    let inside = position.0 >= rs.0.min(rg.0)
+        && position.0 <= rs.0.max(rg.0)
+        && position.1 >= rs.1.min(rg.1)
+        && position.1 <= rs.1.max(rg.1);
+    if !inside {
+        return None;
+    }
+
+    if !rectangle_is_obstacle_free(map, rs, rg) {
+        return None;
+    }
+
+    // Whichever agent's start sits on the entry row travels mainly along
+    // that row, so it is the one crossing the other's column band, and
+    // vice versa.
+    let agent_order = if s1.0 == rs.0 { (i, j) } else { (j, i) };
+
+    Some(ConflictType::Rectangle {
+        rs,
+        rg,
+        time_step: step.saturating_sub(manhattan(position, rs)),
+        agent_order,
+    })
+}
+
+/// A cell's move-degree in `map`, excluding the "stay in place" neighbor
+/// `get_neighbors` always includes: 2 or fewer means it sits on a single-file
+/// passage (a dead end or a straight/bent corridor segment) rather than an
+/// intersection or open area.
+fn corridor_degree(map: &Map, position: (usize, usize)) -> usize {
+    map.get_neighbors(position.0, position.1, true).len() - 1
+}
+
+/// Attempts to reclassify an edge (head-on swap) conflict at `to_time_step`
+/// between agents `i`/`j` as a corridor symmetry: we reflect outward from the
+/// swap while each side keeps mirroring the other, which is the signature of
+/// two agents walking the same straight, width-1 line in opposite
+/// directions, then require every mirrored cell to have `corridor_degree`
+/// at most 2 in `map`. The degree check is what tells a genuine narrow
+/// passage (barring one agent from it for its full length is safe, since
+/// there is no alternate route around the barrier) apart from two paths
+/// that happen to mirror through open space, where a side-step could let
+/// the barred agent slip past and the barrier constraint would wrongly
+/// exclude a valid solution.
+fn try_classify_corridor(
+    map: &Map,
+    paths: &[Path],
+    i: usize,
+    j: usize,
+    to_time_step: usize,
+) -> Option<ConflictType> {
+    let path1 = &paths[i];
+    let path2 = &paths[j];
+
+    if corridor_degree(map, path1[to_time_step - 1]) > 2
+        || corridor_degree(map, path2[to_time_step - 1]) > 2
+    {
+        return None;
+    }
+
+    let mut span = 0usize;
+    loop {
+        let next = span + 1;
+        let Some(t1) = to_time_step.checked_sub(1 + next) else {
+            break;
+        };
+        let t2 = to_time_step + next;
+        let (Some(&p1), Some(&p2)) = (path1.get(t1), path2.get(t2)) else {
+            break;
+        };
+        if p1 != p2 || corridor_degree(map, p1) > 2 {
+            break;
+        }
+        span = next;
     }
+
+    if span == 0 {
+        return None;
+    }
+
+    Some(ConflictType::Corridor {
+        entry: path1[to_time_step - 1 - span],
+        exit: path1[to_time_step - 1],
+        time_step: to_time_step - 1 - span,
+    })
+}
+
+/// Builds the full row band (fixed row `rs.0`, columns spanning `rs.1`..`rg.1`)
+/// or column band (fixed column `rs.1`, rows spanning `rs.0`..`rg.0`) of
+/// negative vertex constraints for a rectangle barrier, timed from
+/// `time_step` (when the barred agent reaches `rs`) by Manhattan distance
+/// from `rs` along the band.
+fn rectangle_barrier_constraints(
+    rs: (usize, usize),
+    rg: (usize, usize),
+    time_step: usize,
+    row_band: bool,
+) -> Vec<Constraint> {
+    if row_band {
+        let (lo, hi) = (rs.1.min(rg.1), rs.1.max(rg.1));
+        (lo..=hi)
+            .map(|col| Constraint::Vertex {
+                position: (rs.0, col),
+                time_step: time_step + col.abs_diff(rs.1),
+                is_permanent: false,
+                kind: ConstraintKind::Negative,
+            })
+            .collect()
+    } else {
+        let (lo, hi) = (rs.0.min(rg.0), rs.0.max(rg.0));
+        (lo..=hi)
+            .map(|row| Constraint::Vertex {
+                position: (row, rs.1),
+                time_step: time_step + row.abs_diff(rs.0),
+                is_permanent: false,
+                kind: ConstraintKind::Negative,
+            })
+            .collect()
+    }
+}
+
+fn step_towards(from: usize, to: usize) -> isize {
+    match from.cmp(&to) {
+        Ordering::Less => 1,
+        Ordering::Greater => -1,
+        Ordering::Equal => 0,
+    }
+}
+
+/// Rebuilds the straight line of cells between a corridor's `entry` and
+/// `exit` (one coordinate constant, the other sweeping between the two) and
+/// returns one negative vertex constraint per cell, timed assuming the
+/// barred agent crosses one cell per timestep starting at `entry_time_step`.
+fn corridor_barrier_constraints(
+    entry: (usize, usize),
+    exit: (usize, usize),
+    entry_time_step: usize,
+) -> Vec<Constraint> {
+    let steps = entry.0.abs_diff(exit.0).max(entry.1.abs_diff(exit.1));
+    let row_step = step_towards(entry.0, exit.0);
+    let col_step = step_towards(entry.1, exit.1);
+
+    (0..=steps)
+        .map(|k| Constraint::Vertex {
+            position: (
+                (entry.0 as isize + row_step * k as isize) as usize,
+                (entry.1 as isize + col_step * k as isize) as usize,
+            ),
+            time_step: entry_time_step + k,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        })
+        .collect()
+}
+
+/// Detects vertex/edge/target conflicts between agents `i` and `j` (`i < j`)
+/// and appends them to `conflicts`. Shared by `detect_conflicts` (all pairs)
+/// and `recompute_conflicts_for` (just the replanned agent against the
+/// rest), so both stay in sync with exactly one copy of the detection logic.
+#[allow(clippy::too_many_arguments)]
+fn detect_conflicts_between(
+    agents: &[Agent],
+    paths: &[Path],
+    mdds: &[Option<Arc<Mdd>>],
+    map: &Map,
+    i: usize,
+    j: usize,
+    op_target_reasoning: bool,
+    op_symmetry_reasoning: bool,
+    op_mutex_reasoning: bool,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let path1 = &paths[i];
+    let path2 = &paths[j];
+    let max_length = path1.len().max(path2.len());
+
+    let mdd1 = &mdds[i];
+    let mdd2 = &mdds[j];
+
+    // Under `op_mutex_reasoning`, a conflict that `is_singleton_at_position`
+    // alone would call `SemiCardinal`/`NonCardinal` still gets upgraded to
+    // `Cardinal` once mutex propagation (see `goal_mutex`) shows the two
+    // agents' goal nodes are mutex -- that's unavoidable regardless of
+    // either MDD's width, which a plain singleton check can miss.
+    let upgrade_via_mutex = |cardinal_type: CardinalType| -> CardinalType {
+        if op_mutex_reasoning && cardinal_type != CardinalType::Cardinal {
+            if let (Some(mdd1), Some(mdd2)) = (mdd1.as_deref(), mdd2.as_deref()) {
+                if goal_mutex(mdd1, mdd2) {
+                    return CardinalType::Cardinal;
+                }
+            }
+        }
+        cardinal_type
+    };
+
+    // Start from 1 since:
+    // 1. Initial positions (step 0) can't have vertex conflicts (agents start at different positions).
+    // 2. Edge conflicts need previous step, so can only start from step 1.
+    for step in 1..max_length {
+        let pos1 = if step < path1.len() {
+            path1[step]
+        } else {
+            *path1.last().unwrap()
+        };
+        let pos2 = if step < path2.len() {
+            path2[step]
+        } else {
+            *path2.last().unwrap()
+        };
+
+        // Check for Vertex Conflict
+        if pos1 == pos2 {
+            // Check for cardinal type
+            let cardinal_type = upgrade_via_mutex(match (&mdd1, &mdd2) {
+                (Some(mdd1), Some(mdd2)) => {
+                    let singlenton1 = is_singleton_at_position(mdd1, step, pos1);
+                    let singlenton2 = is_singleton_at_position(mdd2, step, pos2);
+                    if singlenton1 && singlenton2 {
+                        CardinalType::Cardinal
+                    } else if singlenton1 || singlenton2 {
+                        CardinalType::SemiCardinal
+                    } else {
+                        CardinalType::NonCardinal
+                    }
+                }
+                (Some(mdd), None) | (None, Some(mdd)) => {
+                    let singlenton = is_singleton_at_position(mdd, step, pos1);
+                    if singlenton {
+                        CardinalType::SemiCardinal
+                    } else {
+                        CardinalType::NonCardinal
+                    }
+                }
+                _ => CardinalType::Unknown,
+            });
+
+            // Check for target conflicts first
+            if step >= path1.len() - 1 && pos1 == agents[i].goal {
+                // Agent i is at its target and agent j is interfering
+                conflicts.push(Conflict {
+                    agent_1: i,
+                    agent_2: j,
+                    conflict_type: ConflictType::Target {
+                        position: pos1,
+                        time_step: step,
+                    },
+                    cardinal_type: if op_target_reasoning {
+                        cardinal_type
+                    } else {
+                        CardinalType::Unknown
+                    },
+                });
+            } else if step >= path2.len() - 1 && pos2 == agents[j].goal {
+                // Agent j is at its target and agent i is interfering
+                conflicts.push(Conflict {
+                    agent_1: j,
+                    agent_2: i,
+                    conflict_type: ConflictType::Target {
+                        position: pos2,
+                        time_step: step,
+                    },
+                    cardinal_type: if op_target_reasoning {
+                        cardinal_type
+                    } else {
+                        CardinalType::Unknown
+                    },
+                });
+            } else {
+                // Regular vertex conflict, upgraded to a rectangle symmetry
+                // when op_symmetry_reasoning finds one.
+                let conflict_type = (op_symmetry_reasoning)
+                    .then(|| {
+                        try_classify_rectangle(
+                            agents,
+                            paths,
+                            mdd1.as_deref(),
+                            mdd2.as_deref(),
+                            map,
+                            i,
+                            j,
+                            pos1,
+                            step,
+                        )
+                    })
+                    .flatten()
+                    .unwrap_or(ConflictType::Vertex {
+                        position: pos1,
+                        time_step: step,
+                    });
+                conflicts.push(Conflict {
+                    agent_1: i,
+                    agent_2: j,
+                    conflict_type,
+                    cardinal_type,
+                });
+            }
+        }
+
+        // Check for Edge Conflict.
+        if step >= path1.len() || step >= path2.len() {
+            continue;
+        }
+
+        let prev_pos1 = path1[step - 1];
+        let prev_pos2 = path2[step - 1];
+
+        if prev_pos1 == pos2 && prev_pos2 == pos1 {
+            let cardinal_type = upgrade_via_mutex(match (&mdd1, &mdd2) {
+                (Some(mdd1), Some(mdd2)) => {
+                    // For edge conflicts, need singletons at both t-1 and t.
+                    let agent1_singleton = is_singleton_at_position(mdd1, step - 1, prev_pos1)
+                        && is_singleton_at_position(mdd1, step, pos1);
+                    let agent2_singleton = is_singleton_at_position(mdd2, step - 1, prev_pos2)
+                        && is_singleton_at_position(mdd2, step, pos2);
+
+                    if agent1_singleton && agent2_singleton {
+                        CardinalType::Cardinal
+                    } else if agent1_singleton || agent2_singleton {
+                        CardinalType::SemiCardinal
+                    } else {
+                        CardinalType::NonCardinal
+                    }
+                }
+                (Some(mdd), None) | (None, Some(mdd)) => {
+                    let singlenton = is_singleton_at_position(mdd, step - 1, prev_pos1)
+                        && is_singleton_at_position(mdd, step, pos1);
+                    if singlenton {
+                        CardinalType::SemiCardinal
+                    } else {
+                        CardinalType::NonCardinal
+                    }
+                }
+                _ => CardinalType::Unknown,
+            });
+
+            // Edge (head-on swap) conflict, upgraded to a corridor symmetry
+            // when op_symmetry_reasoning finds one.
+            let conflict_type = (op_symmetry_reasoning)
+                .then(|| try_classify_corridor(map, paths, i, j, step))
+                .flatten()
+                .unwrap_or(ConflictType::Edge {
+                    from_position: prev_pos1,
+                    to_position: pos1,
+                    to_time_step: step,
+                });
+            conflicts.push(Conflict {
+                agent_1: i,
+                agent_2: j,
+                conflict_type,
+                cardinal_type,
+            });
+        }
+    }
+}
+
+/// Plans a single agent's unconstrained root path, going through `cache`
+/// first. Used by `HighLevelOpenNode::new`'s `config.op_parallel_expansion`
+/// branch, where every agent is planned this way concurrently via rayon.
+/// `"cbs"`/`"hbcbs"` genuinely plan independently of every other agent here
+/// (no constraints, no focal heuristic), so parallelizing them changes
+/// nothing but wall-clock time. `"lbcbs"`/`"bcbs"`/`"ecbs"`/`"decbs"`/`"acbs"`
+/// normally thread the other agents' paths-so-far into the focal heuristic
+/// to bias root planning away from conflicts (see the sequential branch
+/// below), which this parallel path can't do since every agent is planned
+/// before any other agent's path exists; it passes an empty `paths` slice
+/// instead. That trades a (possibly) more-conflicted root node for
+/// concurrent low-level search, same as `config.op_parallel_expansion`
+/// already trades for `"cbs"`/`"hbcbs"` by skipping `config.op_prioritize_conflicts`-
+/// driven ordering -- never a correctness issue, since the high-level
+/// search still resolves whatever conflicts the root starts with.
+fn plan_root_agent_independent(
+    agent: &Agent,
+    map: &Map,
+    config: &Config,
+    solver: &str,
+    stats: &mut Stats,
+    map_fingerprint: u64,
+    cache: &mut PathCache,
+) -> Option<(Path, usize, Option<Mdd>)> {
+    let cache_key = PathCacheKey::new(
+        map_fingerprint,
+        agent.id,
+        &HashSet::new(),
+        0,
+        config.sub_optimal.1,
+    );
+    let cached_entry = config
+        .low_level_cache
+        .then(|| cache.get(&cache_key))
+        .flatten();
+
+    if let Some(cached) = cached_entry {
+        stats.low_level_cache_hits += 1;
+        return Some((cached.path.clone(), cached.f_min, cached.mdd.clone()));
+    }
+    stats.low_level_cache_misses += 1;
+
+    let (path, low_level_f_min, mdd) = match solver {
+        "cbs" | "hbcbs" => match a_star_search(
+            map,
+            agent,
+            &HashSet::new(),
+            0,
+            config.op_prioritize_conflicts,
+            config.low_level_mode.as_str(),
+            config.low_level_weight,
+            config.low_level_beam_width,
+            stats,
+        ) {
+            SearchResult::Standard(Some((path, low_level_f_min))) => (path, low_level_f_min, None),
+            SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
+                (path, low_level_f_min, Some(mdd))
+            }
+            SearchResult::Partial { reached, h_remaining, .. } => {
+                warn!("agent {} cannot reach its goal at all (unconstrained search fell short: reached {reached:?} with h_remaining {h_remaining}) -- instance is likely infeasible", agent.id);
+                return None;
+            }
+            _ => return None,
+        },
+        "lbcbs" | "bcbs" | "ecbs" | "decbs" | "acbs" => match focal_a_star_search(
+            map,
+            agent,
+            config.sub_optimal.1.unwrap(),
+            &HashSet::new(),
+            0,
+            &[],
+            config.op_prioritize_conflicts,
+            solver,
+            config.focal_heuristic.as_str(),
+            config.low_level_mode.as_str(),
+            config.low_level_weight,
+            config.low_level_beam_width,
+            stats,
+        ) {
+            SearchResult::Standard(Some((path, low_level_f_min))) => (path, low_level_f_min, None),
+            SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
+                (path, low_level_f_min, Some(mdd))
+            }
+            SearchResult::Partial { reached, h_remaining, .. } => {
+                warn!("agent {} cannot reach its goal at all (unconstrained search fell short: reached {reached:?} with h_remaining {h_remaining}) -- instance is likely infeasible", agent.id);
+                return None;
+            }
+            _ => return None,
+        },
+        _ => unreachable!(),
+    };
+
+    if config.low_level_cache {
+        cache.insert(
+            cache_key,
+            PathCacheEntry {
+                path: path.clone(),
+                f_min: low_level_f_min,
+                mdd: mdd.clone(),
+            },
+        );
+    }
+
+    Some((path, low_level_f_min, mdd))
 }
 
 impl HighLevelOpenNode {
@@ -194,57 +1250,149 @@ impl HighLevelOpenNode {
         config: &Config,
         solver: &str,
         stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
     ) -> Option<Self> {
         let mut paths = Vec::new();
         let mut low_level_f_min_agents = Vec::new();
         let mut mdds = Vec::new();
         let mut total_cost = 0;
 
-        for agent in agents {
-            let (path, low_level_f_min, mdd) = match solver {
-                "cbs" | "hbcbs" => match a_star_search(
-                    map,
-                    agent,
-                    &HashSet::new(),
-                    0,
-                    config.op_prioritize_conflicts,
-                    stats,
-                ) {
-                    SearchResult::Standard(Some((path, low_level_f_min))) => {
-                        (path, low_level_f_min, None)
-                    }
-                    SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
-                        (path, low_level_f_min, Some(mdd))
-                    }
-                    _ => return None,
-                },
-                "lbcbs" | "bcbs" | "ecbs" | "decbs" => match focal_a_star_search(
-                    map,
-                    agent,
-                    Some(0),
-                    config.sub_optimal.1.unwrap(),
+        // The sequential branch below threads each already-planned agent's
+        // path into the next agent's focal heuristic (for "lbcbs"/"bcbs"/
+        // "ecbs"/"decbs"/"acbs") so root planning is biased away from
+        // conflicts before the high-level search even starts. Under
+        // `config.op_parallel_expansion` every agent is instead planned
+        // concurrently via rayon, one task per agent, each against an empty
+        // `paths` (see `plan_root_agent_independent`) and its own
+        // `Stats`/`PathCache` clone folded back in afterwards -- "cbs"/
+        // "hbcbs" lose nothing since they never used `paths` here anyway;
+        // the focal solvers trade a little root conflict-avoidance for
+        // linear speedup on large instances.
+        if config.op_parallel_expansion {
+            let results: Vec<_> = agents
+                .par_iter()
+                .map(|agent| {
+                    let mut agent_stats = Stats::default();
+                    let mut agent_cache = cache.clone();
+                    let result = plan_root_agent_independent(
+                        agent,
+                        map,
+                        config,
+                        solver,
+                        &mut agent_stats,
+                        map_fingerprint,
+                        &mut agent_cache,
+                    );
+                    (result, agent_stats, agent_cache)
+                })
+                .collect();
+
+            for (result, agent_stats, agent_cache) in results {
+                stats.merge(&agent_stats);
+                cache.merge(agent_cache);
+                let (path, low_level_f_min, mdd) = result?;
+
+                // Notice: path include start node.
+                total_cost += path.len() - 1;
+                paths.push(path);
+                low_level_f_min_agents.push(low_level_f_min);
+                mdds.push(mdd.map(Arc::new));
+            }
+        } else {
+            for agent in agents {
+                let cache_key = PathCacheKey::new(
+                    map_fingerprint,
+                    agent.id,
                     &HashSet::new(),
                     0,
-                    &paths,
-                    config.op_prioritize_conflicts,
-                    stats,
-                ) {
-                    SearchResult::Standard(Some((path, low_level_f_min))) => {
-                        (path, low_level_f_min, None)
-                    }
-                    SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
-                        (path, low_level_f_min, Some(mdd))
+                    config.sub_optimal.1,
+                );
+                let cached_entry = config
+                    .low_level_cache
+                    .then(|| cache.get(&cache_key))
+                    .flatten();
+
+                let (path, low_level_f_min, mdd) = if let Some(cached) = cached_entry {
+                    stats.low_level_cache_hits += 1;
+                    (cached.path.clone(), cached.f_min, cached.mdd.clone())
+                } else {
+                    stats.low_level_cache_misses += 1;
+
+                    let (path, low_level_f_min, mdd) = match solver {
+                        "cbs" | "hbcbs" => match a_star_search(
+                            map,
+                            agent,
+                            &HashSet::new(),
+                            0,
+                            config.op_prioritize_conflicts,
+                            config.low_level_mode.as_str(),
+                            config.low_level_weight,
+                            config.low_level_beam_width,
+                            stats,
+                        ) {
+                            SearchResult::Standard(Some((path, low_level_f_min))) => {
+                                (path, low_level_f_min, None)
+                            }
+                            SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
+                                (path, low_level_f_min, Some(mdd))
+                            }
+                            SearchResult::Partial { reached, h_remaining, .. } => {
+                                warn!("agent {} cannot reach its goal at all (unconstrained search fell short: reached {reached:?} with h_remaining {h_remaining}) -- instance is likely infeasible", agent.id);
+                                return None;
+                            }
+                            _ => return None,
+                        },
+                        "lbcbs" | "bcbs" | "ecbs" | "decbs" | "acbs" => match focal_a_star_search(
+                            map,
+                            agent,
+                            config.sub_optimal.1.unwrap(),
+                            &HashSet::new(),
+                            0,
+                            &paths,
+                            config.op_prioritize_conflicts,
+                            solver,
+                            config.focal_heuristic.as_str(),
+                            config.low_level_mode.as_str(),
+                            config.low_level_weight,
+                            config.low_level_beam_width,
+                            stats,
+                        ) {
+                            SearchResult::Standard(Some((path, low_level_f_min))) => {
+                                (path, low_level_f_min, None)
+                            }
+                            SearchResult::WithMDD(Some((path, low_level_f_min, mdd))) => {
+                                (path, low_level_f_min, Some(mdd))
+                            }
+                            SearchResult::Partial { reached, h_remaining, .. } => {
+                                warn!("agent {} cannot reach its goal at all (unconstrained search fell short: reached {reached:?} with h_remaining {h_remaining}) -- instance is likely infeasible", agent.id);
+                                return None;
+                            }
+                            _ => return None,
+                        },
+                        _ => unreachable!(),
+                    };
+
+                    if config.low_level_cache {
+                        cache.insert(
+                            cache_key,
+                            PathCacheEntry {
+                                path: path.clone(),
+                                f_min: low_level_f_min,
+                                mdd: mdd.clone(),
+                            },
+                        );
                     }
-                    _ => return None,
-                },
-                _ => unreachable!(),
-            };
 
-            // Notice: path include start node.
-            total_cost += path.len() - 1;
-            paths.insert(agent.id, path);
-            low_level_f_min_agents.push(low_level_f_min);
-            mdds.push(mdd);
+                    (path, low_level_f_min, mdd)
+                };
+
+                // Notice: path include start node.
+                total_cost += path.len() - 1;
+                paths.insert(agent.id, path);
+                low_level_f_min_agents.push(low_level_f_min);
+                mdds.push(mdd.map(Arc::new));
+            }
         }
 
         let mut start = HighLevelOpenNode {
@@ -257,205 +1405,196 @@ impl HighLevelOpenNode {
             cost: total_cost,
             low_level_f_min_agents,
             mdds,
+            h_cardinal: 0,
+            meta_agent_of: (0..agents.len()).collect(),
+            conflict_counts: vec![vec![0; agents.len()]; agents.len()],
         };
-        start.detect_conflicts(config.op_target_reasoning);
+        start.detect_conflicts(
+            map,
+            config.op_target_reasoning,
+            config.op_symmetry_reasoning,
+            config.op_mutex_reasoning,
+        );
         Some(start)
     }
 
-    pub(crate) fn detect_conflicts(&mut self, op_target_reasoning: bool) {
+    /// A hash of this node's constraint set, invariant to the branch order
+    /// that produced it: two nodes reached via different conflict-split
+    /// histories but carrying the same `(constraints, path_length_constraints)`
+    /// per agent hash identically. Each agent's `HashSet<Constraint>` is
+    /// sorted before hashing since `Constraint` derives `Ord` but a
+    /// `HashSet`'s iteration order isn't otherwise deterministic. Used by
+    /// `config.op_duplicate_detection` to prune a node whose signature was
+    /// already expanded at an equal-or-lower `cost` instead of re-expanding
+    /// an equivalent subtree; deliberately ignores `node_id`, `paths`,
+    /// `conflicts`, and `mdds`, which can differ across equivalent nodes.
+    pub(crate) fn canonical_signature(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (agent_constraints, &path_length) in
+            self.constraints.iter().zip(&self.path_length_constraints)
+        {
+            let mut sorted: Vec<&Constraint> = agent_constraints.iter().collect();
+            sorted.sort();
+            sorted.hash(&mut hasher);
+            path_length.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    pub(crate) fn detect_conflicts(
+        &mut self,
+        map: &Map,
+        op_target_reasoning: bool,
+        op_symmetry_reasoning: bool,
+        op_mutex_reasoning: bool,
+    ) {
         let mut conflicts = Vec::new();
 
         // Compare paths of each pair of agents to find conflicts
         for i in 0..self.agents.len() {
             for j in (i + 1)..self.agents.len() {
-                let path1 = &self.paths[i];
-                let path2 = &self.paths[j];
-                let max_length = path1.len().max(path2.len());
-
-                let mdd1 = &self.mdds[i];
-                let mdd2 = &self.mdds[j];
-
-                // Start from 1 since:
-                // 1. Initial positions (step 0) can't have vertex conflicts (agents start at different positions).
-                // 2. Edge conflicts need previous step, so can only start from step 1.
-                for step in 1..max_length {
-                    let pos1 = if step < path1.len() {
-                        path1[step]
-                    } else {
-                        *path1.last().unwrap()
-                    };
-                    let pos2 = if step < path2.len() {
-                        path2[step]
-                    } else {
-                        *path2.last().unwrap()
-                    };
-
-                    // Check for Vertex Conflict
-                    if pos1 == pos2 {
-                        // Check for cardinal type
-                        let cardinal_type = match (&mdd1, &mdd2) {
-                            (Some(mdd1), Some(mdd2)) => {
-                                let singlenton1 = is_singleton_at_position(mdd1, step, pos1);
-                                let singlenton2 = is_singleton_at_position(mdd2, step, pos2);
-                                if singlenton1 && singlenton2 {
-                                    CardinalType::Cardinal
-                                } else if singlenton1 || singlenton2 {
-                                    CardinalType::SemiCardinal
-                                } else {
-                                    CardinalType::NonCardinal
-                                }
-                            }
-                            (Some(mdd), None) | (None, Some(mdd)) => {
-                                let singlenton = is_singleton_at_position(mdd, step, pos1);
-                                if singlenton {
-                                    CardinalType::SemiCardinal
-                                } else {
-                                    CardinalType::NonCardinal
-                                }
-                            }
-                            _ => CardinalType::Unknown,
-                        };
-
-                        // Check for target conflicts first
-                        if step >= path1.len() - 1 && pos1 == self.agents[i].goal {
-                            // Agent i is at its target and agent j is interfering
-                            conflicts.push(Conflict {
-                                agent_1: i,
-                                agent_2: j,
-                                conflict_type: ConflictType::Target {
-                                    position: pos1,
-                                    time_step: step,
-                                },
-                                cardinal_type: if op_target_reasoning {
-                                    cardinal_type
-                                } else {
-                                    CardinalType::Unknown
-                                },
-                            });
-                        } else if step >= path2.len() - 1 && pos2 == self.agents[j].goal {
-                            // Agent j is at its target and agent i is interfering
-                            conflicts.push(Conflict {
-                                agent_1: j,
-                                agent_2: i,
-                                conflict_type: ConflictType::Target {
-                                    position: pos2,
-                                    time_step: step,
-                                },
-                                cardinal_type: if op_target_reasoning {
-                                    cardinal_type
-                                } else {
-                                    CardinalType::Unknown
-                                },
-                            });
-                        } else {
-                            // Regular vertex conflict
-                            conflicts.push(Conflict {
-                                agent_1: i,
-                                agent_2: j,
-                                conflict_type: ConflictType::Vertex {
-                                    position: pos1,
-                                    time_step: step,
-                                },
-                                cardinal_type,
-                            });
-                        }
-                    }
-
-                    // Check for Edge Conflict.
-                    if step >= path1.len() || step >= path2.len() {
-                        continue;
-                    }
-
-                    let prev_pos1 = path1[step - 1];
-                    let prev_pos2 = path2[step - 1];
-
-                    if prev_pos1 == pos2 && prev_pos2 == pos1 {
-                        let cardinal_type = match (&mdd1, &mdd2) {
-                            (Some(mdd1), Some(mdd2)) => {
-                                // For edge conflicts, need singletons at both t-1 and t.
-                                let agent1_singleton =
-                                    is_singleton_at_position(mdd1, step - 1, prev_pos1)
-                                        && is_singleton_at_position(mdd1, step, pos1);
-                                let agent2_singleton =
-                                    is_singleton_at_position(mdd2, step - 1, prev_pos2)
-                                        && is_singleton_at_position(mdd2, step, pos2);
-
-                                if agent1_singleton && agent2_singleton {
-                                    CardinalType::Cardinal
-                                } else if agent1_singleton || agent2_singleton {
-                                    CardinalType::SemiCardinal
-                                } else {
-                                    CardinalType::NonCardinal
-                                }
-                            }
-                            (Some(mdd), None) | (None, Some(mdd)) => {
-                                let singlenton = is_singleton_at_position(mdd, step - 1, prev_pos1)
-                                    && is_singleton_at_position(mdd, step, pos1);
-                                if singlenton {
-                                    CardinalType::SemiCardinal
-                                } else {
-                                    CardinalType::NonCardinal
-                                }
-                            }
-                            _ => CardinalType::Unknown,
-                        };
-
-                        conflicts.push(Conflict {
-                            agent_1: i,
-                            agent_2: j,
-                            conflict_type: ConflictType::Edge {
-                                from_position: prev_pos1,
-                                to_position: pos1,
-                                to_time_step: step,
-                            },
-                            cardinal_type,
-                        });
-                    }
+                // MA-CBS: a merged meta-agent is one entity; its members
+                // only stay conflict-free because they're planned jointly,
+                // so they're never reported as conflicting with each other.
+                if self.meta_agent_of[i] == self.meta_agent_of[j] {
+                    continue;
+                }
+                let before = conflicts.len();
+                detect_conflicts_between(
+                    &self.agents,
+                    &self.paths,
+                    &self.mdds,
+                    map,
+                    i,
+                    j,
+                    op_target_reasoning,
+                    op_symmetry_reasoning,
+                    op_mutex_reasoning,
+                    &mut conflicts,
+                );
+                let added = conflicts.len() - before;
+                if added > 0 {
+                    self.conflict_counts[i][j] += added;
+                    self.conflict_counts[j][i] += added;
                 }
             }
         }
 
         debug!("Detect conflicts: {:?}", conflicts);
+        self.h_cardinal = cardinal_conflict_heuristic(&conflicts, self.agents.len());
         self.conflicts = conflicts;
     }
 
-    pub(crate) fn update_constraint(
+    /// Incrementally refreshes conflicts after `agent`'s path alone was
+    /// replanned (the `update_constraint` case): drops every `Conflict`
+    /// touching `agent` and rescans only `agent` against the other n-1
+    /// agents, reusing the same vertex/edge/target detection as
+    /// `detect_conflicts`. Turns per-node conflict detection from quadratic
+    /// to linear in the agent count; the root node still needs the full
+    /// `detect_conflicts` since every agent's path is fresh there.
+    ///
+    /// Every pair touching `agent` is fully recomputed via
+    /// `detect_conflicts_between`, not just filtered from the parent's list:
+    /// a path that got shorter or longer can flip whether `other` now has
+    /// (or no longer has) a target conflict at its own goal against `agent`,
+    /// and that's a property of the *other* agent's path, not `agent`'s, so
+    /// it can't be inferred from the dropped conflicts alone.
+    pub(crate) fn recompute_conflicts_for(
+        &mut self,
+        map: &Map,
+        agent: usize,
+        op_target_reasoning: bool,
+        op_symmetry_reasoning: bool,
+        op_mutex_reasoning: bool,
+    ) {
+        self.conflicts
+            .retain(|conflict| conflict.agent_1 != agent && conflict.agent_2 != agent);
+
+        for other in 0..self.agents.len() {
+            if other == agent || self.meta_agent_of[other] == self.meta_agent_of[agent] {
+                continue;
+            }
+            let (i, j) = if agent < other {
+                (agent, other)
+            } else {
+                (other, agent)
+            };
+            let before = self.conflicts.len();
+            detect_conflicts_between(
+                &self.agents,
+                &self.paths,
+                &self.mdds,
+                map,
+                i,
+                j,
+                op_target_reasoning,
+                op_symmetry_reasoning,
+                op_mutex_reasoning,
+                &mut self.conflicts,
+            );
+            let added = self.conflicts.len() - before;
+            if added > 0 {
+                self.conflict_counts[i][j] += added;
+                self.conflict_counts[j][i] += added;
+            }
+        }
+
+        debug!(
+            "Recomputed conflicts for agent {agent}: {:?}",
+            self.conflicts
+        );
+        self.h_cardinal = cardinal_conflict_heuristic(&self.conflicts, self.agents.len());
+    }
+
+    /// Re-runs the low-level search for a single `agent` under `constraints`,
+    /// going through `cache` first. Shared by `update_constraint`'s one
+    /// ordinarily-replanned agent and, under disjoint splitting, every other
+    /// agent whose path is invalidated by a newly added negative constraint.
+    #[allow(clippy::too_many_arguments)]
+    fn replan_agent(
         &self,
-        conflict: &Conflict,
-        resolve_first: bool,
+        agent: usize,
         map: &Map,
         config: &Config,
-        new_node_id: u64,
         stats: &mut Stats,
-    ) -> Option<HighLevelOpenNode> {
-        let mut new_constraints = self.constraints.clone();
-        let mut new_paths = self.paths.clone();
-        let mut new_low_level_f_min_agents = self.low_level_f_min_agents.clone();
-        let mut new_path_length_constraints = self.path_length_constraints.clone();
-        let mut new_mdds = self.mdds.clone();
-
-        let agent_to_update = if resolve_first {
-            conflict.agent_1
-        } else {
-            conflict.agent_2
-        };
-
-        convert_conflict_to_constraint(
-            conflict,
-            resolve_first,
-            config.op_target_reasoning,
-            agent_to_update,
-            &mut new_constraints,
-            &mut new_path_length_constraints,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+        constraints: &HashSet<Constraint>,
+        path_length_constraint: usize,
+    ) -> Option<(Path, usize, Option<Mdd>)> {
+        let cache_key = PathCacheKey::new(
+            map_fingerprint,
+            agent,
+            constraints,
+            path_length_constraint,
+            config.sub_optimal.1,
         );
+        let cached_entry = config
+            .low_level_cache
+            .then(|| cache.get(&cache_key))
+            .flatten();
+
+        if let Some(cached) = cached_entry {
+            stats.low_level_cache_hits += 1;
+            return Some((cached.path.clone(), cached.f_min, cached.mdd.clone()));
+        }
+        stats.low_level_cache_misses += 1;
+
+        // Used only to build the low-level search's reservation table; the
+        // caller's canonical `constraints` (and the cache key above) are
+        // left untouched. See `prune_dead_constraints`.
+        let pruned_constraints = prune_dead_constraints(constraints, &self.agents[agent]);
 
         let (new_path, new_low_level_f_min, new_mdd) = match config.solver.as_str() {
             "cbs" | "hbcbs" => match a_star_search(
                 map,
-                &self.agents[agent_to_update],
-                &new_constraints[agent_to_update],
-                new_path_length_constraints[agent_to_update],
+                &self.agents[agent],
+                &pruned_constraints,
+                path_length_constraint,
                 config.op_prioritize_conflicts,
+                config.low_level_mode.as_str(),
+                config.low_level_weight,
+                config.low_level_beam_width,
                 stats,
             ) {
                 SearchResult::Standard(Some((new_path, new_low_level_f_min))) => {
@@ -464,17 +1603,25 @@ impl HighLevelOpenNode {
                 SearchResult::WithMDD(Some((new_path, new_low_level_f_min, new_mdd))) => {
                     (new_path, new_low_level_f_min, Some(new_mdd))
                 }
+                SearchResult::Partial { reached, h_remaining, .. } => {
+                    debug!("agent {agent} fell short of its goal under the current constraint set (reached {reached:?} with h_remaining {h_remaining}): dead end for this branch");
+                    return None;
+                }
                 _ => return None,
             },
-            "lbcbs" | "bcbs" | "ecbs" => match focal_a_star_search(
+            "lbcbs" | "bcbs" | "ecbs" | "decbs" | "acbs" => match focal_a_star_search(
                 map,
-                &self.agents[agent_to_update],
-                Some(0),
+                &self.agents[agent],
                 config.sub_optimal.1.unwrap(),
-                &new_constraints[agent_to_update],
-                new_path_length_constraints[agent_to_update],
+                &pruned_constraints,
+                path_length_constraint,
                 &self.paths,
                 config.op_prioritize_conflicts,
+                config.solver.as_str(),
+                config.focal_heuristic.as_str(),
+                config.low_level_mode.as_str(),
+                config.low_level_weight,
+                config.low_level_beam_width,
                 stats,
             ) {
                 SearchResult::Standard(Some((new_path, new_low_level_f_min))) => {
@@ -483,52 +1630,573 @@ impl HighLevelOpenNode {
                 SearchResult::WithMDD(Some((new_path, new_low_level_f_min, new_mdd))) => {
                     (new_path, new_low_level_f_min, Some(new_mdd))
                 }
+                SearchResult::Partial { reached, h_remaining, .. } => {
+                    debug!("agent {agent} fell short of its goal under the current constraint set (reached {reached:?} with h_remaining {h_remaining}): dead end for this branch");
+                    return None;
+                }
                 _ => return None,
             },
-            "decbs" => match focal_a_star_search(
+            _ => unreachable!(),
+        };
+
+        if config.low_level_cache {
+            cache.insert(
+                cache_key,
+                PathCacheEntry {
+                    path: new_path.clone(),
+                    f_min: new_low_level_f_min,
+                    mdd: new_mdd.clone(),
+                },
+            );
+        }
+
+        Some((new_path, new_low_level_f_min, new_mdd))
+    }
+
+    /// MA-CBS's low-level counterpart: replans every member of a merged
+    /// meta-agent `group`. A true coupled search over the group's joint
+    /// state space is a much larger undertaking than one backlog item
+    /// warrants, so this approximates it by replanning members one at a
+    /// time via `replan_agent`, carrying each already-planned member's path
+    /// forward as extra negative vertex/edge reservation constraints for
+    /// the rest of the group. This can miss solutions a real joint search
+    /// would find when the group is tightly interlocked, but it keeps every
+    /// member internally conflict-free against the rest of the group and
+    /// reuses the existing single-agent search unchanged.
+    #[allow(clippy::too_many_arguments)]
+    fn replan_meta_agent_group(
+        &self,
+        group: &[usize],
+        map: &Map,
+        config: &Config,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+        new_constraints: &[HashSet<Constraint>],
+        new_path_length_constraints: &[usize],
+    ) -> Option<Vec<(usize, Path, usize, Option<Mdd>)>> {
+        let mut planned = Vec::with_capacity(group.len());
+        let mut reserved: HashSet<Constraint> = HashSet::new();
+
+        for &agent in group {
+            let mut constraints = new_constraints[agent].clone();
+            constraints.extend(reserved.iter().cloned());
+
+            let (path, f_min, mdd) = self.replan_agent(
+                agent,
                 map,
-                &self.agents[agent_to_update],
-                None,
-                config.sub_optimal.1.unwrap(),
-                &new_constraints[agent_to_update],
-                new_path_length_constraints[agent_to_update],
-                &self.paths,
-                config.op_prioritize_conflicts,
+                config,
                 stats,
-            ) {
-                SearchResult::Standard(Some((new_path, new_low_level_f_min))) => {
-                    (new_path, new_low_level_f_min, None)
+                map_fingerprint,
+                cache,
+                &constraints,
+                new_path_length_constraints[agent],
+            )?;
+
+            let last_step = path.len() - 1;
+            for (t, &position) in path.iter().enumerate() {
+                reserved.insert(Constraint::Vertex {
+                    position,
+                    time_step: t,
+                    is_permanent: t == last_step,
+                    kind: ConstraintKind::Negative,
+                });
+                if t > 0 {
+                    let from_position = path[t - 1];
+                    reserved.insert(Constraint::Edge {
+                        from_position,
+                        to_position: position,
+                        to_time_step: t,
+                        kind: ConstraintKind::Negative,
+                    });
+                    // Also reserve the reverse edge so a later group member
+                    // can't swap places with this one head-on.
+                    reserved.insert(Constraint::Edge {
+                        from_position: position,
+                        to_position: from_position,
+                        to_time_step: t,
+                        kind: ConstraintKind::Negative,
+                    });
                 }
-                SearchResult::WithMDD(Some((new_path, new_low_level_f_min, new_mdd))) => {
-                    (new_path, new_low_level_f_min, Some(new_mdd))
+            }
+
+            planned.push((agent, path, f_min, mdd));
+        }
+
+        Some(planned)
+    }
+
+    /// WDG edge weight for one conflicting agent pair: the extra cost
+    /// incurred by solving `i`/`j` optimally together (via the same
+    /// prioritized joint replan `merge_and_replan` uses) over what they
+    /// currently cost separately in this node, or 0 if joint replanning
+    /// can't improve on -- or fails to beat -- their current paths.
+    /// Memoized in `wdg_cache` by `PairWeightKey`, since the same pair
+    /// under the same constraints recurs across sibling nodes.
+    #[allow(clippy::too_many_arguments)]
+    fn pair_weight(
+        &self,
+        i: usize,
+        j: usize,
+        map: &Map,
+        config: &Config,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+        wdg_cache: &mut PairWeightCache,
+    ) -> usize {
+        let key = PairWeightKey::new(
+            map_fingerprint,
+            i,
+            j,
+            &self.constraints[i],
+            &self.constraints[j],
+            self.path_length_constraints[i],
+            self.path_length_constraints[j],
+        );
+        if let Some(cached) = wdg_cache.get(&key) {
+            return cached;
+        }
+
+        let current_cost = (self.paths[i].len() - 1) + (self.paths[j].len() - 1);
+        let weight = self
+            .replan_meta_agent_group(
+                &[i, j],
+                map,
+                config,
+                stats,
+                map_fingerprint,
+                cache,
+                &self.constraints,
+                &self.path_length_constraints,
+            )
+            .map(|planned| {
+                let joint_cost: usize = planned.iter().map(|(_, path, ..)| path.len() - 1).sum();
+                joint_cost.saturating_sub(current_cost)
+            })
+            .unwrap_or(0);
+
+        wdg_cache.insert(key, weight);
+        weight
+    }
+
+    /// Weighted variant of `cardinal_conflict_heuristic`: builds one edge
+    /// per distinct conflicting agent pair (any conflict, not just cardinal
+    /// ones -- a non-cardinal pair that turns out to need no extra joint
+    /// cost just contributes weight 0 and drops out), weighted by
+    /// `pair_weight`, and solves the weighted minimum vertex cover over
+    /// them. Strictly dominates the unweighted CG heuristic (same graph,
+    /// tighter-or-equal edge weights) at the cost of a joint replan per
+    /// distinct uncached pair, so it's opt-in via `config.op_wdg_heuristic`
+    /// rather than always on; see `apply_wdg_heuristic`.
+    #[allow(clippy::too_many_arguments)]
+    fn weighted_dependency_heuristic(
+        &self,
+        map: &Map,
+        config: &Config,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+        wdg_cache: &mut PairWeightCache,
+    ) -> usize {
+        let mut pairs: Vec<(usize, usize)> = self
+            .conflicts
+            .iter()
+            .map(|c| (c.agent_1.min(c.agent_2), c.agent_1.max(c.agent_2)))
+            .filter(|&(i, j)| self.meta_agent_of[i] != self.meta_agent_of[j])
+            .collect();
+        pairs.sort_unstable();
+        pairs.dedup();
+
+        let edges: Vec<(usize, usize, usize)> = pairs
+            .into_iter()
+            .filter_map(|(i, j)| {
+                let weight =
+                    self.pair_weight(i, j, map, config, stats, map_fingerprint, cache, wdg_cache);
+                (weight > 0).then_some((i, j, weight))
+            })
+            .collect();
+
+        weighted_vertex_cover(&edges)
+    }
+
+    /// Overrides the unweighted `h_cardinal` that `detect_conflicts`/
+    /// `recompute_conflicts_for` already set with the WDG heuristic (see
+    /// `weighted_dependency_heuristic`). Solvers that support
+    /// `config.op_wdg_heuristic` call this right after constructing a root
+    /// or replanned node, once `self.conflicts` is up to date.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn apply_wdg_heuristic(
+        &mut self,
+        map: &Map,
+        config: &Config,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+        wdg_cache: &mut PairWeightCache,
+    ) {
+        self.h_cardinal = self.weighted_dependency_heuristic(
+            map,
+            config,
+            stats,
+            map_fingerprint,
+            cache,
+            wdg_cache,
+        );
+    }
+
+    /// MA-CBS: fuses the meta-agent groups containing `conflict.agent_1` and
+    /// `conflict.agent_2` into one (leader = the smaller of the two current
+    /// leader indices) and replans the whole new group jointly via
+    /// `replan_meta_agent_group`, in place of the usual two-way split.
+    /// `update_constraint` calls this once `conflict_counts` for the pair
+    /// exceeds `config.op_merge_bound`.
+    #[allow(clippy::too_many_arguments)]
+    fn merge_and_replan(
+        &self,
+        conflict: &Conflict,
+        map: &Map,
+        config: &Config,
+        new_node_id: u64,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+    ) -> Option<HighLevelOpenNode> {
+        let leader_1 = self.meta_agent_of[conflict.agent_1];
+        let leader_2 = self.meta_agent_of[conflict.agent_2];
+        let new_leader = leader_1.min(leader_2);
+
+        let mut meta_agent_of = self.meta_agent_of.clone();
+        for entry in meta_agent_of.iter_mut() {
+            if *entry == leader_1 || *entry == leader_2 {
+                *entry = new_leader;
+            }
+        }
+
+        let group: Vec<usize> = (0..self.agents.len())
+            .filter(|&a| meta_agent_of[a] == new_leader)
+            .collect();
+
+        let planned = self.replan_meta_agent_group(
+            &group,
+            map,
+            config,
+            stats,
+            map_fingerprint,
+            cache,
+            &self.constraints,
+            &self.path_length_constraints,
+        )?;
+
+        let mut new_paths = self.paths.clone();
+        let mut new_low_level_f_min_agents = self.low_level_f_min_agents.clone();
+        let mut new_mdds = self.mdds.clone();
+        let mut new_cost = self.cost;
+
+        for (agent, path, f_min, mdd) in planned {
+            // Notice: path include start node, calculation here counterbalances each other.
+            new_cost = new_cost - new_paths[agent].len() + path.len();
+            new_paths[agent] = path;
+            new_low_level_f_min_agents[agent] = f_min;
+            new_mdds[agent] = mdd.map(Arc::new);
+        }
+
+        let mut new_node = HighLevelOpenNode {
+            node_id: new_node_id,
+            agents: self.agents.clone(),
+            constraints: self.constraints.clone(),
+            path_length_constraints: self.path_length_constraints.clone(),
+            conflicts: self.conflicts.clone(),
+            paths: new_paths,
+            cost: new_cost,
+            low_level_f_min_agents: new_low_level_f_min_agents,
+            mdds: new_mdds,
+            h_cardinal: 0,
+            meta_agent_of,
+            conflict_counts: self.conflict_counts.clone(),
+        };
+
+        for &agent in &group {
+            new_node.recompute_conflicts_for(
+                map,
+                agent,
+                config.op_target_reasoning,
+                config.op_symmetry_reasoning,
+                config.op_mutex_reasoning,
+            );
+        }
+
+        Some(new_node)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn update_constraint(
+        &self,
+        conflict: &Conflict,
+        resolve_first: bool,
+        map: &Map,
+        config: &Config,
+        new_node_id: u64,
+        stats: &mut Stats,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+    ) -> Option<HighLevelOpenNode> {
+        // MA-CBS: once a pair has conflicted more than `op_merge_bound`
+        // times across this branch of the CT, fuse them into one
+        // meta-agent and replan jointly instead of splitting again. Only
+        // the `resolve_first` branch produces the merged node; the other
+        // branch returns `None` so the pair is replaced by one meta-agent
+        // child rather than split into two as usual.
+        if let Some(bound) = config.op_merge_bound {
+            if self.meta_agent_of[conflict.agent_1] != self.meta_agent_of[conflict.agent_2]
+                && self.conflict_counts[conflict.agent_1][conflict.agent_2] > bound
+            {
+                if !resolve_first {
+                    return None;
                 }
-                _ => return None,
-            },
-            _ => unreachable!(),
+                return self.merge_and_replan(
+                    conflict,
+                    map,
+                    config,
+                    new_node_id,
+                    stats,
+                    map_fingerprint,
+                    cache,
+                );
+            }
+        }
+
+        let mut new_constraints = self.constraints.clone();
+        let mut new_paths = self.paths.clone();
+        let mut new_low_level_f_min_agents = self.low_level_f_min_agents.clone();
+        let mut new_path_length_constraints = self.path_length_constraints.clone();
+        let mut new_mdds = self.mdds.clone();
+
+        // Disjoint splitting keeps both children on the same agent: a
+        // negative child (forbid) and a positive child (require + forbid
+        // every other agent), so the two subtrees' solution sets are
+        // disjoint rather than merely partitioned by which of the two
+        // conflicting agents gets constrained. It only applies to vertex and
+        // edge conflicts; target conflicts keep the ordinary two-agent split.
+        let is_disjoint_splittable = matches!(
+            conflict.conflict_type,
+            ConflictType::Vertex { .. } | ConflictType::Edge { .. }
+        );
+        let disjoint = config.op_disjoint_splitting && is_disjoint_splittable;
+
+        let agent_to_update = if disjoint || resolve_first {
+            conflict.agent_1
+        } else {
+            conflict.agent_2
         };
 
-        debug!(
-                "Update agent {agent_to_update:?} with path {new_path:?} for conflict {conflict:?}, new f min {new_low_level_f_min:?}"
+        let mut agents_to_replan = vec![agent_to_update];
+
+        if let ConflictType::Rectangle {
+            rs,
+            rg,
+            time_step,
+            agent_order,
+        } = conflict.conflict_type
+        {
+            // Barrier split: each child bars one side of the rectangle (the
+            // row band for `agent_order.0`, the column band for
+            // `agent_order.1`) across the whole span instead of a single
+            // cell, collapsing the usual chain of single-cell splits.
+            let barred_agent = if resolve_first {
+                agent_order.0
+            } else {
+                agent_order.1
+            };
+            agents_to_replan = vec![barred_agent];
+            for constraint in
+                rectangle_barrier_constraints(rs, rg, time_step, barred_agent == agent_order.0)
+            {
+                new_constraints[barred_agent].insert(constraint);
+            }
+        } else if let ConflictType::Corridor {
+            entry,
+            exit,
+            time_step,
+        } = conflict.conflict_type
+        {
+            // Barrier split: each child bars one agent from the entire
+            // mirrored corridor span instead of the single swapped cell.
+            let barred_agent = if resolve_first {
+                conflict.agent_1
+            } else {
+                conflict.agent_2
+            };
+            agents_to_replan = vec![barred_agent];
+            for constraint in corridor_barrier_constraints(entry, exit, time_step) {
+                new_constraints[barred_agent].insert(constraint);
+            }
+        } else if disjoint && !resolve_first {
+            match conflict.conflict_type {
+                ConflictType::Vertex {
+                    position,
+                    time_step,
+                } => {
+                    new_constraints[agent_to_update].insert(Constraint::Vertex {
+                        position,
+                        time_step,
+                        is_permanent: false,
+                        kind: ConstraintKind::Positive,
+                    });
+
+                    for other in 0..new_constraints.len() {
+                        if other == agent_to_update {
+                            continue;
+                        }
+                        new_constraints[other].insert(Constraint::Vertex {
+                            position,
+                            time_step,
+                            is_permanent: false,
+                            kind: ConstraintKind::Negative,
+                        });
+                        // Only agents whose current path actually occupies
+                        // the now-forbidden cell at that timestep need
+                        // replanning.
+                        let occupies = new_paths[other]
+                            .get(time_step)
+                            .or_else(|| new_paths[other].last())
+                            == Some(&position);
+                        if occupies {
+                            agents_to_replan.push(other);
+                        }
+                    }
+                }
+                ConflictType::Edge {
+                    from_position,
+                    to_position,
+                    to_time_step,
+                } => {
+                    new_constraints[agent_to_update].insert(Constraint::Edge {
+                        from_position,
+                        to_position,
+                        to_time_step,
+                        kind: ConstraintKind::Positive,
+                    });
+
+                    for other in 0..new_constraints.len() {
+                        if other == agent_to_update {
+                            continue;
+                        }
+                        new_constraints[other].insert(Constraint::Edge {
+                            from_position,
+                            to_position,
+                            to_time_step,
+                            kind: ConstraintKind::Negative,
+                        });
+                        // Only agents whose current path actually makes the
+                        // now-forbidden move need replanning.
+                        let makes_move = to_time_step > 0
+                            && new_paths[other].get(to_time_step) == Some(&to_position)
+                            && new_paths[other].get(to_time_step - 1) == Some(&from_position);
+                        if makes_move {
+                            agents_to_replan.push(other);
+                        }
+                    }
+                }
+                _ => unreachable!("disjoint is only set for vertex/edge conflicts"),
+            }
+        } else {
+            convert_conflict_to_constraint(
+                conflict,
+                resolve_first,
+                config.op_target_reasoning,
+                agent_to_update,
+                &mut new_constraints,
+                &mut new_path_length_constraints,
             );
+        }
+
+        let mut new_cost = self.cost;
+        // An agent in `agents_to_replan` may already belong to a
+        // previously-merged meta-agent group (from an earlier MA-CBS
+        // merge); such a group must be replanned jointly via
+        // `replan_meta_agent_group`; otherwise the members would each be
+        // replanned independently and could reintroduce conflicts inside
+        // the group. `handled_leaders` dedupes a group reached through
+        // more than one `agents_to_replan` entry.
+        let mut handled_leaders: HashSet<usize> = HashSet::new();
+        let mut replanned_agents: Vec<usize> = Vec::new();
+        for &agent in &agents_to_replan {
+            let leader = self.meta_agent_of[agent];
+            let group: Vec<usize> = (0..self.agents.len())
+                .filter(|&a| self.meta_agent_of[a] == leader)
+                .collect();
+
+            if group.len() > 1 {
+                if !handled_leaders.insert(leader) {
+                    continue;
+                }
+                let planned = self.replan_meta_agent_group(
+                    &group,
+                    map,
+                    config,
+                    stats,
+                    map_fingerprint,
+                    cache,
+                    &new_constraints,
+                    &new_path_length_constraints,
+                )?;
+                for (member, new_path, new_low_level_f_min, new_mdd) in planned {
+                    // Notice: actually path include start point, calculation here counterbalance each other.
+                    new_cost = new_cost - new_paths[member].len() + new_path.len();
+                    new_paths[member] = new_path;
+                    new_low_level_f_min_agents[member] = new_low_level_f_min;
+                    new_mdds[member] = new_mdd.map(Arc::new);
+                    replanned_agents.push(member);
+                }
+            } else {
+                let (new_path, new_low_level_f_min, new_mdd) = self.replan_agent(
+                    agent,
+                    map,
+                    config,
+                    stats,
+                    map_fingerprint,
+                    cache,
+                    &new_constraints[agent],
+                    new_path_length_constraints[agent],
+                )?;
+
+                debug!(
+                    "Update agent {agent:?} with path {new_path:?} for conflict {conflict:?}, new f min {new_low_level_f_min:?}"
+                );
 
-        // Notice: actually path include start point, calculation here counterbalance each other.
-        let new_cost = self.cost - new_paths[agent_to_update].len() + new_path.len();
-        new_paths[agent_to_update] = new_path;
-        new_low_level_f_min_agents[agent_to_update] = new_low_level_f_min;
-        new_mdds[agent_to_update] = new_mdd;
+                // Notice: actually path include start point, calculation here counterbalance each other.
+                new_cost = new_cost - new_paths[agent].len() + new_path.len();
+                new_paths[agent] = new_path;
+                new_low_level_f_min_agents[agent] = new_low_level_f_min;
+                new_mdds[agent] = new_mdd.map(Arc::new);
+                replanned_agents.push(agent);
+            }
+        }
 
         let mut new_node = HighLevelOpenNode {
             node_id: new_node_id,
             agents: self.agents.clone(),
             constraints: new_constraints,
             path_length_constraints: new_path_length_constraints,
-            conflicts: Vec::new(),
+            conflicts: self.conflicts.clone(),
             paths: new_paths,
             cost: new_cost,
             low_level_f_min_agents: new_low_level_f_min_agents,
             mdds: new_mdds,
+            h_cardinal: 0,
+            meta_agent_of: self.meta_agent_of.clone(),
+            conflict_counts: self.conflict_counts.clone(),
         };
-        new_node.detect_conflicts(config.op_target_reasoning);
+        for &agent in &replanned_agents {
+            new_node.recompute_conflicts_for(
+                map,
+                agent,
+                config.op_target_reasoning,
+                config.op_symmetry_reasoning,
+                config.op_mutex_reasoning,
+            );
+        }
 
         Some(new_node)
     }
@@ -543,14 +2211,41 @@ impl HighLevelOpenNode {
         bypass_node.node_id = new_node.node_id;
         bypass_node.paths[agent_id] = new_node.paths[agent_id].clone();
         bypass_node.conflicts = new_node.conflicts.clone();
+        bypass_node.h_cardinal = new_node.h_cardinal;
         bypass_node.mdds[agent_id] = new_node.mdds[agent_id].clone();
+        bypass_node.meta_agent_of = new_node.meta_agent_of.clone();
+        bypass_node.conflict_counts = new_node.conflict_counts.clone();
         // Notice: for focal search, bypass node cost might not be equal.
         bypass_node.cost = new_node.cost;
         bypass_node.low_level_f_min_agents[agent_id] = new_node.low_level_f_min_agents[agent_id];
         bypass_node
     }
 
-    pub(crate) fn to_focal_node(&self) -> HighLevelFocalNode {
+    /// Computes the weighted focal score (`config.focal_weights`) that
+    /// orders the high-level focal list: a linear combination of conflict
+    /// count, total sum-of-delays over each agent's low-level f-min path,
+    /// and number of constrained agents. Doesn't affect `cost`/the
+    /// admissible `open` ordering.
+    fn focal_score(&self, config: &Config) -> f64 {
+        let conflicts = self.conflicts.len() as f64;
+        let delay: f64 = self
+            .paths
+            .iter()
+            .zip(&self.low_level_f_min_agents)
+            .map(|(path, &f_min)| (path.len() - 1).saturating_sub(f_min) as f64)
+            .sum();
+        let constrained_agents = self
+            .constraints
+            .iter()
+            .filter(|agent_constraints| !agent_constraints.is_empty())
+            .count() as f64;
+
+        config.focal_weights.conflicts * conflicts
+            + config.focal_weights.delay * delay
+            + config.focal_weights.constrained_agents * constrained_agents
+    }
+
+    pub(crate) fn to_focal_node(&self, config: &Config) -> HighLevelFocalNode {
         HighLevelFocalNode {
             node_id: self.node_id,
             agents: self.agents.clone(),
@@ -558,15 +2253,18 @@ impl HighLevelOpenNode {
             path_length_constraints: self.path_length_constraints.clone(),
             conflicts: self.conflicts.clone(),
             paths: self.paths.clone(),
-            focal: self.conflicts.len(),
+            focal: self.focal_score(config),
             cost: self.cost,
             low_level_f_min_agents: self.low_level_f_min_agents.clone(),
             mdds: self.mdds.clone(),
+            h_cardinal: self.h_cardinal,
+            meta_agent_of: self.meta_agent_of.clone(),
+            conflict_counts: self.conflict_counts.clone(),
         }
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) struct HighLevelFocalNode {
     pub(crate) node_id: u64,
     pub(crate) agents: Vec<Agent>,
@@ -574,16 +2272,24 @@ pub(crate) struct HighLevelFocalNode {
     pub(crate) path_length_constraints: Vec<usize>,
     pub(crate) conflicts: Vec<Conflict>,
     pub(crate) paths: Vec<Path>, // Maps agent IDs to their paths
-    pub(crate) focal: usize,     // Focal cost for all paths under current constraints
+    pub(crate) focal: f64,       // Weighted focal score for all paths under current constraints
     pub(crate) cost: usize,      // Open cost for all paths under current constraints
     pub(crate) low_level_f_min_agents: Vec<usize>, // Agent's f_min, used for ECBS
-    pub(crate) mdds: Vec<Option<Mdd>>,
+    pub(crate) mdds: Vec<Option<Arc<Mdd>>>,
+    pub(crate) h_cardinal: usize,
+    pub(crate) meta_agent_of: Vec<usize>,
+    pub(crate) conflict_counts: Vec<Vec<usize>>,
 }
 
+// `focal` is an `f64` computed from `config.validate()`-checked finite,
+// non-negative weights, so it's never NaN; `Eq`/`Ord` are sound even though
+// `f64` doesn't implement them itself.
+impl Eq for HighLevelFocalNode {}
+
 impl Ord for HighLevelFocalNode {
     fn cmp(&self, other: &Self) -> Ordering {
         self.focal
-            .cmp(&other.focal)
+            .total_cmp(&other.focal)
             .then_with(|| self.cost.cmp(&other.cost))
             .then_with(|| self.conflicts.cmp(&other.conflicts))
             .then_with(|| self.paths.cmp(&other.paths))
@@ -608,6 +2314,9 @@ impl HighLevelFocalNode {
             cost: self.cost,
             low_level_f_min_agents: self.low_level_f_min_agents.clone(),
             mdds: self.mdds.clone(),
+            h_cardinal: self.h_cardinal,
+            meta_agent_of: self.meta_agent_of.clone(),
+            conflict_counts: self.conflict_counts.clone(),
         }
     }
 }
@@ -633,6 +2342,7 @@ mod tests {
             position: (0, 0),
             time_step: 1,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         };
 
         assert!(!non_perminant_vertex_constraint.is_violated((0, 0), (0, 1), 1));
@@ -644,6 +2354,7 @@ mod tests {
             position: (0, 0),
             time_step: 5,
             is_permanent: true,
+            kind: ConstraintKind::Negative,
         };
 
         assert!(!perminant_vertex_constraint.is_violated((0, 0), (0, 1), 5));
@@ -656,48 +2367,164 @@ mod tests {
             from_position: (0, 0),
             to_position: (0, 1),
             to_time_step: 2,
+            kind: ConstraintKind::Negative,
         };
 
         assert!(!edge_constraint.is_violated((0, 0), (0, 1), 1));
         assert!(!edge_constraint.is_violated((1, 1), (0, 1), 2));
         assert!(edge_constraint.is_violated((0, 0), (0, 1), 2));
+
+        // Test positive edge constraint: violated by any move arriving at
+        // `to_time_step` other than the required `from_position ->
+        // to_position` transition.
+        let positive_edge_constraint = Constraint::Edge {
+            from_position: (0, 0),
+            to_position: (0, 1),
+            to_time_step: 2,
+            kind: ConstraintKind::Positive,
+        };
+
+        assert!(!positive_edge_constraint.is_violated((0, 0), (0, 1), 2));
+        assert!(positive_edge_constraint.is_violated((1, 1), (0, 1), 2));
+        assert!(!positive_edge_constraint.is_violated((1, 1), (0, 1), 3));
+    }
+
+    #[test]
+    fn test_prune_dead_constraints() {
+        init_tracing();
+        let agent = Agent::new(0, (0, 0), (5, 5));
+
+        let mut constraints = HashSet::new();
+        // Unreachable that early: (0, 0) -> (3, 3) needs at least 6 steps.
+        constraints.insert(Constraint::Vertex {
+            position: (3, 3),
+            time_step: 1,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        });
+        // Reachable, kept.
+        constraints.insert(Constraint::Vertex {
+            position: (3, 3),
+            time_step: 6,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        });
+        // Permanent constraint at (1, 1) from timestep 5 onward.
+        constraints.insert(Constraint::Vertex {
+            position: (1, 1),
+            time_step: 5,
+            is_permanent: true,
+            kind: ConstraintKind::Negative,
+        });
+        // Dominated by the permanent constraint above, dropped.
+        constraints.insert(Constraint::Vertex {
+            position: (1, 1),
+            time_step: 6,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        });
+        // Reachable (manhattan distance from (0, 0) is 2) but earlier than
+        // the permanent constraint's own timestep, so not dominated: kept.
+        constraints.insert(Constraint::Vertex {
+            position: (1, 1),
+            time_step: 2,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        });
+        // Positive constraints are never pruned, even if unreachable.
+        constraints.insert(Constraint::Vertex {
+            position: (9, 9),
+            time_step: 0,
+            is_permanent: false,
+            kind: ConstraintKind::Positive,
+        });
+        // Unreachable edge arrival, dropped.
+        constraints.insert(Constraint::Edge {
+            from_position: (2, 2),
+            to_position: (3, 2),
+            to_time_step: 1,
+            kind: ConstraintKind::Negative,
+        });
+
+        let pruned = prune_dead_constraints(&constraints, &agent);
+
+        assert!(!pruned.contains(&Constraint::Vertex {
+            position: (3, 3),
+            time_step: 1,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        }));
+        assert!(pruned.contains(&Constraint::Vertex {
+            position: (3, 3),
+            time_step: 6,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        }));
+        assert!(pruned.contains(&Constraint::Vertex {
+            position: (1, 1),
+            time_step: 5,
+            is_permanent: true,
+            kind: ConstraintKind::Negative,
+        }));
+        assert!(!pruned.contains(&Constraint::Vertex {
+            position: (1, 1),
+            time_step: 6,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        }));
+        assert!(pruned.contains(&Constraint::Vertex {
+            position: (1, 1),
+            time_step: 2,
+            is_permanent: false,
+            kind: ConstraintKind::Negative,
+        }));
+        assert!(pruned.contains(&Constraint::Vertex {
+            position: (9, 9),
+            time_step: 0,
+            is_permanent: false,
+            kind: ConstraintKind::Positive,
+        }));
+        assert!(!pruned.contains(&Constraint::Edge {
+            from_position: (2, 2),
+            to_position: (3, 2),
+            to_time_step: 1,
+            kind: ConstraintKind::Negative,
+        }));
+
+        // Canonical set passed in is untouched.
+        assert_eq!(constraints.len(), 7);
     }
 
     use crate::common::MddNode;
-    use std::collections::HashMap;
 
     // Helper function for test mdd construction
     fn create_mdd_from_layers(layers: Vec<Vec<(usize, usize)>>) -> Mdd {
-        let mut mdd = vec![HashMap::new(); layers.len()];
-        for (layer, positions) in layers.iter().enumerate() {
-            for &pos in positions {
-                mdd[layer].insert(
-                    pos,
-                    MddNode {
-                        parents: HashSet::new(),
-                        children: HashSet::new(),
-                    },
-                );
+        let mut nodes = Vec::new();
+        let mut node_layers = Vec::with_capacity(layers.len());
+        for (depth, positions) in layers.iter().enumerate() {
+            let start = nodes.len();
+            for &position in positions {
+                nodes.push(MddNode {
+                    position,
+                    in_edges: Vec::new(),
+                    out_edges: Vec::new(),
+                    value: depth,
+                    value_bot: 0,
+                });
             }
+            node_layers.push(start..nodes.len());
+        }
+        Mdd {
+            nodes,
+            edges: Vec::new(),
+            layers: node_layers,
         }
-        mdd
     }
 
     #[test]
     fn test_detect_conflicts_cardinal_vertex() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 1),
-            },
-            Agent {
-                id: 1,
-                start: (0, 0),
-                goal: (0, 3),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 1)), Agent::new(1, (0, 0), (0, 3))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1)],
@@ -710,6 +2537,7 @@ mod tests {
         let mdd2 =
             create_mdd_from_layers(vec![vec![(0, 0)], vec![(0, 1)], vec![(0, 2)], vec![(0, 3)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -719,10 +2547,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -741,18 +2572,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_semi_cardinal_vertex() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 0),
-            },
-            Agent {
-                id: 1,
-                start: (0, 0),
-                goal: (0, 3),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 0)), Agent::new(1, (0, 0), (0, 3))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1), (0, 0)],
@@ -770,6 +2590,7 @@ mod tests {
         let mdd2 =
             create_mdd_from_layers(vec![vec![(0, 0)], vec![(0, 1)], vec![(0, 2)], vec![(0, 3)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -779,10 +2600,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -801,18 +2625,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_non_cardinal_vertex() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 0),
-            },
-            Agent {
-                id: 1,
-                start: (0, 4),
-                goal: (2, 2),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 0)), Agent::new(1, (0, 4), (2, 2))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1), (0, 0)],
@@ -835,6 +2648,7 @@ mod tests {
             vec![(2, 2)],
         ]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -844,10 +2658,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -866,18 +2683,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_vertex_non_mdd_semicardinal() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 0),
-            },
-            Agent {
-                id: 1,
-                start: (0, 0),
-                goal: (0, 3),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 0)), Agent::new(1, (0, 0), (0, 3))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1), (0, 0)],
@@ -887,6 +2693,7 @@ mod tests {
         let mdd2 =
             create_mdd_from_layers(vec![vec![(0, 0)], vec![(0, 1)], vec![(0, 2)], vec![(0, 3)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -896,10 +2703,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![None, Some(mdd2)],
+            mdds: vec![None, Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -918,18 +2728,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_vertex_non_mdd_noncardinal() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 0),
-            },
-            Agent {
-                id: 1,
-                start: (0, 0),
-                goal: (0, 3),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 0)), Agent::new(1, (0, 0), (0, 3))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1), (0, 0)],
@@ -944,6 +2743,7 @@ mod tests {
             vec![(0, 0)],         // layer 4
         ]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -953,10 +2753,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), None],
+            mdds: vec![Some(Arc::new(mdd1)), None],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -975,24 +2778,14 @@ mod tests {
     #[test]
     fn test_detect_conflicts_vertex_unknowncardinal() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (2, 2),
-                goal: (0, 0),
-            },
-            Agent {
-                id: 1,
-                start: (0, 0),
-                goal: (0, 3),
-            },
-        ];
+        let agents = vec![Agent::new(0, (2, 2), (0, 0)), Agent::new(1, (0, 0), (0, 3))];
 
         let paths = vec![
             vec![(2, 2), (1, 2), (0, 2), (0, 1), (0, 0)],
             vec![(0, 0), (0, 1), (0, 2), (0, 3)],
         ];
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1003,9 +2796,12 @@ mod tests {
             cost: 7,
             low_level_f_min_agents: Vec::new(),
             mdds: vec![None, None],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1024,18 +2820,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_cardinal_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 1),
-                goal: (2, 2),
-            },
-            Agent {
-                id: 1,
-                start: (2, 2),
-                goal: (0, 1),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 1), (2, 2)), Agent::new(1, (2, 2), (0, 1))];
 
         let paths = vec![
             vec![(0, 1), (0, 2), (1, 2), (2, 2)],
@@ -1048,6 +2833,7 @@ mod tests {
         let mdd2 =
             create_mdd_from_layers(vec![vec![(2, 2)], vec![(1, 2)], vec![(0, 2)], vec![(0, 1)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1057,10 +2843,13 @@ mod tests {
             paths,
             cost: 6,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1080,18 +2869,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_semicardinal_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 2),
-                goal: (2, 2),
-            },
-            Agent {
-                id: 1,
-                start: (2, 3),
-                goal: (0, 0),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 2), (2, 2)), Agent::new(1, (2, 3), (0, 0))];
 
         let paths = vec![
             vec![(0, 2), (1, 2), (2, 2)],
@@ -1109,6 +2887,7 @@ mod tests {
             vec![(0, 0)],
         ]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1118,10 +2897,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1141,18 +2923,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_noncardinal_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 0),
-                goal: (2, 3),
-            },
-            Agent {
-                id: 1,
-                start: (2, 3),
-                goal: (0, 0),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 0), (2, 3)), Agent::new(1, (2, 3), (0, 0))];
 
         let paths = vec![
             vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (2, 3)],
@@ -1177,6 +2948,7 @@ mod tests {
             vec![(0, 0)],
         ]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1186,10 +2958,13 @@ mod tests {
             paths,
             cost: 10,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1209,18 +2984,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_none_mdd_semicardinal_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 2),
-                goal: (2, 2),
-            },
-            Agent {
-                id: 1,
-                start: (2, 3),
-                goal: (0, 0),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 2), (2, 2)), Agent::new(1, (2, 3), (0, 0))];
 
         let paths = vec![
             vec![(0, 2), (1, 2), (2, 2)],
@@ -1229,6 +2993,7 @@ mod tests {
 
         let mdd1 = create_mdd_from_layers(vec![vec![(0, 2)], vec![(1, 2)], vec![(2, 2)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1238,10 +3003,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), None],
+            mdds: vec![Some(Arc::new(mdd1)), None],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1261,18 +3029,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_none_mdd_noncardinal_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 2),
-                goal: (2, 2),
-            },
-            Agent {
-                id: 1,
-                start: (2, 3),
-                goal: (0, 0),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 2), (2, 2)), Agent::new(1, (2, 3), (0, 0))];
 
         let paths = vec![
             vec![(0, 2), (1, 2), (2, 2)],
@@ -1288,6 +3045,7 @@ mod tests {
             vec![(0, 0)],
         ]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1297,10 +3055,13 @@ mod tests {
             paths,
             cost: 7,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![None, Some(mdd2)],
+            mdds: vec![None, Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1320,24 +3081,14 @@ mod tests {
     #[test]
     fn test_detect_conflicts_none_mdd_unknown_edge() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 1),
-                goal: (2, 2),
-            },
-            Agent {
-                id: 1,
-                start: (2, 2),
-                goal: (0, 1),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 1), (2, 2)), Agent::new(1, (2, 2), (0, 1))];
 
         let paths = vec![
             vec![(0, 1), (0, 2), (1, 2), (2, 2)],
             vec![(2, 2), (1, 2), (0, 2), (0, 1)],
         ];
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1348,9 +3099,12 @@ mod tests {
             cost: 6,
             low_level_f_min_agents: Vec::new(),
             mdds: vec![None, None],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1370,18 +3124,7 @@ mod tests {
     #[test]
     fn test_detect_conflicts_cardinal_target() {
         init_tracing();
-        let agents = vec![
-            Agent {
-                id: 0,
-                start: (0, 0),
-                goal: (0, 4),
-            },
-            Agent {
-                id: 1,
-                start: (2, 2),
-                goal: (0, 2),
-            },
-        ];
+        let agents = vec![Agent::new(0, (0, 0), (0, 4)), Agent::new(1, (2, 2), (0, 2))];
 
         let paths = vec![
             vec![(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)],
@@ -1398,6 +3141,7 @@ mod tests {
 
         let mdd2 = create_mdd_from_layers(vec![vec![(2, 2)], vec![(1, 2)], vec![(0, 2)]]);
 
+        let map = Map::from_file("map_file/test/test.map", &agents).unwrap();
         let mut node = HighLevelOpenNode {
             node_id: 0,
             agents,
@@ -1407,10 +3151,13 @@ mod tests {
             paths,
             cost: 6,
             low_level_f_min_agents: Vec::new(),
-            mdds: vec![Some(mdd1), Some(mdd2)],
+            mdds: vec![Some(Arc::new(mdd1)), Some(Arc::new(mdd2))],
+            h_cardinal: 0,
+            meta_agent_of: vec![0, 1],
+            conflict_counts: vec![vec![0; 2]; 2],
         };
 
-        node.detect_conflicts(true);
+        node.detect_conflicts(&map, true, false, false);
 
         assert_eq!(
             node.conflicts,
@@ -1455,6 +3202,7 @@ mod tests {
             position: (0, 0),
             time_step: 1,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         }));
         assert!(constraints[1].is_empty());
 
@@ -1475,6 +3223,7 @@ mod tests {
             position: (0, 0),
             time_step: 1,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         }));
         // Assert path length constraints remain unchanged
         assert_eq!(path_length_constraints, vec![0, 0]);
@@ -1510,6 +3259,7 @@ mod tests {
             from_position: (0, 0),
             to_position: (0, 1),
             to_time_step: 2,
+            kind: ConstraintKind::Negative,
         }));
         assert!(constraints[1].is_empty());
 
@@ -1530,6 +3280,7 @@ mod tests {
             from_position: (0, 1),
             to_position: (0, 0),
             to_time_step: 2,
+            kind: ConstraintKind::Negative,
         }));
         // Assert path length constraints remain unchanged
         assert_eq!(path_length_constraints, vec![0, 0]);
@@ -1564,6 +3315,7 @@ mod tests {
             position: (0, 0),
             time_step: 5,
             is_permanent: false,
+            kind: ConstraintKind::Negative,
         }));
         assert!(constraints[1].is_empty());
 
@@ -1584,6 +3336,7 @@ mod tests {
             position: (0, 0),
             time_step: 5,
             is_permanent: true,
+            kind: ConstraintKind::Negative,
         }));
         // Assert path length constraints remain unchanged
         assert_eq!(path_length_constraints, vec![5, 0]);
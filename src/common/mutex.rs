@@ -0,0 +1,218 @@
+use super::{EdgeId, Mdd, NodeId};
+
+use std::collections::HashSet;
+
+/// Whether MDD nodes `u` (in `mdd1`) and `v` (in `mdd2`) occupy the same
+/// cell -- a direct vertex mutex: no pair of paths can realize both at the
+/// same timestep.
+fn vertex_mutex(mdd1: &Mdd, u: NodeId, mdd2: &Mdd, v: NodeId) -> bool {
+    mdd1.node(u).position == mdd2.node(v).position
+}
+
+/// Whether the transitions `pu -> u` (in `mdd1`) and `pv -> v` (in `mdd2`)
+/// swap the two agents across the same edge -- a head-on edge mutex.
+fn edge_mutex(mdd1: &Mdd, pu: NodeId, u: NodeId, mdd2: &Mdd, pv: NodeId, v: NodeId) -> bool {
+    mdd1.node(pu).position == mdd2.node(v).position
+        && mdd2.node(pv).position == mdd1.node(u).position
+}
+
+/// The node(s) occupying `time_step`, padding `mdd` past its own length by
+/// holding its goal in place: inside the MDD proper this is just
+/// `mdd.layer(time_step)`; beyond it, the single final node stands in for
+/// every later timestep, since `mdd`'s agent has nothing left to do but
+/// wait at its goal.
+fn layer_at(mdd: &Mdd, time_step: usize) -> Vec<NodeId> {
+    if time_step < mdd.len() {
+        let range = &mdd.layers[time_step];
+        (range.start..range.end).map(NodeId).collect()
+    } else {
+        let goal_range = &mdd.layers[mdd.len() - 1];
+        vec![NodeId(goal_range.start)]
+    }
+}
+
+/// The parent(s) `node` (sitting at `time_step`) was reached from. Inside
+/// the MDD proper (`time_step < mdd.len()`), these are `node`'s real
+/// in-edges; once padding has taken over (`time_step >= mdd.len()`), the
+/// held goal's only "parent" is itself one step earlier.
+fn parents_at(mdd: &Mdd, time_step: usize, node: NodeId) -> Vec<NodeId> {
+    if time_step == 0 {
+        Vec::new()
+    } else if time_step < mdd.len() {
+        mdd.parents(node).collect()
+    } else {
+        vec![node]
+    }
+}
+
+/// Runs a mutex-propagation pass over `mdd1`/`mdd2` and reports whether the
+/// two goal nodes end up mutually exclusive, in which case the conflict
+/// between these two agents is cardinal no matter how wide either MDD is:
+/// every pair of optimal paths through them collides somewhere.
+///
+/// Mutex pairs are seeded per level from vertex mutexes (`u`/`v` share a
+/// position) and edge mutexes (the transitions into `u`/`v` swap the two
+/// agents across one edge), then propagated level by level: a pair `(u,
+/// v)` is mutex iff every non-mutex parent pair `(pu, pv)` reaches it
+/// through transition edges that are themselves mutex, i.e. there is no
+/// way left for the two agents to arrive at `(u, v)` together. The shorter
+/// MDD is conceptually padded out to the longer one's length by holding
+/// its goal in place (see `layer_at`), so both are compared to a common
+/// makespan.
+pub(crate) fn goal_mutex(mdd1: &Mdd, mdd2: &Mdd) -> bool {
+    if mdd1.len() == 0 || mdd2.len() == 0 {
+        return false;
+    }
+    let max_len = mdd1.len().max(mdd2.len());
+
+    let mut mutex: HashSet<(NodeId, NodeId)> = HashSet::new();
+    for time_step in 0..max_len {
+        let layer1 = layer_at(mdd1, time_step);
+        let layer2 = layer_at(mdd2, time_step);
+        let mut next_mutex = HashSet::new();
+
+        for &u in &layer1 {
+            for &v in &layer2 {
+                if vertex_mutex(mdd1, u, mdd2, v) {
+                    next_mutex.insert((u, v));
+                    continue;
+                }
+                if time_step == 0 {
+                    continue;
+                }
+
+                let parents1 = parents_at(mdd1, time_step, u);
+                let parents2 = parents_at(mdd2, time_step, v);
+                if parents1.is_empty() || parents2.is_empty() {
+                    continue;
+                }
+
+                let every_arrival_blocked = parents1.iter().all(|&pu| {
+                    parents2.iter().all(|&pv| {
+                        mutex.contains(&(pu, pv)) || edge_mutex(mdd1, pu, u, mdd2, pv, v)
+                    })
+                });
+                if every_arrival_blocked {
+                    next_mutex.insert((u, v));
+                }
+            }
+        }
+
+        mutex = next_mutex;
+    }
+
+    let goal1 = layer_at(mdd1, max_len - 1);
+    let goal2 = layer_at(mdd2, max_len - 1);
+    goal1.len() == 1 && goal2.len() == 1 && mutex.contains(&(goal1[0], goal2[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{MddEdge, MddNode};
+
+    /// Builds an `Mdd` from one straight-line path, i.e. a width-1 MDD:
+    /// every level is a singleton and each consecutive pair is linked by
+    /// one edge. Used as the "normal" agent in tests where only the other
+    /// agent's MDD is wide.
+    fn linear_mdd(path: &[(usize, usize)]) -> Mdd {
+        let mut nodes: Vec<MddNode> = path
+            .iter()
+            .map(|&position| MddNode {
+                position,
+                in_edges: Vec::new(),
+                out_edges: Vec::new(),
+                value: 0,
+                value_bot: 0,
+            })
+            .collect();
+        let mut edges = Vec::new();
+        for i in 0..nodes.len().saturating_sub(1) {
+            let edge_id = EdgeId(edges.len());
+            edges.push(MddEdge {
+                from: NodeId(i),
+                to: NodeId(i + 1),
+            });
+            nodes[i].out_edges.push(edge_id);
+            nodes[i + 1].in_edges.push(edge_id);
+        }
+        Mdd {
+            nodes,
+            edges,
+            layers: (0..path.len()).map(|i| i..i + 1).collect(),
+        }
+    }
+
+    #[test]
+    fn goal_mutex_true_when_paths_only_cross_head_on() {
+        // Agent 1 walks (0,0)->(0,1)->(0,2); agent 2 walks the same
+        // corridor in reverse, so every pairing of their single paths
+        // collides at (0,1) at step 1.
+        let mdd1 = linear_mdd(&[(0, 0), (0, 1), (0, 2)]);
+        let mdd2 = linear_mdd(&[(0, 2), (0, 1), (0, 0)]);
+        assert!(goal_mutex(&mdd1, &mdd2));
+    }
+
+    #[test]
+    fn goal_mutex_false_when_a_detour_avoids_collision() {
+        // Agent 2 has two width-2 choices at step 1; one of them ((1,0))
+        // steps around agent 1 entirely, so the goals are not mutex.
+        let mdd1 = linear_mdd(&[(0, 0), (0, 1), (0, 2)]);
+
+        let nodes = vec![
+            MddNode {
+                position: (1, 1),
+                in_edges: Vec::new(),
+                out_edges: vec![EdgeId(0), EdgeId(1)],
+                value: 0,
+                value_bot: 0,
+            },
+            MddNode {
+                position: (0, 1),
+                in_edges: vec![EdgeId(0)],
+                out_edges: vec![EdgeId(2)],
+                value: 1,
+                value_bot: 0,
+            },
+            MddNode {
+                position: (1, 0),
+                in_edges: vec![EdgeId(1)],
+                out_edges: vec![EdgeId(3)],
+                value: 1,
+                value_bot: 0,
+            },
+            MddNode {
+                position: (1, 2),
+                in_edges: vec![EdgeId(2), EdgeId(3)],
+                out_edges: Vec::new(),
+                value: 2,
+                value_bot: 0,
+            },
+        ];
+        let edges = vec![
+            MddEdge {
+                from: NodeId(0),
+                to: NodeId(1),
+            },
+            MddEdge {
+                from: NodeId(0),
+                to: NodeId(2),
+            },
+            MddEdge {
+                from: NodeId(1),
+                to: NodeId(3),
+            },
+            MddEdge {
+                from: NodeId(2),
+                to: NodeId(3),
+            },
+        ];
+        let mdd2 = Mdd {
+            nodes,
+            edges,
+            layers: vec![0..1, 1..3, 3..4],
+        };
+
+        assert!(!goal_mutex(&mdd1, &mdd2));
+    }
+}
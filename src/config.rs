@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
 use clap::Parser;
 
@@ -26,6 +28,13 @@ pub struct Cli {
     #[arg(long, help = "Path to the output file")]
     pub output_path: Option<String>,
 
+    #[arg(
+        long,
+        help = "Format for the stats record appended to --output-path: csv (header row written once, then one row per solve) or jsonl (one JSON object per solve)",
+        default_value = "csv"
+    )]
+    pub output_format: String,
+
     #[arg(long, help = "Output LACAM-style formatted solution to a file")]
     pub solution_path: String,
 
@@ -78,8 +87,210 @@ pub struct Cli {
     #[arg(long, help = "Optimization: Target Reasoning", default_value_t = false)]
     pub op_target_reasoning: bool,
 
+    #[arg(
+        long,
+        help = "Optimization: Disjoint Splitting (vertex and edge conflicts only: one child forbids the chosen agent from the cell/timestep or move, the other requires it and forbids every other agent instead, keeping the two subtrees' solution sets disjoint)",
+        default_value_t = false
+    )]
+    pub op_disjoint_splitting: bool,
+
+    #[arg(
+        long,
+        help = "Optimization: Symmetry Reasoning (rectangle and corridor conflicts get resolved by a barrier spanning the whole rectangle boundary or corridor instead of a single cell/timestep)",
+        default_value_t = false
+    )]
+    pub op_symmetry_reasoning: bool,
+
+    #[arg(
+        long,
+        help = "Optimization: MA-CBS meta-agent merging. When a pair of agents conflicts more than this many times across a branch of the CT, fuse them into one meta-agent replanned jointly (approximated via prioritized sequential replanning) instead of splitting further; omit to disable merging"
+    )]
+    pub op_merge_bound: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Optimization: expand the two children of a conflict split concurrently via a rayon thread pool",
+        default_value_t = false
+    )]
+    pub op_parallel_expansion: bool,
+
+    #[arg(
+        long,
+        help = "Optimization: skip expanding a high-level node whose canonical constraint signature (see HighLevelOpenNode::canonical_signature) was already expanded at an equal-or-lower cost, catching equivalent subtrees reached via a different branch order",
+        default_value_t = false
+    )]
+    pub op_duplicate_detection: bool,
+
+    #[arg(
+        long,
+        help = "Optimization: MDD mutex propagation (see common::mutex::goal_mutex). Upgrades a vertex/edge conflict's CardinalType to Cardinal whenever the two agents' MDD goal nodes are mutex, catching cardinal conflicts that a plain is_singleton_at_position check misses once either MDD has width > 1",
+        default_value_t = false
+    )]
+    pub op_mutex_reasoning: bool,
+
+    #[arg(
+        long,
+        help = "Optimization: WDG high-level heuristic (cbs only). Replaces the unweighted cardinal-conflict-graph MVC heuristic with a weighted one whose edge weights come from jointly replanning each conflicting pair (see HighLevelOpenNode::apply_wdg_heuristic); tighter bound, at the cost of a low-level joint replan per distinct uncached conflicting pair",
+        default_value_t = false
+    )]
+    pub op_wdg_heuristic: bool,
+
     #[arg(long, help = "Timeout seconds", default_value = "60")]
     pub timeout_secs: u64,
+
+    #[arg(
+        long,
+        help = "Path to persist the low-level path cache (serde) across runs against the same map; omit to use an in-memory-only cache"
+    )]
+    pub low_level_cache_path: Option<String>,
+
+    #[arg(
+        long,
+        help = "Low-level focal list tie-breaking heuristic for lbcbs/bcbs/ecbs/decbs/acbs: h1 (conflict count), h2 (conflicting agent count), h3 (conflicting agent pairs), h4 (vertex cover bound)",
+        default_value = "h1"
+    )]
+    pub focal_heuristic: String,
+
+    #[arg(
+        long,
+        help = "Cap the low-level open/focal list to the best-k entries per g-cost layer; omit for unbounded (complete) search"
+    )]
+    pub low_level_beam_width: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Number of high-level frontier nodes to expand concurrently via rayon (hbcbs/bcbs); 1 keeps expansion sequential",
+        default_value_t = 1
+    )]
+    pub num_threads: usize,
+
+    #[arg(
+        long,
+        help = "Cap the high-level open/focal lists (cbs/ecbs/hbcbs/bcbs) to the best-k nodes by cost, pruning the rest; breaks the suboptimality guarantee once a prune occurs"
+    )]
+    pub beam_width: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Disable the in-memory low-level path cache (PathCacheKey -> path/mdd), trading memory for always re-running the low-level search"
+    )]
+    pub no_low_level_cache: bool,
+
+    #[arg(
+        long,
+        help = "Enable anytime refinement (hbcbs/bcbs/acbs): after each solution, shrink the gap between the high-level suboptimality bound and 1.0 by this factor (0 < decay < 1) and search again for a better one"
+    )]
+    pub anytime_decay: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Wall-clock budget in seconds for anytime refinement; once elapsed, the best solution found so far is returned instead of waiting for the bound to reach 1.0"
+    )]
+    pub deadline_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "cbs: wall-clock budget in milliseconds for the high-level search; once exceeded, the lowest-cost (possibly conflict-bearing) node found so far is returned instead of running to completion"
+    )]
+    pub time_limit_ms: Option<u64>,
+
+    #[arg(
+        long,
+        help = "cbs: cap on the number of high-level nodes created; once reached, the lowest-cost (possibly conflict-bearing) node found so far is returned instead of running to completion"
+    )]
+    pub high_level_node_limit: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Milliseconds between SolveProgress snapshots sent to solve_with_progress's channel",
+        default_value_t = 1000
+    )]
+    pub progress_interval_ms: u64,
+
+    #[arg(
+        long,
+        help = "Weight on conflict count in the high-level focal ordering",
+        default_value_t = 1.0
+    )]
+    pub focal_weight_conflicts: f64,
+
+    #[arg(
+        long,
+        help = "Weight on total sum-of-delays (path length over each agent's low-level f-min) in the high-level focal ordering",
+        default_value_t = 0.0
+    )]
+    pub focal_weight_delay: f64,
+
+    #[arg(
+        long,
+        help = "Weight on number of constrained agents in the high-level focal ordering",
+        default_value_t = 0.0
+    )]
+    pub focal_weight_constrained_agents: f64,
+
+    #[arg(
+        long,
+        help = "Heuristic table mode: exact (full per-agent Dijkstra table), chunked (lazy admissible estimate routed through a chunk abstraction shared across agents, see --heuristic-chunk-size), or lazy (resumable per-goal reverse Dijkstra that only settles the cells the search actually queries); prefer chunked or lazy on large maps where an exact table per agent is too expensive",
+        default_value = "exact"
+    )]
+    pub heuristic_mode: String,
+
+    #[arg(
+        long,
+        help = "Chunk side length (cells) for --heuristic-mode=chunked; required when chunked, unused otherwise"
+    )]
+    pub heuristic_chunk_size: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Path to persist the exact per-goal heuristic distance tables (serde) across runs against the same map, keyed by map fingerprint and goal; omit to recompute every run. Only used with --heuristic-mode=exact"
+    )]
+    pub heuristic_cache_path: Option<String>,
+
+    #[arg(
+        long,
+        help = "Path to a sidecar file of teleport edges, one directed `from_x from_y to_x to_y` per line (unit cost, same as any other move); omit for a map with no teleporters"
+    )]
+    pub teleports_path: Option<String>,
+
+    #[arg(
+        long,
+        help = "Cluster side length (cells) for the low-level hierarchical search: partitions the map into clusters, builds an abstract graph over their border cells, and has lbcbs/bcbs/ecbs plan on it first before refining at full resolution; omit to search the full grid directly. Only a bounded-suboptimal shortcut, so only used by solvers with a subopt_factor to stay within"
+    )]
+    pub hierarchical_chunk_size: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Low-level open/focal list ordering: astar (exact f = g + h), weighted_astar (f = g + h * --low-level-weight, trading admissibility for speed), greedy (f = h, ignoring g entirely); incompatible with cbs/mddsat, whose optimality depends on an exact low-level search",
+        default_value = "astar"
+    )]
+    pub low_level_mode: String,
+
+    #[arg(
+        long,
+        help = "Inflation factor applied to h when --low-level-mode=weighted_astar; unused otherwise"
+    )]
+    pub low_level_weight: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Restart schedule for ecbs's high-level search: none (never restart), luby (Luby sequence of focal-reseed thresholds counted in high-level expansions), geometric (threshold doubling each restart); on trigger, focal is cleared and re-seeded from the best open nodes while closed is kept intact",
+        default_value = "none"
+    )]
+    pub restart_schedule: String,
+}
+
+/// Linear weights blending (conflict count, total sum-of-delays over the
+/// f-min path, number of constrained agents) into a single high-level focal
+/// score, so the search can be steered towards "fewest conflicts" versus
+/// "least delay" without touching the admissible `open` ordering. Defaults
+/// to weighting conflicts alone, matching the ordering used before this
+/// became configurable.
+#[derive(Debug, Clone, Copy)]
+pub struct FocalWeights {
+    pub conflicts: f64,
+    pub delay: f64,
+    pub constrained_agents: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +298,7 @@ pub struct Config {
     pub yaml_path: String,
     pub map_path: String,
     pub output_path: Option<String>,
+    pub output_format: String,
     pub solution_path: String,
     pub num_agents: usize,
     pub agents_dist: Vec<usize>,
@@ -98,7 +310,34 @@ pub struct Config {
     pub op_prioritize_conflicts: bool,
     pub op_bypass_conflicts: bool,
     pub op_target_reasoning: bool,
+    pub op_disjoint_splitting: bool,
+    pub op_symmetry_reasoning: bool,
+    pub op_merge_bound: Option<usize>,
+    pub op_parallel_expansion: bool,
+    pub op_duplicate_detection: bool,
+    pub op_mutex_reasoning: bool,
+    pub op_wdg_heuristic: bool,
     pub timeout_secs: u64,
+    pub low_level_cache_path: Option<String>,
+    pub focal_heuristic: String,
+    pub low_level_beam_width: Option<usize>,
+    pub num_threads: usize,
+    pub beam_width: Option<usize>,
+    pub low_level_cache: bool,
+    pub anytime_decay: Option<f64>,
+    pub deadline: Option<Duration>,
+    pub time_limit_ms: Option<u64>,
+    pub high_level_node_limit: Option<usize>,
+    pub progress_interval_ms: u64,
+    pub focal_weights: FocalWeights,
+    pub heuristic_mode: String,
+    pub heuristic_chunk_size: Option<usize>,
+    pub heuristic_cache_path: Option<String>,
+    pub teleports_path: Option<String>,
+    pub hierarchical_chunk_size: Option<usize>,
+    pub low_level_mode: String,
+    pub low_level_weight: Option<f64>,
+    pub restart_schedule: String,
 }
 
 impl Config {
@@ -108,6 +347,7 @@ impl Config {
             map_path: cli.map_path.clone(),
             solution_path: cli.solution_path.clone(),
             output_path: cli.output_path.clone(),
+            output_format: cli.output_format.clone(),
             num_agents: cli.num_agents,
             agents_dist: cli.agents_dist.clone(),
             deterministic_scen: cli.deterministic_scen,
@@ -118,18 +358,49 @@ impl Config {
             op_prioritize_conflicts: cli.op_prioritize_conflicts,
             op_bypass_conflicts: cli.op_bypass_conflicts,
             op_target_reasoning: cli.op_target_reasoning,
+            op_disjoint_splitting: cli.op_disjoint_splitting,
+            op_symmetry_reasoning: cli.op_symmetry_reasoning,
+            op_merge_bound: cli.op_merge_bound,
+            op_parallel_expansion: cli.op_parallel_expansion,
+            op_duplicate_detection: cli.op_duplicate_detection,
+            op_mutex_reasoning: cli.op_mutex_reasoning,
+            op_wdg_heuristic: cli.op_wdg_heuristic,
             timeout_secs: cli.timeout_secs,
+            low_level_cache_path: cli.low_level_cache_path.clone(),
+            focal_heuristic: cli.focal_heuristic.clone(),
+            low_level_beam_width: cli.low_level_beam_width,
+            num_threads: cli.num_threads,
+            beam_width: cli.beam_width,
+            low_level_cache: !cli.no_low_level_cache,
+            anytime_decay: cli.anytime_decay,
+            deadline: cli.deadline_secs.map(Duration::from_secs),
+            time_limit_ms: cli.time_limit_ms,
+            high_level_node_limit: cli.high_level_node_limit,
+            progress_interval_ms: cli.progress_interval_ms,
+            focal_weights: FocalWeights {
+                conflicts: cli.focal_weight_conflicts,
+                delay: cli.focal_weight_delay,
+                constrained_agents: cli.focal_weight_constrained_agents,
+            },
+            heuristic_mode: cli.heuristic_mode.clone(),
+            heuristic_chunk_size: cli.heuristic_chunk_size,
+            heuristic_cache_path: cli.heuristic_cache_path.clone(),
+            teleports_path: cli.teleports_path.clone(),
+            hierarchical_chunk_size: cli.hierarchical_chunk_size,
+            low_level_mode: cli.low_level_mode.clone(),
+            low_level_weight: cli.low_level_weight,
+            restart_schedule: cli.restart_schedule.clone(),
         }
     }
 
     pub fn validate(&self) -> anyhow::Result<()> {
         // Validate suboptimality values are present/absent correctly per solver
         match self.solver.as_str() {
-            "cbs" => {
-                // Both should be None for CBS
+            "cbs" | "mddsat" => {
+                // Both should be None for CBS/MddSat, which are optimal solvers.
                 if self.sub_optimal.0.is_some() || self.sub_optimal.1.is_some() {
                     return Err(anyhow!(
-                        "CBS should not have any suboptimality bounds, got high-level: {:?}, low-level: {:?}",
+                        "CBS/MddSat should not have any suboptimality bounds, got high-level: {:?}, low-level: {:?}",
                         self.sub_optimal.0,
                         self.sub_optimal.1
                     ));
@@ -187,6 +458,166 @@ impl Config {
             }
         }
 
+        if !["h1", "h2", "h3", "h4"].contains(&self.focal_heuristic.as_str()) {
+            return Err(anyhow!(
+                "focal-heuristic must be one of h1/h2/h3/h4, got {}",
+                self.focal_heuristic
+            ));
+        }
+
+        if self.num_threads == 0 {
+            return Err(anyhow!("num-threads must be greater than 0"));
+        }
+
+        if let Some(beam_width) = self.low_level_beam_width {
+            if beam_width == 0 {
+                return Err(anyhow!("low-level-beam-width must be greater than 0"));
+            }
+            if matches!(self.solver.as_str(), "cbs" | "mddsat") {
+                return Err(anyhow!(
+                    "low-level-beam-width is incompatible with {}, which relies on complete low-level search for optimality",
+                    self.solver
+                ));
+            }
+        }
+
+        if let Some(beam_width) = self.beam_width {
+            if beam_width == 0 {
+                return Err(anyhow!("beam-width must be greater than 0"));
+            }
+        }
+
+        for (name, weight) in [
+            ("focal-weight-conflicts", self.focal_weights.conflicts),
+            ("focal-weight-delay", self.focal_weights.delay),
+            (
+                "focal-weight-constrained-agents",
+                self.focal_weights.constrained_agents,
+            ),
+        ] {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(anyhow!("{name} must be finite and non-negative, got {weight}"));
+            }
+        }
+
+        if !["exact", "chunked", "lazy"].contains(&self.heuristic_mode.as_str()) {
+            return Err(anyhow!(
+                "heuristic-mode must be exact, chunked or lazy, got {}",
+                self.heuristic_mode
+            ));
+        }
+
+        match (self.heuristic_mode.as_str(), self.heuristic_chunk_size) {
+            ("chunked", None) | ("chunked", Some(0)) => {
+                return Err(anyhow!(
+                    "heuristic-chunk-size must be set to a value greater than 0 when heuristic-mode=chunked"
+                ));
+            }
+            ("exact", Some(_)) => {
+                return Err(anyhow!(
+                    "heuristic-chunk-size is only used when heuristic-mode=chunked"
+                ));
+            }
+            _ => {}
+        }
+
+        if self.heuristic_cache_path.is_some() && self.heuristic_mode != "exact" {
+            return Err(anyhow!(
+                "heuristic-cache-path is only used when heuristic-mode=exact"
+            ));
+        }
+
+        if self.hierarchical_chunk_size.is_some_and(|size| size == 0) {
+            return Err(anyhow!("hierarchical-chunk-size must be greater than 0"));
+        }
+
+        if self.hierarchical_chunk_size.is_some()
+            && !["lbcbs", "bcbs", "ecbs"].contains(&self.solver.as_str())
+        {
+            return Err(anyhow!(
+                "hierarchical-chunk-size is only used by lbcbs/bcbs/ecbs, got solver {}",
+                self.solver
+            ));
+        }
+
+        if !["astar", "weighted_astar", "greedy"].contains(&self.low_level_mode.as_str()) {
+            return Err(anyhow!(
+                "low-level-mode must be one of astar/weighted_astar/greedy, got {}",
+                self.low_level_mode
+            ));
+        }
+
+        match (self.low_level_mode.as_str(), self.low_level_weight) {
+            ("weighted_astar", None) => {
+                return Err(anyhow!(
+                    "low-level-weight must be set when low-level-mode=weighted_astar"
+                ));
+            }
+            ("weighted_astar", Some(weight)) if weight < 1.0 => {
+                return Err(anyhow!(
+                    "low-level-weight must be greater than or equal to 1.0, got {}",
+                    weight
+                ));
+            }
+            (mode, Some(_)) if mode != "weighted_astar" => {
+                return Err(anyhow!(
+                    "low-level-weight is only used when low-level-mode=weighted_astar"
+                ));
+            }
+            _ => {}
+        }
+
+        if self.low_level_mode != "astar" && matches!(self.solver.as_str(), "cbs" | "mddsat") {
+            return Err(anyhow!(
+                "low-level-mode other than astar breaks the optimality {} relies on",
+                self.solver
+            ));
+        }
+
+        if let Some(decay) = self.anytime_decay {
+            if !(0.0 < decay && decay < 1.0) {
+                return Err(anyhow!(
+                    "anytime-decay must be strictly between 0.0 and 1.0, got {}",
+                    decay
+                ));
+            }
+            if !matches!(self.solver.as_str(), "hbcbs" | "bcbs" | "acbs") {
+                return Err(anyhow!(
+                    "anytime-decay is only supported by hbcbs/bcbs/acbs, got {}",
+                    self.solver
+                ));
+            }
+        }
+
+        if self.time_limit_ms == Some(0) {
+            return Err(anyhow!("time-limit-ms must be greater than 0"));
+        }
+
+        if self.high_level_node_limit == Some(0) {
+            return Err(anyhow!("high-level-node-limit must be greater than 0"));
+        }
+
+        if !["csv", "jsonl"].contains(&self.output_format.as_str()) {
+            return Err(anyhow!(
+                "output-format must be one of csv/jsonl, got {}",
+                self.output_format
+            ));
+        }
+
+        if !["none", "luby", "geometric"].contains(&self.restart_schedule.as_str()) {
+            return Err(anyhow!(
+                "restart-schedule must be one of none/luby/geometric, got {}",
+                self.restart_schedule
+            ));
+        }
+
+        if self.restart_schedule != "none" && self.solver.as_str() != "ecbs" {
+            return Err(anyhow!(
+                "restart-schedule other than none is only supported by ecbs, got {}",
+                self.solver
+            ));
+        }
+
         Ok(())
     }
 }
@@ -1,9 +1,11 @@
 use std::process::exit;
 
+use mapf_rust::common::Solution;
 use mapf_rust::config::{Cli, Config};
-use mapf_rust::map::Map;
+use mapf_rust::map::{HeuristicMode, Map};
 use mapf_rust::scenario::Scenario;
-use mapf_rust::solver::{Solver, ACBS, BCBS, CBS, DECBS, ECBS, HBCBS, LBCBS};
+use mapf_rust::solver::{Solver, ACBS, BCBS, CBS, DECBS, ECBS, HBCBS, LBCBS, MddSat};
+use mapf_rust::stat::Stats;
 
 use clap::Parser;
 use rand::rngs::SmallRng;
@@ -40,7 +42,22 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    let map = Map::from_file(&config.map_path, &agents).expect("Error loading map");
+    let heuristic_mode = match config.heuristic_mode.as_str() {
+        "chunked" => HeuristicMode::Chunked {
+            chunk_size: config.heuristic_chunk_size.unwrap(),
+        },
+        "lazy" => HeuristicMode::Lazy,
+        _ => HeuristicMode::Exact,
+    };
+    let map = Map::from_file_with_heuristic_mode(
+        &config.map_path,
+        &agents,
+        heuristic_mode,
+        config.heuristic_cache_path.as_deref(),
+        config.teleports_path.as_deref(),
+        config.hierarchical_chunk_size,
+    )
+    .expect("Error loading map");
     for agent in agents.clone() {
         assert!(agent.verify(&map));
     }
@@ -53,6 +70,7 @@ async fn main() -> anyhow::Result<()> {
         "ecbs" => Box::new(ECBS::new(agents.clone(), &map)) as Box<dyn Solver>,
         "decbs" => Box::new(DECBS::new(agents.clone(), &map)) as Box<dyn Solver>,
         "acbs" => Box::new(ACBS::new(agents.clone(), &map)) as Box<dyn Solver>,
+        "mddsat" => Box::new(MddSat::new(agents.clone(), &map)) as Box<dyn Solver>,
         _ => unreachable!(),
     };
 
@@ -60,13 +78,36 @@ async fn main() -> anyhow::Result<()> {
     let agents_clone = agents.clone();
     let config_clone = config.clone();
 
-    let solve_future = tokio::task::spawn_blocking(move || solver.solve(&config_clone));
+    let solve_future = tokio::task::spawn_blocking(move || {
+        if config_clone.anytime_decay.is_some() {
+            let on_improved: Box<dyn FnMut(&Solution, &Stats) + Send> =
+                Box::new(|_solution, stats| {
+                    info!("anytime: improved solution with cost {}", stats.costs);
+                });
+            solver.solve_anytime(&config_clone, None, None, on_improved)
+        } else {
+            solver.solve(&config_clone)
+        }
+    });
     let result = timeout(Duration::from_secs(config.timeout_secs), solve_future).await;
 
     match result {
         Ok(Ok(Some(solution))) => {
-            assert!(solution.verify(&map_clone, &agents_clone));
-            solution.log_solution(&config);
+            // A `partial` solution (CBS exhausted `time_limit_ms`/
+            // `high_level_node_limit`) may still carry unresolved
+            // conflicts by design, so a `verify` failure there is expected
+            // rather than the hard bug it would be for a complete solve.
+            if solution.partial {
+                if !solution.verify(&map_clone, &agents_clone) {
+                    error!(
+                        "{} returned a partial solution (budget exhausted) with unresolved conflicts",
+                        cli.solver
+                    );
+                }
+            } else {
+                assert!(solution.verify(&map_clone, &agents_clone));
+            }
+            solution.log_solution(&config, &agents_clone, &map_clone);
         }
         Ok(Ok(None)) => {
             error!("{} solve failured with no solution", cli.solver);
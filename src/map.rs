@@ -1,7 +1,19 @@
+mod heuristic;
+
+pub use heuristic::{HeuristicMode, HeuristicTable};
+
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{self, BufRead, BufReader};
+use std::sync::Arc;
+
+use std::collections::hash_map::DefaultHasher;
+
+use rayon::prelude::*;
+
+use heuristic::{ChunkGraph, ChunkedHeuristic, HeuristicCache, LazyHeuristic};
 
 use crate::common::Agent;
 
@@ -9,6 +21,7 @@ use crate::common::Agent;
 pub struct Tile {
     passable: bool,
     pub neighbors: Vec<(usize, usize)>, // Stores coordinates of accessible neighbors
+    pub teleports: Vec<(usize, usize)>, // Destinations reachable from this tile at unit cost
 }
 
 impl Tile {
@@ -22,11 +35,51 @@ pub struct Map {
     pub height: usize,
     pub width: usize,
     pub grid: Vec<Vec<Tile>>,
-    pub heuristic: Vec<Vec<Vec<usize>>>,
+    pub heuristic: Vec<HeuristicTable>,
+    // Reverse of every `Tile::teleports` edge (destination -> origins),
+    // so `heuristic_dji`'s reverse Dijkstra from the goal can relax a
+    // teleport the same way it relaxes a grid edge: arriving at the
+    // teleport's exit in the reverse search means the entrance is one step
+    // closer to the goal too.
+    reverse_teleports: HashMap<(usize, usize), Vec<(usize, usize)>>,
+    // The same cluster/entrance abstraction `ChunkedHeuristic` estimates
+    // distance through, built independently of `heuristic_mode` and only
+    // when `Config::hierarchical_chunk_size` is set. Consumed through
+    // `hierarchical_waypoints` by `hierarchical_focal_a_star_search`, which
+    // plans on it before refining at full resolution.
+    hierarchical_graph: Option<Arc<ChunkGraph>>,
 }
 
 impl Map {
+    /// Loads the map with the default `HeuristicMode::Exact` heuristic: a
+    /// full per-agent Dijkstra table, see `heuristic_dji`.
     pub fn from_file(path: &str, agents: &Vec<Agent>) -> io::Result<Self> {
+        Self::from_file_with_heuristic_mode(path, agents, HeuristicMode::Exact, None, None, None)
+    }
+
+    /// Same as `from_file`, but lets the caller pick between the exact
+    /// per-agent table, the chunked lazy heuristic, and the resumable
+    /// per-goal reverse Dijkstra (see `HeuristicMode`), which large maps
+    /// should prefer to avoid precomputing and storing a full distance
+    /// table per agent. When `heuristic_mode` is `Exact`,
+    /// `heuristic_cache_path` optionally persists each goal's table to disk
+    /// (keyed by map fingerprint and goal) so repeated runs against the
+    /// same map and scenario bucket skip recomputing it. `teleports_path`
+    /// optionally loads a sidecar list of directed `(from, to)` teleport
+    /// edges (see `load_teleports`); every heuristic mode that's built on
+    /// top of `heuristic_dji` picks up the correction for free since
+    /// teleports are applied before any heuristic table is computed.
+    /// `hierarchical_chunk_size`, independent of `heuristic_mode`, builds
+    /// the cluster/entrance abstraction `hierarchical_waypoints` exposes to
+    /// `hierarchical_focal_a_star_search`.
+    pub fn from_file_with_heuristic_mode(
+        path: &str,
+        agents: &Vec<Agent>,
+        heuristic_mode: HeuristicMode,
+        heuristic_cache_path: Option<&str>,
+        teleports_path: Option<&str>,
+        hierarchical_chunk_size: Option<usize>,
+    ) -> io::Result<Self> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let mut lines = reader.lines();
@@ -58,6 +111,7 @@ impl Map {
                 .map(|ch| Tile {
                     passable: ch == '.',
                     neighbors: Vec::new(),
+                    teleports: Vec::new(),
                 })
                 .collect();
             grid.push(tiles_row);
@@ -68,11 +122,113 @@ impl Map {
             width,
             grid,
             heuristic: Vec::new(),
+            reverse_teleports: HashMap::new(),
+            hierarchical_graph: None,
         };
         map.initialize_neighbors();
-        for agent in agents {
-            map.heuristic.push(map.heuristic_dji(agent.goal));
+        if let Some(teleports_path) = teleports_path {
+            map.load_teleports(teleports_path)?;
+        }
+        if let Some(chunk_size) = hierarchical_chunk_size {
+            map.hierarchical_graph = Some(Arc::new(ChunkGraph::build(&map.grid, chunk_size)));
         }
+        map.heuristic = match heuristic_mode {
+            HeuristicMode::Exact => {
+                let map_fingerprint = map.fingerprint();
+                let mut cache = heuristic_cache_path
+                    .map(HeuristicCache::load)
+                    .unwrap_or_default();
+
+                // Distinct goals across agents -- scenarios commonly share a
+                // goal between several agents, so de-duplicating avoids
+                // running `heuristic_dji` twice for the same cell.
+                let mut goals: Vec<(usize, usize)> = Vec::new();
+                let mut goal_index: HashMap<(usize, usize), usize> = HashMap::new();
+                for agent in agents {
+                    goal_index.entry(agent.goal).or_insert_with(|| {
+                        goals.push(agent.goal);
+                        goals.len() - 1
+                    });
+                }
+
+                // Cache hits are resolved sequentially (cheap lookups); only
+                // the goals that still need a fresh Dijkstra run go through
+                // the parallel pass below.
+                let mut tables: Vec<Option<Vec<Vec<usize>>>> = goals
+                    .iter()
+                    .map(|&goal| cache.get(map_fingerprint, goal).cloned())
+                    .collect();
+
+                let missing: Vec<usize> = tables
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, table)| table.is_none())
+                    .map(|(index, _)| index)
+                    .collect();
+
+                let computed: Vec<(usize, Vec<Vec<usize>>)> = missing
+                    .into_par_iter()
+                    .map(|index| (index, map.heuristic_dji(goals[index])))
+                    .collect();
+
+                for (index, table) in computed {
+                    cache.insert(map_fingerprint, goals[index], table.clone());
+                    tables[index] = Some(table);
+                }
+
+                if let Some(cache_path) = heuristic_cache_path {
+                    if let Err(e) = cache.save(cache_path) {
+                        tracing::warn!("failed to persist heuristic table cache to {cache_path}: {e}");
+                    }
+                }
+
+                agents
+                    .iter()
+                    .map(|agent| {
+                        let table = tables[goal_index[&agent.goal]]
+                            .clone()
+                            .expect("every goal's table is computed or cached above");
+                        HeuristicTable::Exact(table)
+                    })
+                    .collect()
+            }
+            HeuristicMode::Chunked { chunk_size } => {
+                let graph = Arc::new(ChunkGraph::build(&map.grid, chunk_size));
+                agents
+                    .iter()
+                    .map(|agent| {
+                        HeuristicTable::Chunked(ChunkedHeuristic::new(graph.clone(), agent.goal))
+                    })
+                    .collect()
+            }
+            HeuristicMode::Lazy => {
+                // `LazyHeuristic` relaxes only these lists, so each cell's list
+                // also carries the teleport entrances `heuristic_dji` relaxes
+                // through `reverse_teleports`.
+                let neighbors: Vec<Vec<Vec<(usize, usize)>>> = map
+                    .grid
+                    .iter()
+                    .enumerate()
+                    .map(|(x, row)| {
+                        row.iter()
+                            .enumerate()
+                            .map(|(y, tile)| {
+                                let mut cell = tile.neighbors.clone();
+                                if let Some(entrances) = map.reverse_teleports.get(&(x, y)) {
+                                    cell.extend(entrances.iter().copied());
+                                }
+                                cell
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let neighbors = Arc::new(neighbors);
+                agents
+                    .iter()
+                    .map(|agent| HeuristicTable::Lazy(LazyHeuristic::new(neighbors.clone(), agent.goal)))
+                    .collect()
+            }
+        };
 
         Ok(map)
     }
@@ -81,14 +237,18 @@ impl Map {
         for x in 0..self.height {
             for y in 0..self.width {
                 if self.grid[x][y].passable {
-                    self.grid[x][y].neighbors = self.get_neighbors(x, y);
+                    // Plain grid adjacency only (no teleports): this cache
+                    // backs `heuristic_dji`'s relaxation, which needs edges
+                    // it can walk in reverse, and grid moves (unlike
+                    // teleports) are symmetric.
+                    self.grid[x][y].neighbors = self.grid_neighbors(x, y, true);
                 }
             }
         }
     }
 
-    pub fn get_neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let directions = [(-1, 0), (1, 0), (0, -1), (0, 1), (0, 0)]; // Up, down, left, right, stay
+    fn grid_neighbors(&self, x: usize, y: usize, include_wait: bool) -> Vec<(usize, usize)> {
+        let directions = [(-1, 0), (1, 0), (0, -1), (0, 1)]; // Up, down, left, right
         let mut neighbors = Vec::new();
 
         for &(dx, dy) in &directions {
@@ -104,6 +264,27 @@ impl Map {
             }
         }
 
+        if include_wait {
+            neighbors.push((x, y));
+        }
+
+        neighbors
+    }
+
+    /// Cells the low-level search can move to from `(x, y)` in one step:
+    /// the four cardinal grid moves, `(x, y)` itself when `include_wait`
+    /// (used to let the search wait out a constraint), and any teleport
+    /// this tile has an outgoing edge for (see `Tile::teleports`,
+    /// `load_teleports`).
+    pub fn get_neighbors(&self, x: usize, y: usize, include_wait: bool) -> Vec<(usize, usize)> {
+        let mut neighbors = self.grid_neighbors(x, y, include_wait);
+        neighbors.extend(
+            self.grid[x][y]
+                .teleports
+                .iter()
+                .copied()
+                .filter(|&(tx, ty)| self.grid[tx][ty].passable),
+        );
         neighbors
     }
 
@@ -111,6 +292,50 @@ impl Map {
         self.grid[x][y].is_passable()
     }
 
+    /// The border-node route `hierarchical_focal_a_star_search` refines
+    /// segment-by-segment, from `ChunkGraph::waypoints`. `None` both when
+    /// no `--hierarchical-chunk-size` was configured and when the
+    /// abstraction itself found no route between `start` and `goal`.
+    pub(crate) fn hierarchical_waypoints(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        self.hierarchical_graph
+            .as_ref()
+            .and_then(|graph| graph.waypoints(start, goal))
+    }
+
+    /// Loads a sidecar file of directed teleport edges, one
+    /// `from_x from_y to_x to_y` per line, and records each as a unit-cost
+    /// outgoing edge on the origin tile (`Tile::teleports`, consumed by
+    /// `get_neighbors`) and its reverse on `self.reverse_teleports`
+    /// (consumed by `heuristic_dji`). A teleport is one-directional; a
+    /// bidirectional one needs both `from to` and `to from` lines.
+    fn load_teleports(&mut self, path: &str) -> io::Result<()> {
+        let file = File::open(path)?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let fields: Vec<usize> = line
+                .split_whitespace()
+                .map(|field| field.parse().expect("teleport coordinates must be usize"))
+                .collect();
+            if fields.is_empty() {
+                continue; // Skip blank lines.
+            }
+            let [from_x, from_y, to_x, to_y] = fields[..] else {
+                panic!("teleport line must have exactly 4 fields `from_x from_y to_x to_y`, got {line:?}");
+            };
+
+            self.grid[from_x][from_y].teleports.push((to_x, to_y));
+            self.reverse_teleports
+                .entry((to_x, to_y))
+                .or_default()
+                .push((from_x, from_y));
+        }
+        Ok(())
+    }
+
     pub fn heuristic_dji(&self, goal: (usize, usize)) -> Vec<Vec<usize>> {
         let mut heuristic = vec![vec![usize::MAX; self.width]; self.height];
         let mut heap = BinaryHeap::new();
@@ -123,17 +348,45 @@ impl Map {
                 continue;
             }
 
+            let next_cost = cost + 1;
             for &(new_x, new_y) in &self.grid[x][y].neighbors {
-                let next_cost = cost + 1;
                 if next_cost < heuristic[new_x][new_y] {
                     heap.push((Reverse(next_cost), (new_x, new_y)));
                     heuristic[new_x][new_y] = next_cost;
                 }
             }
+
+            // A teleport `entrance -> (x, y)` makes `entrance` one step
+            // closer to the goal whenever `(x, y)` is, exactly like a
+            // grid edge relaxed in reverse above -- see
+            // `reverse_teleports`'s doc comment.
+            if let Some(entrances) = self.reverse_teleports.get(&(x, y)) {
+                for &(entrance_x, entrance_y) in entrances {
+                    if next_cost < heuristic[entrance_x][entrance_y] {
+                        heap.push((Reverse(next_cost), (entrance_x, entrance_y)));
+                        heuristic[entrance_x][entrance_y] = next_cost;
+                    }
+                }
+            }
         }
 
         heuristic
     }
+
+    /// A stable digest of the map's dimensions and passability grid, used to
+    /// invalidate on-disk caches keyed against a specific map (e.g. the
+    /// low-level path cache) when the map file changes.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.height.hash(&mut hasher);
+        self.width.hash(&mut hasher);
+        for row in &self.grid {
+            for tile in row {
+                tile.is_passable().hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
 }
 
 #[cfg(test)]
@@ -142,11 +395,7 @@ mod tests {
 
     #[test]
     fn test_read_map() {
-        let agents = vec![Agent {
-            id: 0,
-            start: (1, 1),
-            goal: (2, 2),
-        }];
+        let agents = vec![Agent::new(0, (1, 1), (2, 2))];
         let map =
             Map::from_file("map_file/maze-32-32-2-scen-even/maze-32-32-2.map", &agents).unwrap();
 
@@ -158,7 +407,7 @@ mod tests {
         assert!(!map.is_passable(0, 1));
         assert!(map.is_passable(1, 1));
 
-        let neighbors = map.get_neighbors(1, 1);
+        let neighbors = map.get_neighbors(1, 1, true);
         assert_eq!(neighbors.len(), 3);
         assert!(neighbors.contains(&(2, 1)));
         assert!(neighbors.contains(&(1, 2)));
@@ -0,0 +1,527 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::Tile;
+
+/// Which per-agent heuristic table `Map::from_file_with_heuristic_mode`
+/// builds: `Exact` precomputes the full per-cell Dijkstra table (see
+/// `Map::heuristic_dji`) -- cheap on small maps, prohibitive in time/memory
+/// on large ones, since it's repeated once per agent. `Chunked` instead
+/// builds one `ChunkGraph` shared by every agent on the map and answers each
+/// query lazily through it, trading per-query cost for a single map-wide
+/// precompute instead of one full table per agent. `Lazy` keeps the same
+/// reverse-Dijkstra `heuristic_dji` runs, but resumes each goal's search one
+/// query at a time instead of running it to completion up front, settling
+/// only the cells the search actually visits (see `LazyHeuristic`).
+#[derive(Debug, Clone, Copy)]
+pub enum HeuristicMode {
+    Exact,
+    Chunked { chunk_size: usize },
+    Lazy,
+}
+
+/// A per-agent heuristic lookup table: the full exact distance grid from
+/// `Map::heuristic_dji`, a lazy admissible estimate routed through a
+/// `ChunkGraph` shared by every agent on the map, or a resumable per-goal
+/// reverse Dijkstra (`LazyHeuristic`). `Map::heuristic[agent.id]` holds one
+/// of these per agent.
+#[derive(Debug, Clone)]
+pub enum HeuristicTable {
+    Exact(Vec<Vec<usize>>),
+    Chunked(ChunkedHeuristic),
+    Lazy(LazyHeuristic),
+}
+
+impl HeuristicTable {
+    pub fn get(&self, pos: (usize, usize)) -> usize {
+        match self {
+            HeuristicTable::Exact(table) => table[pos.0][pos.1],
+            HeuristicTable::Chunked(heuristic) => heuristic.get(pos),
+            HeuristicTable::Lazy(heuristic) => heuristic.get(pos),
+        }
+    }
+}
+
+/// Identifies one `Map::heuristic_dji` table by everything that determines
+/// it: which map (by fingerprint) and which goal. Two loads with equal keys
+/// are guaranteed to return the same table, so the result of the first can
+/// be reused by the rest -- same idea as `PathCacheKey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct HeuristicCacheKey {
+    map_fingerprint: u64,
+    goal: (usize, usize),
+}
+
+/// Disk-backed cache of exact per-goal distance tables (`Map::heuristic_dji`
+/// output), keyed by map fingerprint and goal so a cache loaded against a
+/// different map or an unseen goal simply misses instead of needing a
+/// whole-file staleness check -- mirrors `PathCache`'s pattern for the
+/// low-level search cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct HeuristicCache {
+    entries: HashMap<HeuristicCacheKey, Vec<Vec<usize>>>,
+}
+
+impl HeuristicCache {
+    /// Loads a previously saved cache from `path`. Any failure (missing
+    /// file, corrupt contents) is treated as a cold start rather than an
+    /// error, since the cache is purely a performance optimization.
+    pub(crate) fn load(path: &str) -> Self {
+        match File::open(path) {
+            Ok(file) => match serde_yaml::from_reader(BufReader::new(file)) {
+                Ok(cache) => {
+                    debug!("loaded heuristic table cache from {path}");
+                    cache
+                }
+                Err(e) => {
+                    warn!("failed to parse heuristic table cache at {path}, starting empty: {e}");
+                    HeuristicCache::default()
+                }
+            },
+            Err(_) => HeuristicCache::default(),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &str) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_yaml::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, map_fingerprint: u64, goal: (usize, usize)) -> Option<&Vec<Vec<usize>>> {
+        self.entries
+            .get(&HeuristicCacheKey { map_fingerprint, goal })
+    }
+
+    pub(crate) fn insert(&mut self, map_fingerprint: u64, goal: (usize, usize), table: Vec<Vec<usize>>) {
+        self.entries
+            .insert(HeuristicCacheKey { map_fingerprint, goal }, table);
+    }
+}
+
+/// One agent's lazy view into a map-wide `ChunkGraph`: `graph` is shared
+/// (built once per map, not once per agent) and `goal` selects which cell
+/// this table reports distances to.
+#[derive(Debug, Clone)]
+pub struct ChunkedHeuristic {
+    graph: Arc<ChunkGraph>,
+    goal: (usize, usize),
+}
+
+impl ChunkedHeuristic {
+    pub fn new(graph: Arc<ChunkGraph>, goal: (usize, usize)) -> Self {
+        ChunkedHeuristic { graph, goal }
+    }
+
+    /// An admissible lower bound on the distance from `pos` to `self.goal`,
+    /// routed through `graph`'s precomputed chunk structure and capped at
+    /// the 4-connected Manhattan distance (itself always a lower bound) so
+    /// the coarse abstraction can never overestimate and break A*'s
+    /// admissibility.
+    pub fn get(&self, pos: (usize, usize)) -> usize {
+        let goal = self.goal;
+        if pos == goal {
+            return 0;
+        }
+        let manhattan = pos.0.abs_diff(goal.0) + pos.1.abs_diff(goal.1);
+        self.graph.distance(pos, goal).min(manhattan)
+    }
+}
+
+/// Resumable reverse-Dijkstra state for one goal. A cell's distance is
+/// final the moment it's popped off `heap` (same invariant `Map::
+/// heuristic_dji` relies on), so `settled` lookups are O(1) and an
+/// unsettled query simply resumes popping from wherever the frontier was
+/// left off, instead of restarting the whole search.
+#[derive(Debug)]
+struct LazyDijkstraState {
+    dist: Vec<Vec<usize>>,
+    settled: Vec<Vec<bool>>,
+    heap: BinaryHeap<(Reverse<usize>, (usize, usize))>,
+}
+
+impl LazyDijkstraState {
+    fn new(goal: (usize, usize), height: usize, width: usize) -> Self {
+        let mut dist = vec![vec![usize::MAX; width]; height];
+        dist[goal.0][goal.1] = 0;
+        let mut heap = BinaryHeap::new();
+        heap.push((Reverse(0), goal));
+        LazyDijkstraState {
+            dist,
+            settled: vec![vec![false; width]; height],
+            heap,
+        }
+    }
+}
+
+/// A per-goal heuristic that runs the same reverse Dijkstra as `Map::
+/// heuristic_dji`, but only as far as each query requires instead of over
+/// the whole grid up front -- worthwhile on large maps where the
+/// low-level search only ever touches a small region around an agent's
+/// path. `neighbors` is the map's passable-neighbor lists, shared (via
+/// `Arc`) across every goal's `LazyHeuristic` on the map instead of
+/// duplicated per agent; `state` is behind a `Mutex` since resuming the
+/// search mutates the shared heap/settled set and `get` is called from
+/// parallel high-level expansions (see `HBCBS`/`ECBS`'s rayon batches).
+#[derive(Debug, Clone)]
+pub struct LazyHeuristic {
+    neighbors: Arc<Vec<Vec<Vec<(usize, usize)>>>>,
+    state: Arc<Mutex<LazyDijkstraState>>,
+}
+
+impl LazyHeuristic {
+    pub fn new(neighbors: Arc<Vec<Vec<Vec<(usize, usize)>>>>, goal: (usize, usize)) -> Self {
+        let height = neighbors.len();
+        let width = neighbors.first().map_or(0, |row| row.len());
+        LazyHeuristic {
+            neighbors,
+            state: Arc::new(Mutex::new(LazyDijkstraState::new(goal, height, width))),
+        }
+    }
+
+    /// The distance from `pos` to this heuristic's goal: O(1) once `pos`
+    /// has been settled, otherwise resumes popping the saved heap
+    /// (relaxing neighbors exactly as `Map::heuristic_dji` does) until
+    /// `pos` itself is popped. Returns `usize::MAX` if the heap drains
+    /// without ever reaching `pos` (it's disconnected from the goal).
+    pub fn get(&self, pos: (usize, usize)) -> usize {
+        let mut state = self.state.lock().unwrap();
+        if state.settled[pos.0][pos.1] {
+            return state.dist[pos.0][pos.1];
+        }
+
+        while let Some((Reverse(cost), (x, y))) = state.heap.pop() {
+            if state.settled[x][y] {
+                continue;
+            }
+            state.settled[x][y] = true;
+
+            for &(new_x, new_y) in &self.neighbors[x][y] {
+                let next_cost = cost + 1;
+                if next_cost < state.dist[new_x][new_y] {
+                    state.dist[new_x][new_y] = next_cost;
+                    state.heap.push((Reverse(next_cost), (new_x, new_y)));
+                }
+            }
+
+            if (x, y) == pos {
+                return cost;
+            }
+        }
+
+        usize::MAX
+    }
+}
+
+/// Precomputed once per map and shared (via `Arc`) across every agent's
+/// `ChunkedHeuristic`: the map partitioned into `chunk_size`-by-`chunk_size`
+/// chunks, each chunk's border cells (passable cells with a passable
+/// neighbor in a different chunk), the chunk-local distance from every
+/// border cell to the rest of its chunk, and the shortest distance between
+/// every pair of border cells across the whole map (the "abstract graph").
+#[derive(Debug)]
+pub(crate) struct ChunkGraph {
+    chunk_size: usize,
+    chunks_per_row: usize,
+    neighbors: Vec<Vec<Vec<(usize, usize)>>>,
+    border_nodes: HashMap<usize, Vec<(usize, usize)>>,
+    intra_chunk_dist: HashMap<(usize, usize), HashMap<(usize, usize), usize>>,
+    abstract_adjacency: HashMap<(usize, usize), Vec<((usize, usize), usize)>>,
+    abstract_dist: HashMap<(usize, usize), HashMap<(usize, usize), usize>>,
+}
+
+impl ChunkGraph {
+    pub(crate) fn build(grid: &[Vec<Tile>], chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let height = grid.len();
+        let width = grid.first().map_or(0, |row| row.len());
+        let chunks_per_row = width.div_ceil(chunk_size).max(1);
+        let chunk_id_of = |pos: (usize, usize)| (pos.0 / chunk_size) * chunks_per_row + pos.1 / chunk_size;
+
+        let neighbors: Vec<Vec<Vec<(usize, usize)>>> = grid
+            .iter()
+            .map(|row| row.iter().map(|tile| tile.neighbors.clone()).collect())
+            .collect();
+
+        // Border cells: passable cells with a passable neighbor in a
+        // different chunk.
+        let mut border_nodes: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        for (x, row) in grid.iter().enumerate() {
+            for (y, tile) in row.iter().enumerate() {
+                if !tile.is_passable() {
+                    continue;
+                }
+                let pos = (x, y);
+                let this_chunk = chunk_id_of(pos);
+                let is_border = tile
+                    .neighbors
+                    .iter()
+                    .any(|&n| n != pos && chunk_id_of(n) != this_chunk);
+                if is_border {
+                    border_nodes.entry(this_chunk).or_default().push(pos);
+                }
+            }
+        }
+
+        // Chunk-local BFS from every border node, confined to its own chunk.
+        let mut intra_chunk_dist: HashMap<(usize, usize), HashMap<(usize, usize), usize>> =
+            HashMap::new();
+        for (&chunk, borders) in &border_nodes {
+            for &border in borders {
+                let dist = Self::bfs_within_chunk(&neighbors, border, chunk, chunk_id_of);
+                intra_chunk_dist.insert(border, dist);
+            }
+        }
+
+        // Abstract graph edges: every pair of border nodes within the same
+        // chunk (weight = chunk-local distance), plus every pair of
+        // grid-adjacent border nodes straddling two different chunks
+        // (weight 1, since they're one grid step apart).
+        let mut abstract_adjacency: HashMap<(usize, usize), Vec<((usize, usize), usize)>> =
+            HashMap::new();
+        for borders in border_nodes.values() {
+            for &b1 in borders {
+                for &b2 in borders {
+                    if b1 == b2 {
+                        continue;
+                    }
+                    if let Some(&dist) = intra_chunk_dist.get(&b1).and_then(|d| d.get(&b2)) {
+                        abstract_adjacency.entry(b1).or_default().push((b2, dist));
+                    }
+                }
+            }
+        }
+        for borders in border_nodes.values() {
+            for &border in borders {
+                for &neighbor in &neighbors[border.0][border.1] {
+                    if neighbor != border && chunk_id_of(neighbor) != chunk_id_of(border) {
+                        abstract_adjacency
+                            .entry(border)
+                            .or_default()
+                            .push((neighbor, 1));
+                    }
+                }
+            }
+        }
+
+        // All-pairs shortest distance between border nodes, via one
+        // Dijkstra run per border node over the abstract graph above.
+        let mut abstract_dist = HashMap::new();
+        for &source in abstract_adjacency.keys() {
+            abstract_dist.insert(source, Self::dijkstra(&abstract_adjacency, source));
+        }
+
+        ChunkGraph {
+            chunk_size,
+            chunks_per_row,
+            neighbors,
+            border_nodes,
+            intra_chunk_dist,
+            abstract_adjacency,
+            abstract_dist,
+        }
+    }
+
+    fn chunk_id(&self, pos: (usize, usize)) -> usize {
+        (pos.0 / self.chunk_size) * self.chunks_per_row + pos.1 / self.chunk_size
+    }
+
+    fn bfs_within_chunk(
+        neighbors: &[Vec<Vec<(usize, usize)>>],
+        source: (usize, usize),
+        chunk: usize,
+        chunk_id_of: impl Fn((usize, usize)) -> usize,
+    ) -> HashMap<(usize, usize), usize> {
+        let mut dist = HashMap::new();
+        let mut queue = VecDeque::new();
+        dist.insert(source, 0);
+        queue.push_back(source);
+        while let Some(pos) = queue.pop_front() {
+            let d = dist[&pos];
+            for &neighbor in &neighbors[pos.0][pos.1] {
+                if neighbor == pos || chunk_id_of(neighbor) != chunk {
+                    continue;
+                }
+                if !dist.contains_key(&neighbor) {
+                    dist.insert(neighbor, d + 1);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        dist
+    }
+
+    fn dijkstra(
+        adjacency: &HashMap<(usize, usize), Vec<((usize, usize), usize)>>,
+        source: (usize, usize),
+    ) -> HashMap<(usize, usize), usize> {
+        let mut dist = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        dist.insert(source, 0);
+        heap.push((Reverse(0), source));
+        while let Some((Reverse(cost), pos)) = heap.pop() {
+            if cost > *dist.get(&pos).unwrap_or(&usize::MAX) {
+                continue;
+            }
+            if let Some(edges) = adjacency.get(&pos) {
+                for &(next, weight) in edges {
+                    let next_cost = cost + weight;
+                    if next_cost < *dist.get(&next).unwrap_or(&usize::MAX) {
+                        dist.insert(next, next_cost);
+                        heap.push((Reverse(next_cost), next));
+                    }
+                }
+            }
+        }
+        dist
+    }
+
+    /// A (not yet Manhattan-capped) estimate of the distance from `pos` to
+    /// `goal`: if they share a chunk, an exact BFS confined to that chunk;
+    /// otherwise the minimum, over every pair of border nodes spanning the
+    /// two chunks, of chunk-local distance to the border plus that border
+    /// pair's precomputed abstract distance. Returns `usize::MAX` if no
+    /// route was found (e.g. a chunk with no border nodes), which
+    /// `ChunkedHeuristic::get` then caps away with the Manhattan bound.
+    fn distance(&self, pos: (usize, usize), goal: (usize, usize)) -> usize {
+        let pos_chunk = self.chunk_id(pos);
+        let goal_chunk = self.chunk_id(goal);
+
+        if pos_chunk == goal_chunk {
+            let local = Self::bfs_within_chunk(&self.neighbors, pos, pos_chunk, |p| self.chunk_id(p));
+            if let Some(&dist) = local.get(&goal) {
+                return dist;
+            }
+        }
+
+        let empty = Vec::new();
+        let pos_borders = self.border_nodes.get(&pos_chunk).unwrap_or(&empty);
+        let goal_borders = self.border_nodes.get(&goal_chunk).unwrap_or(&empty);
+
+        let mut best = usize::MAX;
+        for &b1 in pos_borders {
+            let Some(&d1) = self.intra_chunk_dist.get(&b1).and_then(|d| d.get(&pos)) else {
+                continue;
+            };
+            let Some(reachable) = self.abstract_dist.get(&b1) else {
+                continue;
+            };
+            for &b2 in goal_borders {
+                let Some(&to_border) = reachable.get(&b2) else {
+                    continue;
+                };
+                let Some(&d2) = self.intra_chunk_dist.get(&b2).and_then(|d| d.get(&goal)) else {
+                    continue;
+                };
+                best = best.min(d1 + to_border + d2);
+            }
+        }
+        best
+    }
+
+    /// Same search `distance` uses to pick the best `(entry, exit)` border
+    /// pair, but returns the actual route instead of just its length:
+    /// `start`, the chunk-local leg to `start`'s best entry border, the
+    /// abstract-graph path of border nodes to `goal`'s best exit border,
+    /// then `goal`. Consumed by `hierarchical_focal_a_star_search`, which
+    /// refines each consecutive pair at full resolution instead of running
+    /// the low-level search over the whole grid. `None` when `distance`
+    /// would also have found no route (e.g. a chunk with no border nodes);
+    /// `start == goal`'s chunk returns the trivial `[start, goal]` since
+    /// the abstraction has nothing to add there.
+    pub(crate) fn waypoints(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        let start_chunk = self.chunk_id(start);
+        let goal_chunk = self.chunk_id(goal);
+
+        if start_chunk == goal_chunk {
+            return Some(vec![start, goal]);
+        }
+
+        let empty = Vec::new();
+        let start_borders = self.border_nodes.get(&start_chunk).unwrap_or(&empty);
+        let goal_borders = self.border_nodes.get(&goal_chunk).unwrap_or(&empty);
+
+        let mut best: Option<(usize, (usize, usize), (usize, usize))> = None;
+        for &entry in start_borders {
+            let Some(&entry_dist) = self.intra_chunk_dist.get(&entry).and_then(|d| d.get(&start))
+            else {
+                continue;
+            };
+            let Some(reachable) = self.abstract_dist.get(&entry) else {
+                continue;
+            };
+            for &exit in goal_borders {
+                let Some(&border_dist) = reachable.get(&exit) else {
+                    continue;
+                };
+                let Some(&exit_dist) = self.intra_chunk_dist.get(&exit).and_then(|d| d.get(&goal))
+                else {
+                    continue;
+                };
+                let total = entry_dist + border_dist + exit_dist;
+                if best.is_none_or(|(b, _, _)| total < b) {
+                    best = Some((total, entry, exit));
+                }
+            }
+        }
+
+        let (_, entry, exit) = best?;
+        let (_, prev) = Self::dijkstra_with_prev(&self.abstract_adjacency, entry);
+
+        let mut border_path = vec![exit];
+        let mut node = exit;
+        while node != entry {
+            node = prev[&node];
+            border_path.push(node);
+        }
+        border_path.reverse();
+
+        let mut path = vec![start];
+        path.extend(border_path);
+        path.push(goal);
+        Some(path)
+    }
+
+    /// Same as `dijkstra`, but also records each settled node's predecessor
+    /// so the caller can reconstruct the actual shortest path, not just its
+    /// length.
+    fn dijkstra_with_prev(
+        adjacency: &HashMap<(usize, usize), Vec<((usize, usize), usize)>>,
+        source: (usize, usize),
+    ) -> (
+        HashMap<(usize, usize), usize>,
+        HashMap<(usize, usize), (usize, usize)>,
+    ) {
+        let mut dist = HashMap::new();
+        let mut prev = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        dist.insert(source, 0);
+        heap.push((Reverse(0), source));
+        while let Some((Reverse(cost), pos)) = heap.pop() {
+            if cost > *dist.get(&pos).unwrap_or(&usize::MAX) {
+                continue;
+            }
+            if let Some(edges) = adjacency.get(&pos) {
+                for &(next, weight) in edges {
+                    let next_cost = cost + weight;
+                    if next_cost < *dist.get(&next).unwrap_or(&usize::MAX) {
+                        dist.insert(next, next_cost);
+                        prev.insert(next, pos);
+                        heap.push((Reverse(next_cost), next));
+                    }
+                }
+            }
+        }
+        (dist, prev)
+    }
+}
@@ -0,0 +1,198 @@
+//! Structured, schema-stable alternative to hand-rolled positional CSV
+//! formatting. `StatsRecord` names every field explicitly via `serde`, so
+//! adding a counter can't silently shift an existing column, and
+//! `write_record` renders it as either a CSV row (with a header written
+//! once per output file) or a newline-delimited JSON object, selected by
+//! `config.output_format`.
+
+use crate::config::Config;
+use crate::stat::Stats;
+
+use anyhow::Context;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// One solve's inputs (the subset of `Config` relevant to interpreting the
+/// run) and outputs (every `Stats` counter), named explicitly so CSV/JSON
+/// consumers can select by field name instead of column position.
+#[derive(Debug, Serialize)]
+pub(crate) struct StatsRecord<'a> {
+    pub map_path: &'a str,
+    pub yaml_path: &'a str,
+    pub num_agents: usize,
+    pub agents_dist: &'a [usize],
+    pub seed: usize,
+    pub solver: &'a str,
+    pub high_level_sub_optimal: f64,
+    pub low_level_sub_optimal: f64,
+    pub op_prioritize_conflicts: bool,
+    pub op_bypass_conflicts: bool,
+    pub op_target_reasoning: bool,
+    pub costs: usize,
+    /// Solve time in microseconds, copied from `Stats::time_ms`.
+    pub time_ms: usize,
+    pub high_level_expand_nodes: usize,
+    pub low_level_expand_open_nodes: usize,
+    pub low_level_expand_focal_nodes: usize,
+    pub low_level_expand_nodes: usize,
+    pub low_level_cache_hits: usize,
+    pub low_level_cache_misses: usize,
+    pub high_level_pruned_nodes: usize,
+    pub soc_lb: Option<usize>,
+    pub soc_lb_ratio: Option<f64>,
+    pub high_level_restarts: usize,
+    pub beam_widen_rounds: usize,
+    pub low_level_pruned_nodes: usize,
+    pub budget_exhausted: bool,
+}
+
+impl<'a> StatsRecord<'a> {
+    pub(crate) fn new(config: &'a Config, stats: &'a Stats) -> Self {
+        let soc_lb_ratio = stats
+            .soc_lb
+            .filter(|&soc_lb| soc_lb > 0)
+            .map(|soc_lb| stats.costs as f64 / soc_lb as f64);
+
+        StatsRecord {
+            map_path: &config.map_path,
+            yaml_path: &config.yaml_path,
+            num_agents: config.num_agents,
+            agents_dist: &config.agents_dist,
+            seed: config.seed,
+            solver: &config.solver,
+            high_level_sub_optimal: config.sub_optimal.0.unwrap_or(f64::NAN),
+            low_level_sub_optimal: config.sub_optimal.1.unwrap_or(f64::NAN),
+            op_prioritize_conflicts: config.op_prioritize_conflicts,
+            op_bypass_conflicts: config.op_bypass_conflicts,
+            op_target_reasoning: config.op_target_reasoning,
+            costs: stats.costs,
+            time_ms: stats.time_ms,
+            high_level_expand_nodes: stats.high_level_expand_nodes,
+            low_level_expand_open_nodes: stats.low_level_expand_open_nodes,
+            low_level_expand_focal_nodes: stats.low_level_expand_focal_nodes,
+            low_level_expand_nodes: stats.low_level_expand_open_nodes
+                + stats.low_level_expand_focal_nodes,
+            low_level_cache_hits: stats.low_level_cache_hits,
+            low_level_cache_misses: stats.low_level_cache_misses,
+            high_level_pruned_nodes: stats.high_level_pruned_nodes,
+            soc_lb: stats.soc_lb,
+            soc_lb_ratio,
+            high_level_restarts: stats.high_level_restarts,
+            beam_widen_rounds: stats.beam_widen_rounds,
+            low_level_pruned_nodes: stats.low_level_pruned_nodes,
+            budget_exhausted: stats.budget_exhausted,
+        }
+    }
+
+    /// Field names in declaration order, for the CSV header row.
+    fn header() -> &'static [&'static str] {
+        &[
+            "map_path",
+            "yaml_path",
+            "num_agents",
+            "agents_dist",
+            "seed",
+            "solver",
+            "high_level_sub_optimal",
+            "low_level_sub_optimal",
+            "op_prioritize_conflicts",
+            "op_bypass_conflicts",
+            "op_target_reasoning",
+            "costs",
+            "time_ms",
+            "high_level_expand_nodes",
+            "low_level_expand_open_nodes",
+            "low_level_expand_focal_nodes",
+            "low_level_expand_nodes",
+            "low_level_cache_hits",
+            "low_level_cache_misses",
+            "high_level_pruned_nodes",
+            "soc_lb",
+            "soc_lb_ratio",
+            "high_level_restarts",
+            "beam_widen_rounds",
+            "low_level_pruned_nodes",
+            "budget_exhausted",
+        ]
+    }
+
+    fn to_csv_row(&self) -> anyhow::Result<String> {
+        // `agents_dist` is the one field that isn't a scalar; CSV has no
+        // nested-value syntax, so it's rendered as a `;`-joined string
+        // rather than the `{:?}` debug formatting the old positional CSV
+        // used.
+        let agents_dist = self
+            .agents_dist
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        Ok(format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.map_path,
+            self.yaml_path,
+            self.num_agents,
+            agents_dist,
+            self.seed,
+            self.solver,
+            self.high_level_sub_optimal,
+            self.low_level_sub_optimal,
+            self.op_prioritize_conflicts,
+            self.op_bypass_conflicts,
+            self.op_target_reasoning,
+            self.costs,
+            self.time_ms,
+            self.high_level_expand_nodes,
+            self.low_level_expand_open_nodes,
+            self.low_level_expand_focal_nodes,
+            self.low_level_expand_nodes,
+            self.low_level_cache_hits,
+            self.low_level_cache_misses,
+            self.high_level_pruned_nodes,
+            self.soc_lb.map(|v| v as f64).unwrap_or(f64::NAN),
+            self.soc_lb_ratio.unwrap_or(f64::NAN),
+            self.high_level_restarts,
+            self.beam_widen_rounds,
+            self.low_level_pruned_nodes,
+            self.budget_exhausted,
+        ))
+    }
+}
+
+/// Appends one `StatsRecord` to `config.output_path` in `config.output_format`,
+/// writing the CSV header first if the file is currently empty (i.e. this is
+/// the first record written to it). No-op if `config.output_path` is unset.
+pub(crate) fn write_record(config: &Config, stats: &Stats) -> anyhow::Result<()> {
+    let Some(output_path) = &config.output_path else {
+        return Ok(());
+    };
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(output_path)
+        .with_context(|| format!("failed to open output file '{output_path}'"))?;
+
+    let record = StatsRecord::new(config, stats);
+
+    match config.output_format.as_str() {
+        "csv" => {
+            if file.metadata().map(|m| m.len()).unwrap_or(0) == 0 {
+                writeln!(file, "{}", StatsRecord::header().join(","))?;
+            }
+            writeln!(file, "{}", record.to_csv_row()?)?;
+        }
+        "jsonl" => {
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        }
+        other => {
+            // `Config::validate` already rejects anything else before the
+            // solve runs, so this is unreachable in practice.
+            unreachable!("unknown output format {other}");
+        }
+    }
+
+    Ok(())
+}
@@ -1,24 +1,86 @@
 use anyhow::Result;
 use rand::prelude::*;
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use rand::rngs::SmallRng;
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
 use tracing::info;
 
 use crate::common::Agent;
+use crate::map::Map;
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Sidecar metadata written next to a `.scen` export by `write_agents_to_scen`.
+/// Doesn't participate in reloading (the export itself already pins every
+/// start/goal/waypoint) -- it's a provenance record of how the export was
+/// generated, so a run can be explained and, for the seeded-bucket case,
+/// regenerated from scratch rather than only replayed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenExportMeta {
+    pub seed: [u8; 32],
+    pub bucket_indices: Option<Vec<usize>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Route {
     pub start_x: usize,
     pub start_y: usize,
     pub goal_x: usize,
     pub goal_y: usize,
+    /// Intermediate stops this route's agent must visit before its goal,
+    /// in file order. See `Agent::waypoints` for how `waypoints_ordered`
+    /// is interpreted.
+    #[serde(default)]
+    pub waypoints: Option<Vec<(usize, usize)>>,
+    #[serde(default)]
+    pub waypoints_ordered: bool,
+}
+
+/// Drives `Scenario::generate_agents_from_config`: a reproducible,
+/// file-authored alternative to passing an explicit `agent_buckets: Vec<usize>`
+/// of exact length `num_agents`. Rather than naming each agent's bucket one
+/// by one, `bucket_weights` expresses a target difficulty mix ("20% easy, 50%
+/// medium, 30% hard") that gets stratified-sampled into per-bucket counts.
+/// Keeping `seed` here (rather than threading it in separately) means a whole
+/// experiment batch is reproducible from this one file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScenarioGenConfig {
+    pub num_agents: usize,
+    pub seed: u64,
+    /// Weight per bucket index; need not sum to 1 (target counts are
+    /// computed proportionally), and need not cover every bucket the
+    /// scenario has -- only the ones this config wants to draw from.
+    pub bucket_weights: HashMap<usize, f64>,
+}
+
+impl ScenarioGenConfig {
+    pub fn load_from_yaml(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        let config = serde_yaml::from_reader(BufReader::new(file))?;
+        Ok(config)
+    }
 }
 
 type Bucket = Vec<Route>;
 
-#[derive(Debug, Deserialize)]
+/// Serializer selected by `Scenario::save_to_file` and
+/// `Scenario::save_agents_to_file`. `Scen` is the human-readable, hand-editable
+/// format `load_from_scen` already reads; `Bincode` is a compact binary cache
+/// for large pre-bucketed corpora that would otherwise re-parse the same text
+/// on every load; `GeoJson` emits a `FeatureCollection` of start-to-goal
+/// `LineString`s for quick visual inspection in any GeoJSON viewer. None of
+/// these round-trip through each other -- pick the one the reader needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Scen,
+    Bincode,
+    GeoJson,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Scenario {
     pub map: String,
     pub map_width: usize,
@@ -27,6 +89,11 @@ pub struct Scenario {
 }
 
 impl Scenario {
+    /// Bucket index `write_agents_to_scen` writes every exported agent into.
+    /// A fixed index is fine since an export is always its own standalone
+    /// `.scen` file, never merged into another scenario's buckets.
+    const EXPORT_BUCKET_INDEX: usize = 0;
+
     pub fn load_from_scen(path: &str) -> io::Result<Scenario> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
@@ -47,11 +114,29 @@ impl Scenario {
             let parts: Vec<&str> = line.split_whitespace().collect();
             let bucket_index: usize = parts[0].parse().unwrap();
 
+            // Standard movingai .scen columns stop at index 8 (optimal path
+            // length, which we don't otherwise use). A trailing
+            // "ordered"/"unordered" token followed by `y x` coordinate
+            // pairs is our own extension for routes with required
+            // intermediate stops.
+            let (waypoints, waypoints_ordered) = if parts.len() > 9 {
+                let waypoints_ordered = parts[9] == "ordered";
+                let waypoints: Vec<(usize, usize)> = parts[10..]
+                    .chunks_exact(2)
+                    .map(|pair| (pair[1].parse().unwrap(), pair[0].parse().unwrap()))
+                    .collect();
+                (Some(waypoints), waypoints_ordered)
+            } else {
+                (None, false)
+            };
+
             let route = Route {
                 start_x: parts[5].parse().unwrap(),
                 start_y: parts[4].parse().unwrap(),
                 goal_x: parts[7].parse().unwrap(),
                 goal_y: parts[6].parse().unwrap(),
+                waypoints,
+                waypoints_ordered,
             };
 
             if scenario.map.is_empty() {
@@ -74,6 +159,169 @@ impl Scenario {
         Ok(scenario)
     }
 
+    /// Builds a scenario from a bare `.map` file by sampling random
+    /// start/goal cell pairs and computing each pair's true shortest-path
+    /// length, rather than reading a pre-computed `optimal_length` column
+    /// from an existing `.scen` benchmark (`load_from_scen`). Keeps
+    /// sampling until `num_routes` routes (summed across every bucket) have
+    /// been collected, bucketing each by `floor(length / bucket_width)`.
+    /// Unreachable pairs (including start == goal) are skipped.
+    pub fn generate_from_map<R: Rng + ?Sized>(
+        map_path: &str,
+        num_routes: usize,
+        bucket_width: usize,
+        rng: &mut R,
+    ) -> Result<Scenario> {
+        let map = Map::from_file(map_path, &Vec::new())?;
+
+        let passable_cells: Vec<(usize, usize)> = (0..map.height)
+            .flat_map(|x| (0..map.width).map(move |y| (x, y)))
+            .filter(|&(x, y)| map.is_passable(x, y))
+            .collect();
+
+        if passable_cells.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "map {map_path} has too few passable cells ({}) to sample routes from",
+                passable_cells.len()
+            ));
+        }
+
+        let map_name = std::path::Path::new(map_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(map_path)
+            .to_string();
+
+        let mut buckets: HashMap<usize, Bucket> = HashMap::new();
+        // Reverse flood-fill amortizes one goal's distances across every
+        // start candidate paired with it, so cache per goal rather than
+        // per (start, goal) pair.
+        let mut distances_from_goal: HashMap<(usize, usize), Vec<Vec<f64>>> = HashMap::new();
+
+        let routes_collected = |buckets: &HashMap<usize, Bucket>| -> usize {
+            buckets.values().map(Vec::len).sum()
+        };
+
+        // Sampling random pairs on a sparse/disconnected map can take many
+        // tries to land a reachable one; cap attempts instead of looping
+        // forever, and return whatever was collected so far.
+        let max_attempts = num_routes.saturating_mul(200).max(10_000);
+        let mut attempts = 0;
+        while routes_collected(&buckets) < num_routes && attempts < max_attempts {
+            attempts += 1;
+
+            let goal = *passable_cells.choose(rng).unwrap();
+            let start = *passable_cells.choose(rng).unwrap();
+            if start == goal {
+                continue;
+            }
+
+            let distances = distances_from_goal
+                .entry(goal)
+                .or_insert_with(|| octile_distances_from(&map, goal));
+
+            let length = distances[start.0][start.1];
+            if !length.is_finite() {
+                continue;
+            }
+
+            let bucket_index = (length / bucket_width as f64).floor() as usize;
+            buckets.entry(bucket_index).or_default().push(Route {
+                start_x: start.0,
+                start_y: start.1,
+                goal_x: goal.0,
+                goal_y: goal.1,
+                waypoints: None,
+                waypoints_ordered: false,
+            });
+        }
+
+        if routes_collected(&buckets) < num_routes {
+            tracing::warn!(
+                "generate_from_map only found {}/{num_routes} reachable routes on {map_path} after {max_attempts} attempts",
+                routes_collected(&buckets)
+            );
+        }
+
+        Ok(Scenario {
+            map: map_name,
+            map_width: map.width,
+            map_height: map.height,
+            buckets: Some(buckets),
+        })
+    }
+
+    /// Persists the whole scenario (map name/dimensions plus every bucket) to
+    /// `path` in `format`. Unlike `save_agents_to_file`, this keeps each
+    /// route's original bucket index, so `ExportFormat::Scen` output reloads
+    /// via `load_from_scen` into the same buckets rather than collapsing
+    /// everything into `EXPORT_BUCKET_INDEX`.
+    pub fn save_to_file(&self, path: &str, format: ExportFormat) -> Result<()> {
+        match format {
+            ExportFormat::Scen => {
+                let mut contents = String::from("version 1\n");
+                let buckets = self.buckets.as_ref().cloned().unwrap_or_default();
+                for (bucket_index, routes) in &buckets {
+                    for route in routes {
+                        contents.push_str(&format!(
+                            "{bucket_index}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                            self.map,
+                            self.map_width,
+                            self.map_height,
+                            route.start_y,
+                            route.start_x,
+                            route.goal_y,
+                            route.goal_x,
+                            manhattan_distance(
+                                (route.start_x, route.start_y),
+                                (route.goal_x, route.goal_y)
+                            ),
+                        ));
+
+                        if let Some(waypoints) = &route.waypoints {
+                            contents.push_str(if route.waypoints_ordered {
+                                "\tordered"
+                            } else {
+                                "\tunordered"
+                            });
+                            for &(x, y) in waypoints {
+                                contents.push_str(&format!("\t{y}\t{x}"));
+                            }
+                        }
+                        contents.push('\n');
+                    }
+                }
+
+                let file = File::create(path)?;
+                io::BufWriter::new(file).write_all(contents.as_bytes())?;
+            }
+            ExportFormat::Bincode => {
+                let file = File::create(path)?;
+                bincode::serialize_into(io::BufWriter::new(file), self)?;
+            }
+            ExportFormat::GeoJson => {
+                let buckets = self.buckets.as_ref().cloned().unwrap_or_default();
+                let routes = buckets
+                    .iter()
+                    .flat_map(|(bucket_index, routes)| {
+                        routes.iter().map(move |route| (*bucket_index, route))
+                    })
+                    .map(|(bucket_index, route)| {
+                        route_to_geojson_feature(route, json!({ "bucket": bucket_index }))
+                    });
+                let feature_collection = json!({
+                    "type": "FeatureCollection",
+                    "features": routes.collect::<Vec<_>>(),
+                });
+
+                let file = File::create(path)?;
+                serde_json::to_writer_pretty(io::BufWriter::new(file), &feature_collection)?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn generate_agents_by_buckets<R: Rng + ?Sized>(
         &self,
         num_agents: usize,
@@ -117,11 +365,13 @@ impl Scenario {
                 .ok_or_else(|| "Failed to choose a random route".to_string())?;
 
             let route = &bucket[*route_index];
-            agents.push(Agent {
-                id: agent_id,
-                start: (route.start_x, route.start_y),
-                goal: (route.goal_x, route.goal_y),
-            });
+            agents.push(Agent::with_waypoints(
+                agent_id,
+                (route.start_x, route.start_y),
+                (route.goal_x, route.goal_y),
+                route.waypoints.clone(),
+                route.waypoints_ordered,
+            ));
 
             // Mark this route as used
             used_routes
@@ -166,11 +416,13 @@ impl Scenario {
                 .pop()
                 .ok_or("Ran out of routes unexpectedly")?;
 
-            agents.push(Agent {
-                id: agent_id,
-                start: (route.start_x, route.start_y),
-                goal: (route.goal_x, route.goal_y),
-            });
+            agents.push(Agent::with_waypoints(
+                agent_id,
+                (route.start_x, route.start_y),
+                (route.goal_x, route.goal_y),
+                route.waypoints.clone(),
+                route.waypoints_ordered,
+            ));
 
             // Mark this route as used
             used_routes.insert(route);
@@ -181,6 +433,238 @@ impl Scenario {
         Ok(agents)
     }
 
+    /// Like `generate_agents_randomly`, but rejects any candidate whose start
+    /// lies within `min_dist` of an already-accepted start (and, if
+    /// `enforce_goal_separation`, whose goal lies within `min_dist` of an
+    /// already-accepted goal), under `metric`. Keeps a separate `rstar`
+    /// R-tree of accepted starts/goals so each candidate's nearest neighbor
+    /// is a log-time query rather than a scan of everything accepted so far.
+    /// Over-constrained requests (too many agents, too large a `min_dist`,
+    /// or simply not enough routes left in the shuffled pool) return an
+    /// error after a bounded number of rejections rather than looping
+    /// forever.
+    pub fn generate_agents_with_min_separation<R: Rng + ?Sized>(
+        &self,
+        num_agents: usize,
+        min_dist: f64,
+        metric: SeparationMetric,
+        enforce_goal_separation: bool,
+        rng: &mut R,
+    ) -> Result<Vec<Agent>, String> {
+        let mut available_routes: Vec<Route> = self
+            .buckets
+            .as_ref()
+            .unwrap()
+            .clone()
+            .into_iter()
+            .flat_map(|(_, bucket)| bucket)
+            .collect();
+        available_routes.sort();
+
+        if available_routes.len() < num_agents {
+            return Err(
+                "Not enough unique routes available to match the number of agents".to_string(),
+            );
+        }
+
+        available_routes.shuffle(rng);
+
+        let max_rejections = num_agents.saturating_mul(200).max(1_000);
+
+        let mut agents: Vec<Agent> = Vec::new();
+        let mut start_tree: RTree<CoordPoint> = RTree::new();
+        let mut goal_tree: RTree<CoordPoint> = RTree::new();
+        let mut rejections = 0;
+
+        while agents.len() < num_agents {
+            let Some(route) = available_routes.pop() else {
+                return Err(format!(
+                    "ran out of candidate routes after accepting {}/{num_agents} agents under the min-separation constraint",
+                    agents.len()
+                ));
+            };
+
+            let start = (route.start_x, route.start_y);
+            let goal = (route.goal_x, route.goal_y);
+
+            if violates_min_separation(&start_tree, start, min_dist, metric)
+                || (enforce_goal_separation
+                    && violates_min_separation(&goal_tree, goal, min_dist, metric))
+            {
+                rejections += 1;
+                if rejections > max_rejections {
+                    return Err(format!(
+                        "exceeded {max_rejections} rejections trying to place {num_agents} agents with min_dist={min_dist}; the separation radius is likely too large for this map/agent count"
+                    ));
+                }
+                continue;
+            }
+
+            let agent_id = agents.len();
+            agents.push(Agent::with_waypoints(
+                agent_id,
+                start,
+                goal,
+                route.waypoints.clone(),
+                route.waypoints_ordered,
+            ));
+            start_tree.insert(CoordPoint(start));
+            if enforce_goal_separation {
+                goal_tree.insert(CoordPoint(goal));
+            }
+        }
+
+        info!("Generate scen: {agents:?}");
+        Ok(agents)
+    }
+
+    /// Stratified-samples `config.num_agents` agents according to
+    /// `config.bucket_weights`: each bucket's target count is
+    /// `round(num_agents * weight / total_weight)`, reconciled to sum to
+    /// exactly `num_agents` via the largest-remainder method (since
+    /// `num_agents * weight / total_weight` rarely lands on a whole number),
+    /// then that many distinct unused routes are drawn from the bucket
+    /// uniformly at random using `config.seed`. Errors out (naming the
+    /// nearest non-empty bucket as a suggestion) the moment any bucket can't
+    /// supply its target count, rather than only failing once the whole
+    /// scenario has been walked.
+    pub fn generate_agents_from_config(&self, config: &ScenarioGenConfig) -> Result<Vec<Agent>, String> {
+        if config.bucket_weights.is_empty() {
+            return Err("ScenarioGenConfig.bucket_weights must not be empty".to_string());
+        }
+
+        let total_weight: f64 = config.bucket_weights.values().sum();
+        if total_weight <= 0.0 {
+            return Err("ScenarioGenConfig.bucket_weights must sum to a positive value".to_string());
+        }
+
+        let mut bucket_indices: Vec<usize> = config.bucket_weights.keys().copied().collect();
+        bucket_indices.sort_unstable();
+
+        let mut targets: HashMap<usize, usize> = HashMap::new();
+        let mut remainders: Vec<(usize, f64)> = Vec::with_capacity(bucket_indices.len());
+        let mut assigned = 0usize;
+        for &bucket_index in &bucket_indices {
+            let exact = config.num_agents as f64 * config.bucket_weights[&bucket_index] / total_weight;
+            let floor = exact.floor() as usize;
+            targets.insert(bucket_index, floor);
+            remainders.push((bucket_index, exact - floor as f64));
+            assigned += floor;
+        }
+        remainders.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        let mut leftover = config.num_agents.saturating_sub(assigned);
+        for (bucket_index, _) in remainders {
+            if leftover == 0 {
+                break;
+            }
+            *targets.get_mut(&bucket_index).unwrap() += 1;
+            leftover -= 1;
+        }
+
+        let empty_bucket: Bucket = Vec::new();
+        let buckets = self.buckets.as_ref();
+        let mut rng = SmallRng::seed_from_u64(config.seed);
+        let mut agents: Vec<Agent> = Vec::with_capacity(config.num_agents);
+
+        for &bucket_index in &bucket_indices {
+            let target = targets[&bucket_index];
+            if target == 0 {
+                continue;
+            }
+
+            let bucket = buckets
+                .and_then(|buckets| buckets.get(&bucket_index))
+                .unwrap_or(&empty_bucket);
+
+            if bucket.len() < target {
+                return Err(match nearest_nonempty_bucket(buckets, bucket_index) {
+                    Some(nearest) => format!(
+                        "bucket {bucket_index} only has {}/{target} routes needed for this distribution; the nearest non-empty bucket is {nearest}",
+                        bucket.len()
+                    ),
+                    None => format!(
+                        "bucket {bucket_index} only has {}/{target} routes needed for this distribution, and no other bucket has any routes",
+                        bucket.len()
+                    ),
+                });
+            }
+
+            let mut route_indices: Vec<usize> = (0..bucket.len()).collect();
+            route_indices.shuffle(&mut rng);
+            for &route_index in route_indices.iter().take(target) {
+                let route = &bucket[route_index];
+                let agent_id = agents.len();
+                agents.push(Agent::with_waypoints(
+                    agent_id,
+                    (route.start_x, route.start_y),
+                    (route.goal_x, route.goal_y),
+                    route.waypoints.clone(),
+                    route.waypoints_ordered,
+                ));
+            }
+        }
+
+        info!("Generate scen from config: {agents:?}");
+        Ok(agents)
+    }
+
+    /// Samples `waypoints_per_agent + 2` distinct passable cells per agent
+    /// from `map_path` (one start, one goal, the rest intermediate stops)
+    /// and fixes each agent's visiting order up front via
+    /// `Agent::resolve_waypoint_order` -- the same exact-permutation/
+    /// Held-Karp/greedy tiers used for any other unordered waypoint set --
+    /// rather than leaving the low-level search to recompute it on every
+    /// expansion of this agent's node. Useful for package-delivery-style
+    /// MAPF instances where the order stops are visited in matters.
+    pub fn generate_multi_waypoint_agents<R: Rng + ?Sized>(
+        map_path: &str,
+        num_agents: usize,
+        waypoints_per_agent: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Agent>> {
+        let map = Map::from_file(map_path, &Vec::new())?;
+
+        let passable_cells: Vec<(usize, usize)> = (0..map.height)
+            .flat_map(|x| (0..map.width).map(move |y| (x, y)))
+            .filter(|&(x, y)| map.is_passable(x, y))
+            .collect();
+
+        let cells_needed = waypoints_per_agent + 2;
+        if passable_cells.len() < cells_needed {
+            return Err(anyhow::anyhow!(
+                "map {map_path} has too few passable cells ({}) to sample {cells_needed} distinct stops per agent",
+                passable_cells.len()
+            ));
+        }
+
+        let mut agents = Vec::with_capacity(num_agents);
+        for agent_id in 0..num_agents {
+            let mut cells: Vec<(usize, usize)> = passable_cells
+                .choose_multiple(rng, cells_needed)
+                .copied()
+                .collect();
+            cells.shuffle(rng);
+
+            let start = cells.remove(0);
+            let goal = cells.remove(0);
+            let waypoints = cells;
+
+            let unordered = Agent::with_waypoints(agent_id, start, goal, Some(waypoints), false);
+            let ordered_waypoints = unordered.resolve_waypoint_order(&map);
+
+            agents.push(Agent::with_waypoints(
+                agent_id,
+                start,
+                goal,
+                Some(ordered_waypoints),
+                true,
+            ));
+        }
+
+        info!("Generate multi-waypoint agents: {agents:?}");
+        Ok(agents)
+    }
+
     pub fn load_agents_from_yaml(path: &str) -> Result<Vec<Agent>> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
@@ -196,6 +680,365 @@ impl Scenario {
 
         Ok(())
     }
+
+    /// Writes `agents` back out as a single new bucket in the movingai
+    /// `.scen` format `load_from_scen` understands (including each agent's
+    /// waypoints via the same trailing-columns extension), so a generated
+    /// instance is persisted the same way a hand-authored scenario is rather
+    /// than only as a YAML agent dump. A sidecar file at `{path}.meta.yaml`
+    /// records `seed` and, for `generate_agents_by_buckets` callers, the
+    /// bucket index chosen per agent -- together documenting how this export
+    /// came to exist. Pair with `load_agents_from_scen_export` to read an
+    /// export back to byte-identical agents.
+    pub fn write_agents_to_scen(
+        path: &str,
+        map_name: &str,
+        map_width: usize,
+        map_height: usize,
+        agents: &[Agent],
+        seed: [u8; 32],
+        bucket_indices: Option<Vec<usize>>,
+    ) -> Result<()> {
+        let mut contents = String::from("version 1\n");
+        for agent in agents {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                Self::EXPORT_BUCKET_INDEX,
+                map_name,
+                map_width,
+                map_height,
+                agent.start.1,
+                agent.start.0,
+                agent.goal.1,
+                agent.goal.0,
+                manhattan_distance(agent.start, agent.goal),
+            ));
+
+            if let Some(waypoints) = &agent.waypoints {
+                contents.push_str(if agent.waypoints_ordered {
+                    "\tordered"
+                } else {
+                    "\tunordered"
+                });
+                for &(x, y) in waypoints {
+                    contents.push_str(&format!("\t{y}\t{x}"));
+                }
+            }
+            contents.push('\n');
+        }
+
+        let file = File::create(path)?;
+        let mut writer = io::BufWriter::new(file);
+        writer.write_all(contents.as_bytes())?;
+
+        let meta = ScenExportMeta {
+            seed,
+            bucket_indices,
+        };
+        let meta_file = File::create(format!("{path}.meta.yaml"))?;
+        serde_yaml::to_writer(meta_file, &meta)?;
+
+        Ok(())
+    }
+
+    /// Exports `agents` in `format`, dispatching to `write_agents_to_scen` for
+    /// `ExportFormat::Scen` (kept as its own function since it's the one
+    /// format that round-trips via `load_agents_from_scen_export`), a
+    /// `bincode` dump of `agents` for `ExportFormat::Bincode` so a large
+    /// pre-bucketed corpus loads instantly instead of re-parsing text, and a
+    /// GeoJSON `FeatureCollection` for `ExportFormat::GeoJson` (one
+    /// start-to-goal `LineString` per agent) for quick visual inspection.
+    /// Only the `Scen` branch writes the `{path}.meta.yaml` provenance
+    /// sidecar, since `seed`/`bucket_indices` only matter for replaying a
+    /// text export.
+    pub fn save_agents_to_file(
+        path: &str,
+        map_name: &str,
+        map_width: usize,
+        map_height: usize,
+        agents: &[Agent],
+        format: ExportFormat,
+        seed: [u8; 32],
+        bucket_indices: Option<Vec<usize>>,
+    ) -> Result<()> {
+        match format {
+            ExportFormat::Scen => Self::write_agents_to_scen(
+                path,
+                map_name,
+                map_width,
+                map_height,
+                agents,
+                seed,
+                bucket_indices,
+            ),
+            ExportFormat::Bincode => {
+                let file = File::create(path)?;
+                bincode::serialize_into(io::BufWriter::new(file), agents)?;
+                Ok(())
+            }
+            ExportFormat::GeoJson => {
+                let features: Vec<_> = agents
+                    .iter()
+                    .map(|agent| {
+                        let route = Route {
+                            start_x: agent.start.0,
+                            start_y: agent.start.1,
+                            goal_x: agent.goal.0,
+                            goal_y: agent.goal.1,
+                            waypoints: agent.waypoints.clone(),
+                            waypoints_ordered: agent.waypoints_ordered,
+                        };
+                        route_to_geojson_feature(&route, json!({ "agent_id": agent.id }))
+                    })
+                    .collect();
+                let feature_collection = json!({
+                    "type": "FeatureCollection",
+                    "features": features,
+                });
+
+                let file = File::create(path)?;
+                serde_json::to_writer_pretty(io::BufWriter::new(file), &feature_collection)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Reloads a `.scen` export written by `write_agents_to_scen` back to the
+    /// exact agents it recorded. No RNG involved: the export already pins
+    /// every start/goal/waypoint, so this is a plain read, not a replay.
+    pub fn load_agents_from_scen_export(path: &str) -> io::Result<Vec<Agent>> {
+        let scenario = Self::load_from_scen(path)?;
+        let bucket = scenario
+            .buckets
+            .as_ref()
+            .and_then(|buckets| buckets.get(&Self::EXPORT_BUCKET_INDEX))
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(bucket
+            .into_iter()
+            .enumerate()
+            .map(|(agent_id, route)| {
+                Agent::with_waypoints(
+                    agent_id,
+                    (route.start_x, route.start_y),
+                    (route.goal_x, route.goal_y),
+                    route.waypoints,
+                    route.waypoints_ordered,
+                )
+            })
+            .collect())
+    }
+}
+
+/// Builds a single GeoJSON `Feature` for `route`: a `LineString` running
+/// start -> waypoints (in file order) -> goal, so a viewer can render a
+/// whole scenario or agent export as a set of routes overlaid on the grid.
+/// Coordinates are emitted `[y, x]` (column, row) to match how the grid is
+/// normally plotted (column as the horizontal axis), not a geographic
+/// `[lon, lat]` pair -- there's no real-world CRS here, just a convenient
+/// visualization of grid cells.
+fn route_to_geojson_feature(route: &Route, properties: serde_json::Value) -> serde_json::Value {
+    let mut coordinates = vec![[route.start_y as f64, route.start_x as f64]];
+    if let Some(waypoints) = &route.waypoints {
+        coordinates.extend(waypoints.iter().map(|&(x, y)| [y as f64, x as f64]));
+    }
+    coordinates.push([route.goal_y as f64, route.goal_x as f64]);
+
+    json!({
+        "type": "Feature",
+        "properties": properties,
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+    })
+}
+
+/// Distance metric `generate_agents_with_min_separation` enforces between an
+/// already-accepted coordinate and a new candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeparationMetric {
+    Euclidean,
+    Chebyshev,
+}
+
+impl SeparationMetric {
+    fn distance(self, a: (usize, usize), b: (usize, usize)) -> f64 {
+        let dx = (a.0 as f64 - b.0 as f64).abs();
+        let dy = (a.1 as f64 - b.1 as f64).abs();
+        match self {
+            SeparationMetric::Euclidean => dx.hypot(dy),
+            SeparationMetric::Chebyshev => dx.max(dy),
+        }
+    }
+}
+
+/// R-tree element wrapping a single accepted start or goal coordinate, so
+/// `generate_agents_with_min_separation` can query "is anything already
+/// accepted too close to this candidate?" in log time instead of scanning
+/// every coordinate accepted so far.
+#[derive(Debug, Clone, Copy)]
+struct CoordPoint((usize, usize));
+
+impl RTreeObject for CoordPoint {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.0 .0 as f64, self.0 .1 as f64])
+    }
+}
+
+impl PointDistance for CoordPoint {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        let dx = self.0 .0 as f64 - point[0];
+        let dy = self.0 .1 as f64 - point[1];
+        dx * dx + dy * dy
+    }
+}
+
+/// Whether `tree` already holds a point within `min_dist` of `candidate`
+/// under `metric`. Euclidean uses `rstar`'s native nearest-neighbor search
+/// (it optimizes for squared Euclidean distance); Chebyshev instead queries
+/// the axis-aligned box of side `2 * min_dist` centered on `candidate`,
+/// since a Chebyshev ball of radius `min_dist` *is* exactly that square, and
+/// refines with the exact Chebyshev distance because the box is closed: it
+/// also returns points at distance exactly `min_dist`, which do not count.
+fn violates_min_separation(
+    tree: &RTree<CoordPoint>,
+    candidate: (usize, usize),
+    min_dist: f64,
+    metric: SeparationMetric,
+) -> bool {
+    let candidate_f = [candidate.0 as f64, candidate.1 as f64];
+    match metric {
+        SeparationMetric::Euclidean => tree
+            .nearest_neighbor(&candidate_f)
+            .is_some_and(|nearest| metric.distance(nearest.0, candidate) < min_dist),
+        SeparationMetric::Chebyshev => {
+            let envelope = AABB::from_corners(
+                [candidate_f[0] - min_dist, candidate_f[1] - min_dist],
+                [candidate_f[0] + min_dist, candidate_f[1] + min_dist],
+            );
+            tree.locate_in_envelope(&envelope)
+                .any(|point| metric.distance(point.0, candidate) < min_dist)
+        }
+    }
+}
+
+/// The non-empty bucket index closest to `from` (excluding `from` itself),
+/// used by `generate_agents_from_config` to suggest an alternative when the
+/// requested bucket can't supply enough routes.
+fn nearest_nonempty_bucket(buckets: Option<&HashMap<usize, Bucket>>, from: usize) -> Option<usize> {
+    buckets?
+        .iter()
+        .filter(|&(&index, bucket)| index != from && !bucket.is_empty())
+        .min_by_key(|&(&index, _)| index.abs_diff(from))
+        .map(|(&index, _)| index)
+}
+
+/// Manhattan distance between `from` and `to`, used as the `.scen` format's
+/// "optimal path length" column for exported agents. `load_from_scen` never
+/// reads this column back (see `Route`), so an estimate is all that's
+/// needed to keep the file well-formed.
+fn manhattan_distance(from: (usize, usize), to: (usize, usize)) -> usize {
+    from.0.abs_diff(to.0) + from.1.abs_diff(to.1)
+}
+
+/// Cost of a diagonal move under the octile-distance convention
+/// `generate_from_map` uses to match MovingAI `.scen` benchmarks: cardinal
+/// moves cost 1, diagonal moves cost `sqrt(2)`.
+const DIAGONAL_COST: f64 = std::f64::consts::SQRT_2;
+
+/// Min-heap entry for `octile_distances_from`'s Dijkstra; wraps an `f64`
+/// cost so it can sit in a `BinaryHeap`, which needs a total order that
+/// `f64`'s `PartialOrd` doesn't provide on its own. Costs here are always
+/// finite non-NaN accumulated path lengths, so falling back to `Equal` on
+/// an incomparable pair (which cannot occur) is never actually exercised.
+struct OctileHeapEntry(f64, (usize, usize));
+
+impl PartialEq for OctileHeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for OctileHeapEntry {}
+impl PartialOrd for OctileHeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for OctileHeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so `BinaryHeap` (a max-heap) pops the smallest cost first.
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Reverse flood-fill from `goal` over `map`'s passable cells using octile
+/// movement (cardinal cost 1, diagonal cost `sqrt(2)`, diagonals disallowed
+/// when either orthogonally adjacent cell is blocked -- no corner cutting),
+/// matching the MovingAI `.scen` convention `load_from_scen` already
+/// assumes for its `optimal_length` column. One Dijkstra run from the goal
+/// yields every reachable cell's shortest distance to it, amortizing the
+/// cost across every start candidate later paired with that goal.
+/// Unreachable cells hold `f64::INFINITY`.
+fn octile_distances_from(map: &Map, goal: (usize, usize)) -> Vec<Vec<f64>> {
+    let mut dist = vec![vec![f64::INFINITY; map.width]; map.height];
+    dist[goal.0][goal.1] = 0.0;
+
+    let mut heap = BinaryHeap::new();
+    heap.push(OctileHeapEntry(0.0, goal));
+
+    while let Some(OctileHeapEntry(cost, (x, y))) = heap.pop() {
+        if cost > dist[x][y] {
+            continue;
+        }
+        for (nx, ny, step_cost) in octile_neighbors(map, x, y) {
+            let next_cost = cost + step_cost;
+            if next_cost < dist[nx][ny] {
+                dist[nx][ny] = next_cost;
+                heap.push(OctileHeapEntry(next_cost, (nx, ny)));
+            }
+        }
+    }
+
+    dist
+}
+
+/// The passable cells reachable from `(x, y)` in one octile move, paired
+/// with their step cost. A diagonal move is excluded if either of the two
+/// orthogonally adjacent cells it would cut past is blocked, matching the
+/// "no corner cutting" rule `octile_distances_from`'s doc comment states.
+fn octile_neighbors(map: &Map, x: usize, y: usize) -> Vec<(usize, usize, f64)> {
+    const DELTAS: [(i64, i64, f64); 8] = [
+        (-1, 0, 1.0),
+        (1, 0, 1.0),
+        (0, -1, 1.0),
+        (0, 1, 1.0),
+        (-1, -1, DIAGONAL_COST),
+        (-1, 1, DIAGONAL_COST),
+        (1, -1, DIAGONAL_COST),
+        (1, 1, DIAGONAL_COST),
+    ];
+
+    let mut neighbors = Vec::with_capacity(8);
+    for &(dx, dy, step_cost) in &DELTAS {
+        let nx = x as i64 + dx;
+        let ny = y as i64 + dy;
+        if nx < 0 || ny < 0 || nx as usize >= map.height || ny as usize >= map.width {
+            continue;
+        }
+        let (nx, ny) = (nx as usize, ny as usize);
+        if !map.is_passable(nx, ny) {
+            continue;
+        }
+        if dx != 0 && dy != 0 && (!map.is_passable(x, ny) || !map.is_passable(nx, y)) {
+            continue;
+        }
+        neighbors.push((nx, ny, step_cost));
+    }
+    neighbors
 }
 
 #[cfg(test)]
@@ -219,17 +1062,37 @@ mod tests {
             .generate_agents_by_buckets(num_agents, agent_buckets, &mut rng)
             .unwrap();
         let answer = [
-            Agent {
-                id: 0,
-                start: (9, 25),
-                goal: (8, 28),
-            },
-            Agent {
-                id: 1,
-                start: (8, 19),
-                goal: (10, 17),
-            },
+            Agent::new(0, (9, 25), (8, 28)),
+            Agent::new(1, (8, 19), (10, 17)),
         ];
         assert_eq!(agents, answer);
     }
+
+    #[test]
+    fn test_scen_export_round_trips_to_identical_agents() {
+        let agents = vec![
+            Agent::new(0, (9, 25), (8, 28)),
+            Agent::with_waypoints(1, (8, 19), (10, 17), Some(vec![(9, 18)]), true),
+        ];
+        let seed = [0u8; 32];
+        let path = std::env::temp_dir().join("mapf_rust_test_export.scen");
+        let path = path.to_str().unwrap();
+
+        Scenario::write_agents_to_scen(
+            path,
+            "maze-32-32-2.map",
+            32,
+            32,
+            &agents,
+            seed,
+            Some(vec![0, 1]),
+        )
+        .unwrap();
+
+        let reloaded = Scenario::load_agents_from_scen_export(path).unwrap();
+        assert_eq!(reloaded, agents);
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(format!("{path}.meta.yaml")).unwrap();
+    }
 }
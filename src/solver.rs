@@ -1,22 +1,120 @@
+mod acbs;
 mod bcbs;
 mod cbs;
 mod decbs;
 mod ecbs;
 mod hbcbs;
 mod lbcbs;
+mod mddsat;
+mod progress;
 
+pub use acbs::ACBS;
 pub use bcbs::BCBS;
 pub use cbs::CBS;
 pub use decbs::DECBS;
 pub use ecbs::ECBS;
 pub use hbcbs::HBCBS;
 pub use lbcbs::LBCBS;
+pub use mddsat::MddSat;
+pub use progress::{SolveProgress, StopFlag};
 
 use crate::common::{HighLevelOpenNode, Solution};
 use crate::config::Config;
+use crate::stat::Stats;
+
+use std::collections::HashMap;
+
+use crossbeam_channel::Sender;
+
+/// Runs `f` inside a rayon thread pool capped at `config.num_threads`,
+/// instead of letting its `rayon::join`/`par_iter` calls fall back to the
+/// default global pool sized to every visible CPU. Used by solvers whose
+/// `config.op_parallel_expansion` path parallelizes root construction and
+/// conflict-split replanning, so `num_threads` actually bounds that
+/// parallelism rather than merely documenting an intent. Falls back to
+/// running `f` on the current thread if the pool fails to build (shouldn't
+/// happen, since `Config::validate` already rejects `num_threads == 0`).
+pub(crate) fn with_capped_thread_pool<T: Send>(
+    config: &Config,
+    f: impl FnOnce() -> T + Send,
+) -> T {
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(config.num_threads)
+        .build()
+    {
+        Ok(pool) => pool.install(f),
+        Err(_) => f(),
+    }
+}
+
+/// Invoked with each improved incumbent an anytime solve finds, alongside
+/// the `Stats` accumulated up to that point.
+pub(crate) type AnytimeCallback = Box<dyn FnMut(&Solution, &Stats) + Send>;
 
 pub trait Solver {
     fn solve(&mut self, config: &Config) -> Option<Solution>;
+
+    /// Same as `solve`, but additionally reports periodic `SolveProgress`
+    /// over `progress` and cooperatively cancels when `stop` is set,
+    /// returning the incumbent recovered so far (or `None`) instead of
+    /// running to completion. The default delegates straight to `solve`, so
+    /// solvers that don't support streaming progress keep compiling as-is.
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        _progress: Option<Sender<SolveProgress>>,
+        _stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        self.solve(config)
+    }
+
+    /// Anytime variant: repeatedly solves with a high-level suboptimality
+    /// bound that tightens towards 1.0 by `config.anytime_decay` each round,
+    /// calling `on_improved` with every improved solution and stopping once
+    /// the bound reaches 1.0 (proven optimal) or `config.deadline` elapses.
+    /// Returns the best solution found. The default has no notion of a
+    /// tightening bound, so it just runs `solve_with_progress` once and
+    /// reports that result (with empty `Stats`, since solvers keep their
+    /// own `Stats` private) before returning it.
+    fn solve_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        mut on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let result = self.solve_with_progress(config, progress, stop);
+        if let Some(solution) = &result {
+            on_improved(solution, &Stats::default());
+        }
+        result
+    }
+}
+
+/// Under `config.op_duplicate_detection`, skip inserting `child` into `open`
+/// if its canonical constraint signature (see
+/// `HighLevelOpenNode::canonical_signature`) was already expanded at an
+/// equal-or-lower cost, since that subtree has already been explored via a
+/// different branch order. Otherwise records this node's cost as the best
+/// seen for its signature and returns `true` so the caller inserts it.
+/// Callers must compare against `cost` (the admissible f), never `focal`,
+/// or bounded-suboptimal solvers (ECBS, BCBS, ...) lose their guarantee.
+pub(crate) fn admit_node(
+    config: &Config,
+    signatures: &mut HashMap<u64, usize>,
+    child: &HighLevelOpenNode,
+) -> bool {
+    if !config.op_duplicate_detection {
+        return true;
+    }
+    let signature = child.canonical_signature();
+    match signatures.get(&signature) {
+        Some(&best_cost) if best_cost <= child.cost => false,
+        _ => {
+            signatures.insert(signature, child.cost);
+            true
+        }
+    }
 }
 
 pub(crate) fn sub_optimal_bypass_comparation(
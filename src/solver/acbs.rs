@@ -1,5 +1,9 @@
-use super::{sub_optimal_bypass_comparation, Solver};
-use crate::common::{Agent, CardinalType, HighLevelOpenNode, Solution};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{
+    sub_optimal_bypass_comparation, with_capped_thread_pool, AnytimeCallback, Solver,
+    SolveProgress, StopFlag,
+};
+use crate::common::{select_prioritized_conflict, Agent, HighLevelOpenNode, PathCache, Solution};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
@@ -8,6 +12,8 @@ use std::collections::BTreeSet;
 use std::time::Instant;
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+
 pub struct ACBS {
     agents: Vec<Agent>,
     map: Map,
@@ -22,181 +28,594 @@ impl ACBS {
             stats: Stats::default(),
         }
     }
-}
 
-impl Solver for ACBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
-        let mut global_high_level_node_id = 0;
         let subopt_factor = config.sub_optimal.1.unwrap();
 
         let mut open = BTreeSet::new();
         let mut focal = BTreeSet::new();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
-        if let Some(root) =
-            HighLevelOpenNode::new(&self.agents, &self.map, config, "acbs", &mut self.stats)
-        {
-            open.insert(root.clone());
-            focal.insert(root.to_focal_node());
-
-            while let Some(current_focal_node) = focal.pop_first() {
-                debug!(
-                    "Node Id: {:?}, conflicts: {:?}",
-                    current_focal_node.node_id, current_focal_node.conflicts
-                );
-                let current_open_node = current_focal_node.to_open_node();
-                let old_f_min: usize = open.first().unwrap().low_level_f_min_agents.iter().sum();
-
-                open.remove(&current_open_node);
-
-                let conflict = if config.op_prioritize_conflicts {
-                    current_open_node
-                        .conflicts
-                        .iter()
-                        .find(|c| c.cardinal_type == CardinalType::Cardinal)
-                        .or_else(|| {
-                            current_open_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::SemiCardinal)
-                        })
-                        .or_else(|| {
-                            current_open_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::NonCardinal)
-                        })
-                        .or_else(|| current_open_node.conflicts.first())
-                } else {
-                    current_open_node.conflicts.first()
-                };
-
-                if let Some(conflict) = conflict {
-                    debug!("conflict: {conflict:?}");
-                    let mut bypass = false;
-
-                    global_high_level_node_id += 1;
-                    let child_1 = current_open_node.update_constraint(
-                        conflict,
-                        true,
-                        &self.map,
-                        config,
-                        global_high_level_node_id,
-                        &mut self.stats,
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
+
+        let result = 'search: {
+            if let Some(root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "acbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                open.insert(root.clone());
+                focal.insert(root.to_focal_node(config));
+
+                while let Some(current_focal_node) = focal.pop_first() {
+                    if is_stopped(&stop) {
+                        break 'search None;
+                    }
+                    ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        best_cost: current_focal_node.cost,
+                        lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: focal.len(),
+                        ..Default::default()
+                    });
+                    debug!(
+                        "Node Id: {:?}, conflicts: {:?}",
+                        current_focal_node.node_id, current_focal_node.conflicts
                     );
+                    let current_open_node = current_focal_node.to_open_node();
+                    let old_f_min: usize =
+                        open.first().unwrap().low_level_f_min_agents.iter().sum();
+
+                    open.remove(&current_open_node);
+
+                    let conflict = if config.op_prioritize_conflicts {
+                        select_prioritized_conflict(
+                            &current_open_node.conflicts,
+                            current_open_node.agents.len(),
+                        )
+                        .or_else(|| current_open_node.conflicts.first())
+                    } else {
+                        current_open_node.conflicts.first()
+                    };
+
+                    if let Some(conflict) = conflict {
+                        debug!("conflict: {conflict:?}");
+                        let mut bypass = false;
 
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_1 {
-                            if sub_optimal_bypass_comparation(
-                                &current_open_node,
-                                child,
-                                conflict.agent_1,
-                                subopt_factor,
-                            ) {
-                                debug!(
-                                    "Bypass Node {:?} into Node {:?}",
-                                    current_open_node.node_id, child.node_id
-                                );
-                                open.insert(
-                                    current_open_node.update_bypass_node(child, conflict.agent_1),
-                                );
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
+                        let (child_1, child_2) = if config.op_parallel_expansion {
+                            let mut stats_1 = Stats::default();
+                            let mut stats_2 = Stats::default();
+                            let mut cache_1 = cache.clone();
+                            let mut cache_2 = cache.clone();
+                            let (child_1, child_2) = rayon::join(
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        true,
+                                        &self.map,
+                                        config,
+                                        &mut stats_1,
+                                        map_fingerprint,
+                                        &mut cache_1,
+                                    )
+                                },
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        false,
+                                        &self.map,
+                                        config,
+                                        &mut stats_2,
+                                        map_fingerprint,
+                                        &mut cache_2,
+                                    )
+                                },
+                            );
+                            self.stats.merge(&stats_1);
+                            self.stats.merge(&stats_2);
+                            cache.merge(cache_1);
+                            cache.merge(cache_2);
+                            (child_1, child_2)
+                        } else {
+                            let child_1 = current_open_node.update_constraint(
+                                conflict,
+                                true,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            let child_2 = current_open_node.update_constraint(
+                                conflict,
+                                false,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            (child_1, child_2)
+                        };
+
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_1 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    conflict.agent_1,
+                                    subopt_factor,
+                                ) {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_open_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_1),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
                             }
                         }
-                    }
 
-                    global_high_level_node_id += 1;
-                    let child_2 = current_open_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        config,
-                        global_high_level_node_id,
-                        &mut self.stats,
-                    );
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_2 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    conflict.agent_2,
+                                    subopt_factor,
+                                ) {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_open_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_2),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
+                            }
+                        }
+
+                        if bypass {
+                            continue;
+                        }
 
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_2 {
-                            if sub_optimal_bypass_comparation(
-                                &current_open_node,
-                                child,
-                                conflict.agent_2,
-                                subopt_factor,
-                            ) {
-                                debug!(
-                                    "Bypass Node {:?} into Node {:?}",
-                                    current_open_node.node_id, child.node_id
-                                );
-                                open.insert(
-                                    current_open_node.update_bypass_node(child, conflict.agent_2),
-                                );
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
+                        if let Some(child) = child_1 {
+                            debug!(
+                                "Expand Node {:?} into Node {:?}",
+                                current_open_node.node_id, child.node_id
+                            );
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
                             }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
                         }
-                    }
 
-                    if bypass {
-                        continue;
+                        if let Some(child) = child_2 {
+                            debug!(
+                                "Expand Node {:?} into Node {:?}",
+                                current_open_node.node_id, child.node_id
+                            );
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
+                        }
+                    } else {
+                        // No conflicts, return solution
+                        debug!("Find solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_open_node.cost;
+
+                        self.stats.print(config);
+                        break 'search Some(Solution {
+                            paths: current_open_node.paths,
+                            ..Default::default()
+                        });
                     }
 
-                    if let Some(child) = child_1 {
-                        debug!(
-                            "Expand Node {:?} into Node {:?}",
-                            current_open_node.node_id, child.node_id
-                        );
-                        if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
-                            focal.insert(child.to_focal_node());
+                    // Maintain the focal list
+                    if !open.is_empty() {
+                        let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
+                        if old_f_min < new_f_min {
+                            open.iter().for_each(|node| {
+                                if node.cost as f64 > subopt_factor * old_f_min as f64
+                                    && node.cost as f64 <= subopt_factor * new_f_min as f64
+                                {
+                                    focal.insert(node.to_focal_node(config));
+                                }
+                            });
                         }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
                     }
+                }
+            }
 
-                    if let Some(child) = child_2 {
-                        debug!(
-                            "Expand Node {:?} into Node {:?}",
-                            current_open_node.node_id, child.node_id
-                        );
-                        if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
-                            focal.insert(child.to_focal_node());
-                        }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
+            None
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
+            }
+        }
+
+        result
+    }
+
+    /// Single-tree anytime variant of `solve_inner`: instead of returning on
+    /// the first conflict-free node, it is kept as the incumbent, the
+    /// high-level `subopt_factor` is tightened by `decay` towards 1.0, and
+    /// `focal` is rebuilt from whatever is left in `open` under the new
+    /// bound so the same search keeps expanding rather than restarting from
+    /// the root. This stops once `subopt_factor` reaches 1.0 (the usual ACBS
+    /// optimality guarantee), once `open` is exhausted (no candidate is left
+    /// at any bound, so the incumbent is already optimal), or once
+    /// `config.deadline` elapses - whichever comes first.
+    #[allow(clippy::too_many_arguments)]
+    fn solve_inner_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        deadline_start: Instant,
+        decay: f64,
+        mut on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let total_solve_start_time = Instant::now();
+        let mut subopt_factor = config.sub_optimal.1.unwrap();
+
+        let mut open = BTreeSet::new();
+        let mut focal = BTreeSet::new();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
+
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
+
+        let mut incumbent: Option<Solution> = None;
+
+        let result = 'search: {
+            if let Some(root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "acbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                open.insert(root.clone());
+                focal.insert(root.to_focal_node(config));
+
+                loop {
+                    if is_stopped(&stop)
+                        || config.deadline.is_some_and(|d| deadline_start.elapsed() >= d)
+                    {
+                        break 'search incumbent;
                     }
-                } else {
-                    // No conflicts, return solution
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_open_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_open_node.paths,
+
+                    let Some(current_focal_node) = focal.pop_first() else {
+                        // Nothing left under the current bound: either `open`
+                        // is empty too (the whole tree is exhausted) or every
+                        // remaining node's cost exceeds `subopt_factor *
+                        // f_min`, which cannot happen for the min-cost node
+                        // itself - so `open` must be empty and the incumbent
+                        // is already optimal.
+                        break 'search incumbent;
+                    };
+
+                    ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        best_cost: current_focal_node.cost,
+                        lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: focal.len(),
+                        ..Default::default()
                     });
-                }
+                    debug!(
+                        "Node Id: {:?}, conflicts: {:?}",
+                        current_focal_node.node_id, current_focal_node.conflicts
+                    );
+                    let current_open_node = current_focal_node.to_open_node();
+                    let old_f_min: usize =
+                        open.first().unwrap().low_level_f_min_agents.iter().sum();
+
+                    open.remove(&current_open_node);
 
-                // Maintain the focal list
-                if !open.is_empty() {
-                    let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
-                    if old_f_min < new_f_min {
-                        open.iter().for_each(|node| {
-                            if node.cost as f64 > subopt_factor * old_f_min as f64
-                                && node.cost as f64 <= subopt_factor * new_f_min as f64
-                            {
-                                focal.insert(node.to_focal_node());
+                    let conflict = if config.op_prioritize_conflicts {
+                        select_prioritized_conflict(
+                            &current_open_node.conflicts,
+                            current_open_node.agents.len(),
+                        )
+                        .or_else(|| current_open_node.conflicts.first())
+                    } else {
+                        current_open_node.conflicts.first()
+                    };
+
+                    if let Some(conflict) = conflict {
+                        debug!("conflict: {conflict:?}");
+                        let mut bypass = false;
+
+                        let (child_1, child_2) = if config.op_parallel_expansion {
+                            let mut stats_1 = Stats::default();
+                            let mut stats_2 = Stats::default();
+                            let mut cache_1 = cache.clone();
+                            let mut cache_2 = cache.clone();
+                            let (child_1, child_2) = rayon::join(
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        true,
+                                        &self.map,
+                                        config,
+                                        &mut stats_1,
+                                        map_fingerprint,
+                                        &mut cache_1,
+                                    )
+                                },
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        false,
+                                        &self.map,
+                                        config,
+                                        &mut stats_2,
+                                        map_fingerprint,
+                                        &mut cache_2,
+                                    )
+                                },
+                            );
+                            self.stats.merge(&stats_1);
+                            self.stats.merge(&stats_2);
+                            cache.merge(cache_1);
+                            cache.merge(cache_2);
+                            (child_1, child_2)
+                        } else {
+                            let child_1 = current_open_node.update_constraint(
+                                conflict,
+                                true,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            let child_2 = current_open_node.update_constraint(
+                                conflict,
+                                false,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            (child_1, child_2)
+                        };
+
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_1 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    conflict.agent_1,
+                                    subopt_factor,
+                                ) {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_open_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_1),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
                             }
-                        });
+                        }
+
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_2 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    conflict.agent_2,
+                                    subopt_factor,
+                                ) {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_open_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_2),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
+                            }
+                        }
+
+                        if bypass {
+                            continue;
+                        }
+
+                        if let Some(child) = child_1 {
+                            debug!(
+                                "Expand Node {:?} into Node {:?}",
+                                current_open_node.node_id, child.node_id
+                            );
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
+                        }
+
+                        if let Some(child) = child_2 {
+                            debug!(
+                                "Expand Node {:?} into Node {:?}",
+                                current_open_node.node_id, child.node_id
+                            );
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
+                        }
+                    } else {
+                        // No conflicts: this is a new incumbent. Report it,
+                        // tighten the bound, and keep expanding the same
+                        // tree instead of returning.
+                        debug!("Find incumbent solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_open_node.cost;
+                        self.stats.print(config);
+
+                        let incumbent_cost = current_open_node.cost;
+                        let solution = Solution {
+                            paths: current_open_node.paths,
+                            ..Default::default()
+                        };
+                        on_improved(&solution, &self.stats);
+                        incumbent = Some(solution);
+
+                        if subopt_factor <= 1.0 + f64::EPSILON {
+                            break 'search incumbent;
+                        }
+                        subopt_factor = (1.0 + (subopt_factor - 1.0) * decay).max(1.0);
+
+                        if open.is_empty() {
+                            break 'search incumbent;
+                        }
+                        let global_f_min: usize =
+                            open.first().unwrap().low_level_f_min_agents.iter().sum();
+                        if global_f_min as f64 >= incumbent_cost as f64 {
+                            break 'search incumbent;
+                        }
+                        focal.clear();
+                        for node in open.iter() {
+                            if node.cost as f64 <= subopt_factor * global_f_min as f64 {
+                                focal.insert(node.to_focal_node(config));
+                            }
+                        }
+                        continue;
+                    }
+
+                    // Maintain the focal list
+                    if !open.is_empty() {
+                        let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
+                        if old_f_min < new_f_min {
+                            open.iter().for_each(|node| {
+                                if node.cost as f64 > subopt_factor * old_f_min as f64
+                                    && node.cost as f64 <= subopt_factor * new_f_min as f64
+                                {
+                                    focal.insert(node.to_focal_node(config));
+                                }
+                            });
+                        }
                     }
                 }
             }
 
-            None
+            incumbent
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
+            }
+        }
+
+        result
+    }
+}
+
+impl Solver for ACBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, None, None))
         } else {
-            None
+            self.solve_inner(config, None, None)
+        }
+    }
+
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, progress, stop))
+        } else {
+            self.solve_inner(config, progress, stop)
+        }
+    }
+
+    /// Keeps expanding the same high-level tree past the first conflict-free
+    /// node it finds, tightening `subopt_factor` towards 1.0 by
+    /// `config.anytime_decay` each time and reporting every improved
+    /// incumbent to `on_improved`, until the bound reaches 1.0, `open` is
+    /// exhausted, or `config.deadline` elapses.
+    fn solve_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let Some(decay) = config.anytime_decay else {
+            return self.solve_with_progress(config, progress, stop);
+        };
+
+        let deadline_start = Instant::now();
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || {
+                self.solve_inner_anytime(
+                    config,
+                    progress,
+                    stop,
+                    deadline_start,
+                    decay,
+                    on_improved,
+                )
+            })
+        } else {
+            self.solve_inner_anytime(config, progress, stop, deadline_start, decay, on_improved)
         }
     }
 }
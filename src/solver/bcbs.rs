@@ -1,6 +1,7 @@
-use super::comm::HighLevelOpenNode;
-use super::Solver;
-use crate::common::{Agent, Solution};
+use super::comm::{HighLevelFocalNode, HighLevelOpenNode};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{AnytimeCallback, SolveProgress, Solver, StopFlag};
+use crate::common::{trivial_cost_lower_bounds, Agent, Solution};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
@@ -9,6 +10,30 @@ use std::collections::{BTreeSet, HashSet};
 use std::time::Instant;
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+use rayon::prelude::*;
+
+/// Caps `open` (and, symmetrically, `focal`) to the best `beam_width` nodes
+/// by cost, dropping the worst-cost tail of the `BTreeSet`. Once a prune
+/// actually occurs, BCBS's suboptimality guarantee no longer holds for this
+/// run; `stats.high_level_pruned_nodes` records how many nodes were dropped
+/// so callers can tell a beam-induced failure from a genuinely unsolvable
+/// instance.
+fn apply_beam_width(
+    open: &mut BTreeSet<HighLevelOpenNode>,
+    focal: &mut BTreeSet<HighLevelFocalNode>,
+    beam_width: usize,
+    stats: &mut Stats,
+) {
+    while open.len() > beam_width {
+        let Some(worst) = open.pop_last() else {
+            break;
+        };
+        focal.remove(&worst.to_focal_node());
+        stats.high_level_pruned_nodes += 1;
+    }
+}
+
 pub struct BCBS {
     agents: Vec<Agent>,
     map: Map,
@@ -25,15 +50,19 @@ impl BCBS {
             subopt_factor,
         }
     }
-}
 
-impl Solver for BCBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
 
         let mut open = BTreeSet::new();
         let mut focal = BTreeSet::new();
         let mut closed = HashSet::new();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
         if let Some(root) = HighLevelOpenNode::new(
             &self.agents,
@@ -43,65 +72,153 @@ impl Solver for BCBS {
         ) {
             open.insert(root.clone());
             focal.insert(root.to_focal_node());
-            while let Some(current_focal_node) = focal.pop_first() {
-                let current_open_node = current_focal_node.to_open_node();
-                let old_f_min = current_open_node.cost;
-
-                open.remove(&current_open_node);
-                closed.insert(current_open_node.clone());
-                if let Some(conflict) = current_open_node.conflicts.first() {
-                    if let Some(child_1) = current_open_node.update_constraint(
-                        conflict,
-                        true,
-                        &self.map,
-                        self.subopt_factor.1,
-                        &mut self.stats,
-                    ) {
+            while !focal.is_empty() {
+                if is_stopped(&stop) {
+                    return None;
+                }
+
+                // Drain up to `config.num_threads` frontier nodes to expand together.
+                let batch_size = config.num_threads.max(1);
+                let mut batch = Vec::with_capacity(batch_size);
+                while batch.len() < batch_size {
+                    match focal.pop_first() {
+                        Some(node) => batch.push(node),
+                        None => break,
+                    }
+                }
+
+                let mut jobs = Vec::with_capacity(batch.len());
+                for current_focal_node in &batch {
+                    ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        best_cost: current_focal_node.cost,
+                        lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: focal.len(),
+                        ..Default::default()
+                    });
+                    let current_open_node = current_focal_node.to_open_node();
+                    let old_f_min = current_open_node.cost;
+
+                    open.remove(&current_open_node);
+                    closed.insert(current_open_node.clone());
+                    if let Some(conflict) = current_open_node.conflicts.first() {
+                        jobs.push((current_open_node, conflict.clone(), old_f_min));
+                    } else {
+                        // No conflicts, return solution
+                        debug!("Find solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_focal_node.cost;
+                        let (soc_lb, _) = trivial_cost_lower_bounds(&self.agents, &self.map);
+                        self.stats.soc_lb = Some(soc_lb);
+
+                        self.stats.print(config);
+                        return Some(Solution {
+                            paths: current_focal_node.paths.clone(),
+                            ..Default::default()
+                        });
+                    }
+                }
+
+                // Under `config.op_parallel_expansion` the two
+                // `update_constraint` replans run concurrently via
+                // `rayon::join`, each against its own `Stats` folded back in
+                // afterwards (see `CBS`/`LBCBS`'s identical split). This
+                // nests inside the batch-level `jobs.par_iter()` below;
+                // rayon's work-stealing scheduler handles that fine. Root
+                // construction in `HighLevelOpenNode::new` stays sequential
+                // for BCBS regardless: it's a focal solver, so each agent's
+                // `a_star_search` threads the prior agents' `&paths` into
+                // the focal heuristic and can't be planned independently.
+                let expand_one = |(node, conflict, _): &(HighLevelOpenNode, _, usize)| {
+                    if config.op_parallel_expansion {
+                        let mut stats_1 = Stats::default();
+                        let mut stats_2 = Stats::default();
+                        let (child_1, child_2) = rayon::join(
+                            || {
+                                node.update_constraint(
+                                    conflict,
+                                    true,
+                                    &self.map,
+                                    self.subopt_factor.1,
+                                    &mut stats_1,
+                                )
+                            },
+                            || {
+                                node.update_constraint(
+                                    conflict,
+                                    false,
+                                    &self.map,
+                                    self.subopt_factor.1,
+                                    &mut stats_2,
+                                )
+                            },
+                        );
+                        (child_1, child_2, stats_1, stats_2)
+                    } else {
+                        let mut stats_1 = Stats::default();
+                        let mut stats_2 = Stats::default();
+                        let child_1 = node.update_constraint(
+                            conflict,
+                            true,
+                            &self.map,
+                            self.subopt_factor.1,
+                            &mut stats_1,
+                        );
+                        let child_2 = node.update_constraint(
+                            conflict,
+                            false,
+                            &self.map,
+                            self.subopt_factor.1,
+                            &mut stats_2,
+                        );
+                        (child_1, child_2, stats_1, stats_2)
+                    }
+                };
+
+                let results: Vec<_> = if config.num_threads > 1 {
+                    jobs.par_iter().map(expand_one).collect()
+                } else {
+                    jobs.iter().map(expand_one).collect()
+                };
+
+                for ((_, _, old_f_min), (child_1, child_2, stats_1, stats_2)) in
+                    jobs.iter().zip(results)
+                {
+                    self.stats.merge(&stats_1);
+                    self.stats.merge(&stats_2);
+
+                    if let Some(child_1) = child_1 {
                         if !closed.contains(&child_1) {
-                            open.insert(child_1.clone());
                             self.stats.high_level_expand_nodes += 1;
 
                             if child_1.cost as f64
-                                <= (old_f_min as f64 * self.subopt_factor.0.unwrap())
+                                <= (*old_f_min as f64 * self.subopt_factor.0.unwrap())
                             {
                                 focal.insert(child_1.to_focal_node());
                             }
+                            open.insert(child_1);
                         }
                     }
 
-                    if let Some(child_2) = current_open_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        self.subopt_factor.1,
-                        &mut self.stats,
-                    ) {
+                    if let Some(child_2) = child_2 {
                         if !closed.contains(&child_2) {
-                            open.insert(child_2.clone());
                             self.stats.high_level_expand_nodes += 1;
 
                             if child_2.cost as f64
-                                <= (old_f_min as f64 * self.subopt_factor.0.unwrap())
+                                <= (*old_f_min as f64 * self.subopt_factor.0.unwrap())
                             {
                                 focal.insert(child_2.to_focal_node());
                             }
+                            open.insert(child_2);
                         }
                     }
-                } else {
-                    // No conflicts, return solution
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_focal_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_focal_node.paths,
-                    });
                 }
 
                 // Maintain the focal list
                 if !open.is_empty() {
+                    let old_f_min = jobs.first().map(|(node, _, _)| node.cost).unwrap_or(0);
                     let new_f_min = open.first().unwrap().cost;
                     if old_f_min < new_f_min {
                         open.iter().for_each(|node| {
@@ -114,6 +231,10 @@ impl Solver for BCBS {
                         });
                     }
                 }
+
+                if let Some(beam_width) = config.beam_width {
+                    apply_beam_width(&mut open, &mut focal, beam_width, &mut self.stats);
+                }
             }
 
             None
@@ -122,3 +243,71 @@ impl Solver for BCBS {
         }
     }
 }
+
+impl Solver for BCBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        self.solve_inner(config, None, None)
+    }
+
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        self.solve_inner(config, progress, stop)
+    }
+
+    /// Re-solves with `self.subopt_factor.0` shrinking towards 1.0 by
+    /// `config.anytime_decay` each round, reporting every improved solution
+    /// to `on_improved`. BCBS keeps no low-level path cache, so every round
+    /// repeats its low-level searches. See `HBCBS::solve_anytime` for the
+    /// beam-widening retry that kicks in when `config.beam_width` is set.
+    fn solve_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        mut on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let start = Instant::now();
+        let mut round_config = config.clone();
+        let mut best = None;
+
+        loop {
+            let Some(solution) =
+                self.solve_inner(&round_config, progress.clone(), stop.clone())
+            else {
+                // A beam-pruned round finding nothing isn't proof the
+                // instance is unsolvable (see `apply_beam_width`), so widen
+                // and retry rather than giving up. Plain BCBS
+                // (`config.beam_width` unset) has nothing to widen.
+                let Some(beam_width) = round_config.beam_width else {
+                    break;
+                };
+                if is_stopped(&stop) || config.deadline.is_some_and(|d| start.elapsed() >= d) {
+                    break;
+                }
+                self.stats.beam_widen_rounds += 1;
+                round_config.beam_width = Some(beam_width.saturating_mul(2));
+                continue;
+            };
+            on_improved(&solution, &self.stats);
+            best = Some(solution);
+
+            let Some(decay) = config.anytime_decay else {
+                break;
+            };
+            let bound = self.subopt_factor.0.unwrap();
+            if bound <= 1.0 + f64::EPSILON
+                || is_stopped(&stop)
+                || config.deadline.is_some_and(|d| start.elapsed() >= d)
+            {
+                break;
+            }
+            self.subopt_factor.0 = Some((1.0 + (bound - 1.0) * decay).max(1.0));
+        }
+
+        best
+    }
+}
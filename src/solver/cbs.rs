@@ -1,13 +1,35 @@
-use super::Solver;
-use crate::common::{Agent, CardinalType, HighLevelOpenNode, Solution};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{admit_node, with_capped_thread_pool, AnytimeCallback, SolveProgress, Solver, StopFlag};
+use crate::common::{
+    select_prioritized_conflict, Agent, CardinalType, HighLevelOpenNode, PairWeightCache,
+    PathCache, Solution,
+};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
 
-use std::collections::BTreeSet;
-use std::time::Instant;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+
+/// Caps `open` to the best `beam_width` nodes by cost, dropping the
+/// worst-cost tail of the `BTreeSet`. Once a prune actually occurs, CBS's
+/// optimality guarantee no longer holds for this run: the true-optimal node
+/// may have been sitting in the dropped tail. `stats.high_level_pruned_nodes`
+/// records how many nodes were dropped so callers can tell a beam-induced
+/// `None`/suboptimal result from a genuinely unsolvable instance. The node
+/// just popped off `open` for expansion (including the root) is never in
+/// `open` while this runs, so it can never be pruned.
+fn apply_beam_width(open: &mut BTreeSet<HighLevelOpenNode>, beam_width: usize, stats: &mut Stats) {
+    while open.len() > beam_width {
+        if open.pop_last().is_some() {
+            stats.high_level_pruned_nodes += 1;
+        }
+    }
+}
+
 pub struct CBS {
     agents: Vec<Agent>,
     map: Map,
@@ -22,147 +44,416 @@ impl CBS {
             stats: Stats::default(),
         }
     }
-}
 
-impl Solver for CBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    /// When `config.beam_width` is set, `open` is capped to the best
+    /// `beam_width` nodes by cost after every round of child insertions (see
+    /// `apply_beam_width`). This bounds high-level memory/time at the cost of
+    /// CBS's completeness and optimality guarantees: a pruned run may return
+    /// a suboptimal solution, or `None` for an instance that is actually
+    /// solvable. The root node is inserted and popped before any pruning
+    /// runs, so it is never itself a candidate for pruning.
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
         let mut global_high_level_node_id = 0;
         let mut open = BTreeSet::new();
+        let mut duplicate_signatures = HashMap::new();
+        let mut wdg_cache = PairWeightCache::default();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
-        if let Some(root) =
-            HighLevelOpenNode::new(&self.agents, &self.map, config, "cbs", &mut self.stats)
-        {
-            open.insert(root);
-            while let Some(current_node) = open.pop_first() {
-                debug!(
-                    "Node Id: {:?}, conflicts: {:?}",
-                    current_node.node_id, current_node.conflicts
-                );
-                let conflict = if config.op_prioritize_conflicts {
-                    current_node
-                        .conflicts
-                        .iter()
-                        .find(|c| c.cardinal_type == CardinalType::Cardinal)
-                        .or_else(|| {
-                            current_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::SemiCardinal)
-                        })
-                        .or_else(|| {
-                            current_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::NonCardinal)
-                        })
-                        .or_else(|| current_node.conflicts.first())
-                } else {
-                    current_node.conflicts.first()
-                };
-
-                if let Some(conflict) = conflict {
-                    debug!("conflict: {conflict:?}");
-                    let mut bypass = false;
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
 
-                    global_high_level_node_id += 1;
-                    let child_1 = current_node.update_constraint(
-                        conflict,
-                        true,
+        let result = 'search: {
+            if let Some(mut root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "cbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                if config.op_wdg_heuristic {
+                    root.apply_wdg_heuristic(
                         &self.map,
                         config,
-                        global_high_level_node_id,
                         &mut self.stats,
+                        map_fingerprint,
+                        &mut cache,
+                        &mut wdg_cache,
                     );
+                }
+                open.insert(root);
+                while let Some(current_node) = open.pop_first() {
+                    if is_stopped(&stop) {
+                        break 'search None;
+                    }
 
-                    if config.op_bypass_conflicts
-                        && conflict.cardinal_type != CardinalType::Cardinal
-                    {
-                        if let Some(ref child) = child_1 {
-                            if child.cost == current_node.cost
-                                && child.conflicts.len() < current_node.conflicts.len()
-                            {
+                    // `config.time_limit_ms`/`config.high_level_node_limit`
+                    // bound this loop for batch runs where a single
+                    // pathological instance shouldn't hang the whole run.
+                    // Unlike `is_stopped` above (which gives up entirely),
+                    // exceeding either budget returns `current_node` itself
+                    // as a best-effort incumbent: `open` is a priority queue
+                    // popped in non-decreasing cost order, so `current_node`
+                    // is the lowest-cost complete (but possibly
+                    // conflict-bearing) assignment discovered so far.
+                    // Returned with `Solution::partial` set so callers know
+                    // not to treat a `Solution::verify` failure here as the
+                    // hard bug it would be for a search-complete result.
+                    let time_limit_exceeded = config.time_limit_ms.is_some_and(|limit_ms| {
+                        total_solve_start_time.elapsed() >= Duration::from_millis(limit_ms)
+                    });
+                    let node_limit_exceeded = config
+                        .high_level_node_limit
+                        .is_some_and(|limit| global_high_level_node_id >= limit);
+                    if time_limit_exceeded || node_limit_exceeded {
+                        debug!(
+                            "Node Id: {:?}, budget exhausted (time_limit_exceeded: {time_limit_exceeded}, node_limit_exceeded: {node_limit_exceeded}), returning incumbent",
+                            current_node.node_id
+                        );
+                        self.stats.budget_exhausted = true;
+                        self.stats.time_ms = total_solve_start_time.elapsed().as_micros() as usize;
+                        self.stats.costs = current_node.cost;
+                        self.stats.print(config);
+                        break 'search Some(Solution {
+                            paths: current_node.paths,
+                            partial: true,
+                        });
+                    }
+
+                    // Dropping the receiver cancels the solve the same way
+                    // setting `stop` does, so a caller that only has a
+                    // `Sender<SolveProgress>` handy (no `StopFlag`) can still
+                    // cancel by dropping it.
+                    if ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        low_level_expanded: self.stats.low_level_expand_open_nodes
+                            + self.stats.low_level_expand_focal_nodes,
+                        best_cost: current_node.cost,
+                        lower_bound: current_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: 0,
+                        elapsed_ms: total_solve_start_time.elapsed().as_millis() as usize,
+                    }) {
+                        break 'search None;
+                    }
+                    debug!(
+                        "Node Id: {:?}, conflicts: {:?}",
+                        current_node.node_id, current_node.conflicts
+                    );
+                    let conflict = if config.op_prioritize_conflicts {
+                        select_prioritized_conflict(&current_node.conflicts, current_node.agents.len())
+                            .or_else(|| current_node.conflicts.first())
+                    } else {
+                        current_node.conflicts.first()
+                    };
+
+                    if let Some(conflict) = conflict {
+                        debug!("conflict: {conflict:?}");
+                        let mut bypass = false;
+
+                        global_high_level_node_id += 1;
+                        let child_1_id = global_high_level_node_id;
+                        global_high_level_node_id += 1;
+                        let child_2_id = global_high_level_node_id;
+
+                        let (child_1, child_2) = if config.op_parallel_expansion {
+                            let mut stats_1 = Stats::default();
+                            let mut stats_2 = Stats::default();
+                            let mut cache_1 = cache.clone();
+                            let mut cache_2 = cache.clone();
+                            let (child_1, child_2) = rayon::join(
+                                || {
+                                    current_node.update_constraint(
+                                        conflict,
+                                        true,
+                                        &self.map,
+                                        config,
+                                        child_1_id,
+                                        &mut stats_1,
+                                        map_fingerprint,
+                                        &mut cache_1,
+                                    )
+                                },
+                                || {
+                                    current_node.update_constraint(
+                                        conflict,
+                                        false,
+                                        &self.map,
+                                        config,
+                                        child_2_id,
+                                        &mut stats_2,
+                                        map_fingerprint,
+                                        &mut cache_2,
+                                    )
+                                },
+                            );
+                            self.stats.merge(&stats_1);
+                            self.stats.merge(&stats_2);
+                            cache.merge(cache_1);
+                            cache.merge(cache_2);
+                            (child_1, child_2)
+                        } else {
+                            let child_1 = current_node.update_constraint(
+                                conflict,
+                                true,
+                                &self.map,
+                                config,
+                                child_1_id,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            let child_2 = current_node.update_constraint(
+                                conflict,
+                                false,
+                                &self.map,
+                                config,
+                                child_2_id,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            (child_1, child_2)
+                        };
+
+                        let mut child_1 = child_1;
+                        let mut child_2 = child_2;
+                        if config.op_wdg_heuristic {
+                            if config.op_parallel_expansion {
+                                // Like the `update_constraint` join above,
+                                // each branch works against its own cache
+                                // clones (a joint replan per agent pair is
+                                // just as expensive as the low-level search
+                                // `update_constraint` already parallelizes)
+                                // and the discovered entries are folded back
+                                // into the shared caches afterwards.
+                                let mut stats_1 = Stats::default();
+                                let mut stats_2 = Stats::default();
+                                let mut cache_1 = cache.clone();
+                                let mut cache_2 = cache.clone();
+                                let mut wdg_cache_1 = wdg_cache.clone();
+                                let mut wdg_cache_2 = wdg_cache.clone();
+                                rayon::join(
+                                    || {
+                                        if let Some(child) = child_1.as_mut() {
+                                            child.apply_wdg_heuristic(
+                                                &self.map,
+                                                config,
+                                                &mut stats_1,
+                                                map_fingerprint,
+                                                &mut cache_1,
+                                                &mut wdg_cache_1,
+                                            );
+                                        }
+                                    },
+                                    || {
+                                        if let Some(child) = child_2.as_mut() {
+                                            child.apply_wdg_heuristic(
+                                                &self.map,
+                                                config,
+                                                &mut stats_2,
+                                                map_fingerprint,
+                                                &mut cache_2,
+                                                &mut wdg_cache_2,
+                                            );
+                                        }
+                                    },
+                                );
+                                self.stats.merge(&stats_1);
+                                self.stats.merge(&stats_2);
+                                cache.merge(cache_1);
+                                cache.merge(cache_2);
+                                wdg_cache.merge(wdg_cache_1);
+                                wdg_cache.merge(wdg_cache_2);
+                            } else {
+                                for child in [child_1.as_mut(), child_2.as_mut()]
+                                    .into_iter()
+                                    .flatten()
+                                {
+                                    child.apply_wdg_heuristic(
+                                        &self.map,
+                                        config,
+                                        &mut self.stats,
+                                        map_fingerprint,
+                                        &mut cache,
+                                        &mut wdg_cache,
+                                    );
+                                }
+                            }
+                        }
+
+                        if config.op_bypass_conflicts
+                            && conflict.cardinal_type != CardinalType::Cardinal
+                        {
+                            if let Some(ref child) = child_1 {
+                                if child.cost == current_node.cost
+                                    && child.conflicts.len() < current_node.conflicts.len()
+                                {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_node.update_bypass_node(child, conflict.agent_1),
+                                    );
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
+                            }
+                        }
+
+                        if config.op_bypass_conflicts
+                            && conflict.cardinal_type != CardinalType::Cardinal
+                        {
+                            if let Some(ref child) = child_2 {
+                                if child.cost == current_node.cost
+                                    && child.conflicts.len() < current_node.conflicts.len()
+                                {
+                                    debug!(
+                                        "Bypass Node {:?} into Node {:?}",
+                                        current_node.node_id, child.node_id
+                                    );
+                                    open.insert(
+                                        current_node.update_bypass_node(child, conflict.agent_2),
+                                    );
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
+                            }
+                        }
+
+                        if bypass {
+                            continue;
+                        }
+
+                        if let Some(child) = child_1 {
+                            if admit_node(config, &mut duplicate_signatures, &child) {
                                 debug!(
-                                    "Bypass Node {:?} into Node {:?}",
+                                    "Expand Node {:?} into Node {:?}",
                                     current_node.node_id, child.node_id
                                 );
-                                open.insert(
-                                    current_node.update_bypass_node(child, conflict.agent_1),
-                                );
+                                open.insert(child);
                                 self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
                             }
                         }
-                    }
 
-                    global_high_level_node_id += 1;
-                    let child_2 = current_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        config,
-                        global_high_level_node_id,
-                        &mut self.stats,
-                    );
-
-                    if config.op_bypass_conflicts
-                        && conflict.cardinal_type != CardinalType::Cardinal
-                    {
-                        if let Some(ref child) = child_2 {
-                            if child.cost == current_node.cost
-                                && child.conflicts.len() < current_node.conflicts.len()
-                            {
+                        if let Some(child) = child_2 {
+                            if admit_node(config, &mut duplicate_signatures, &child) {
                                 debug!(
-                                    "Bypass Node {:?} into Node {:?}",
+                                    "Expand Node {:?} into Node {:?}",
                                     current_node.node_id, child.node_id
                                 );
-                                open.insert(
-                                    current_node.update_bypass_node(child, conflict.agent_2),
-                                );
+                                open.insert(child);
                                 self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
                             }
                         }
-                    }
-
-                    if bypass {
-                        continue;
-                    }
 
-                    if let Some(child) = child_1 {
-                        debug!(
-                            "Expand Node {:?} into Node {:?}",
-                            current_node.node_id, child.node_id
-                        );
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
-                    }
+                        if let Some(beam_width) = config.beam_width {
+                            apply_beam_width(&mut open, beam_width, &mut self.stats);
+                        }
+                    } else {
+                        // No conflicts, return solution.
+                        debug!("Find solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_node.cost;
 
-                    if let Some(child) = child_2 {
-                        debug!(
-                            "Expand Node {:?} into Node {:?}",
-                            current_node.node_id, child.node_id
-                        );
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
+                        self.stats.print(config);
+                        break 'search Some(Solution {
+                            paths: current_node.paths,
+                            ..Default::default()
+                        });
                     }
-                } else {
-                    // No conflicts, return solution.
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_node.paths,
-                    });
                 }
+
+                None
+            } else {
+                None
+            }
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
             }
+        }
+
+        result
+    }
+}
+
+impl Solver for CBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, None, None))
+        } else {
+            self.solve_inner(config, None, None)
+        }
+    }
 
-            None
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, progress, stop))
         } else {
-            None
+            self.solve_inner(config, progress, stop)
+        }
+    }
+
+    /// Plain CBS has no suboptimality bound to decay, so unlike
+    /// `BCBS`/`HBCBS` the only thing this can usefully do across rounds is
+    /// the beam-widening retry: a round pruned by `config.beam_width` that
+    /// finds no solution isn't proof the instance is unsolvable (see
+    /// `apply_beam_width`), so widen and retry rather than giving up.
+    /// `config.beam_width` unset has nothing to widen, so it falls straight
+    /// through to a single `solve_with_progress` call.
+    fn solve_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        mut on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let start = Instant::now();
+        let mut round_config = config.clone();
+
+        loop {
+            let result = if round_config.op_parallel_expansion {
+                with_capped_thread_pool(&round_config, || {
+                    self.solve_inner(&round_config, progress.clone(), stop.clone())
+                })
+            } else {
+                self.solve_inner(&round_config, progress.clone(), stop.clone())
+            };
+
+            let Some(solution) = result else {
+                let Some(beam_width) = round_config.beam_width else {
+                    return None;
+                };
+                if is_stopped(&stop) || config.deadline.is_some_and(|d| start.elapsed() >= d) {
+                    return None;
+                }
+                self.stats.beam_widen_rounds += 1;
+                round_config.beam_width = Some(beam_width.saturating_mul(2));
+                continue;
+            };
+            on_improved(&solution, &self.stats);
+            return Some(solution);
         }
     }
 }
@@ -1,5 +1,10 @@
-use super::{sub_optimal_bypass_comparation, Solver};
-use crate::common::{Agent, CardinalType, HighLevelOpenNode, Solution};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{
+    sub_optimal_bypass_comparation, with_capped_thread_pool, SolveProgress, Solver, StopFlag,
+};
+use crate::common::{
+    select_prioritized_conflict, Agent, CardinalType, HighLevelOpenNode, PathCache, Solution,
+};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
@@ -8,6 +13,8 @@ use std::collections::BTreeSet;
 use std::time::Instant;
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+
 pub struct DECBS {
     agents: Vec<Agent>,
     map: Map,
@@ -22,159 +29,250 @@ impl DECBS {
             stats: Stats::default(),
         }
     }
-}
 
-impl Solver for DECBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
         let subopt_factor = config.sub_optimal.1.unwrap();
 
         let mut open = BTreeSet::new();
         let mut focal = BTreeSet::new();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
-        if let Some(root) =
-            HighLevelOpenNode::new(&self.agents, &self.map, config, "decbs", &mut self.stats)
-        {
-            open.insert(root.clone());
-            focal.insert(root.to_focal_node());
-            while let Some(current_focal_node) = focal.pop_first() {
-                let current_open_node = current_focal_node.to_open_node();
-                debug!("current node : {current_open_node:?}");
-                let old_f_min: usize = open.first().unwrap().low_level_f_min_agents.iter().sum();
-
-                open.remove(&current_open_node);
-
-                let conflict = if config.op_prioritize_conflicts {
-                    current_open_node
-                        .conflicts
-                        .iter()
-                        .find(|c| c.cardinal_type == CardinalType::Cardinal)
-                        .or_else(|| {
-                            current_open_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::SemiCardinal)
-                        })
-                        .or_else(|| {
-                            current_open_node
-                                .conflicts
-                                .iter()
-                                .find(|c| c.cardinal_type == CardinalType::NonCardinal)
-                        })
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
+
+        let result = 'search: {
+            if let Some(root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "decbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                open.insert(root.clone());
+                focal.insert(root.to_focal_node(config));
+                while let Some(current_focal_node) = focal.pop_first() {
+                    if is_stopped(&stop) {
+                        break 'search None;
+                    }
+                    ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        best_cost: current_focal_node.cost,
+                        lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: focal.len(),
+                        ..Default::default()
+                    });
+                    let current_open_node = current_focal_node.to_open_node();
+                    debug!("current node : {current_open_node:?}");
+                    let old_f_min: usize =
+                        open.first().unwrap().low_level_f_min_agents.iter().sum();
+
+                    open.remove(&current_open_node);
+
+                    let conflict = if config.op_prioritize_conflicts {
+                        select_prioritized_conflict(
+                            &current_open_node.conflicts,
+                            current_open_node.agents.len(),
+                        )
                         .or_else(|| {
                             current_open_node
                                 .conflicts
                                 .iter()
                                 .find(|c| c.cardinal_type == CardinalType::Unknown)
                         })
-                } else {
-                    current_open_node.conflicts.first()
-                };
-
-                if let Some(conflict) = conflict {
-                    debug!("conflict: {conflict:?}");
-                    let mut bypass = false;
-
-                    let child_1 = current_open_node.update_constraint(
-                        conflict,
-                        true,
-                        &self.map,
-                        config,
-                        &mut self.stats,
-                    );
-
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_1 {
-                            if sub_optimal_bypass_comparation(
-                                &current_open_node,
-                                child,
-                                subopt_factor,
-                            ) {
-                                open.insert(
-                                    current_open_node.update_bypass_node(child, conflict.agent_1),
-                                );
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
+                    } else {
+                        current_open_node.conflicts.first()
+                    };
+
+                    if let Some(conflict) = conflict {
+                        debug!("conflict: {conflict:?}");
+                        let mut bypass = false;
+
+                        let (child_1, child_2) = if config.op_parallel_expansion {
+                            let mut stats_1 = Stats::default();
+                            let mut stats_2 = Stats::default();
+                            let mut cache_1 = cache.clone();
+                            let mut cache_2 = cache.clone();
+                            let (child_1, child_2) = rayon::join(
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        true,
+                                        &self.map,
+                                        config,
+                                        &mut stats_1,
+                                        map_fingerprint,
+                                        &mut cache_1,
+                                    )
+                                },
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        false,
+                                        &self.map,
+                                        config,
+                                        &mut stats_2,
+                                        map_fingerprint,
+                                        &mut cache_2,
+                                    )
+                                },
+                            );
+                            self.stats.merge(&stats_1);
+                            self.stats.merge(&stats_2);
+                            cache.merge(cache_1);
+                            cache.merge(cache_2);
+                            (child_1, child_2)
+                        } else {
+                            let child_1 = current_open_node.update_constraint(
+                                conflict,
+                                true,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            let child_2 = current_open_node.update_constraint(
+                                conflict,
+                                false,
+                                &self.map,
+                                config,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            (child_1, child_2)
+                        };
+
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_1 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    subopt_factor,
+                                ) {
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_1),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
                             }
                         }
-                    }
 
-                    let child_2 = current_open_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        config,
-                        &mut self.stats,
-                    );
-
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_2 {
-                            if sub_optimal_bypass_comparation(
-                                &current_open_node,
-                                child,
-                                subopt_factor,
-                            ) {
-                                open.insert(
-                                    current_open_node.update_bypass_node(child, conflict.agent_2),
-                                );
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                bypass = true;
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_2 {
+                                if sub_optimal_bypass_comparation(
+                                    &current_open_node,
+                                    child,
+                                    subopt_factor,
+                                ) {
+                                    open.insert(
+                                        current_open_node
+                                            .update_bypass_node(child, conflict.agent_2),
+                                    );
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    bypass = true;
+                                }
                             }
                         }
-                    }
-
-                    if bypass {
-                        continue;
-                    }
 
-                    if let Some(child) = child_1 {
-                        if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
-                            focal.insert(child.to_focal_node());
+                        if bypass {
+                            continue;
                         }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
-                    }
 
-                    if let Some(child) = child_2 {
-                        if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
-                            focal.insert(child.to_focal_node());
+                        if let Some(child) = child_1 {
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
                         }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
-                    }
-                } else {
-                    // No conflicts, return solution
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_open_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_open_node.paths,
-                    });
-                }
 
-                // Maintain the focal list
-                if !open.is_empty() {
-                    let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
-                    if old_f_min < new_f_min {
-                        open.iter().for_each(|node| {
-                            if node.cost as f64 > subopt_factor * old_f_min as f64
-                                && node.cost as f64 <= subopt_factor * new_f_min as f64
-                            {
-                                focal.insert(node.to_focal_node());
+                        if let Some(child) = child_2 {
+                            if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
                             }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
+                        }
+                    } else {
+                        // No conflicts, return solution
+                        debug!("Find solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_open_node.cost;
+
+                        self.stats.print(config);
+                        break 'search Some(Solution {
+                            paths: current_open_node.paths,
+                            ..Default::default()
                         });
                     }
+
+                    // Maintain the focal list
+                    if !open.is_empty() {
+                        let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
+                        if old_f_min < new_f_min {
+                            open.iter().for_each(|node| {
+                                if node.cost as f64 > subopt_factor * old_f_min as f64
+                                    && node.cost as f64 <= subopt_factor * new_f_min as f64
+                                {
+                                    focal.insert(node.to_focal_node(config));
+                                }
+                            });
+                        }
+                    }
                 }
             }
 
             None
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
+            }
+        }
+
+        result
+    }
+}
+
+impl Solver for DECBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, None, None))
         } else {
-            None
+            self.solve_inner(config, None, None)
+        }
+    }
+
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, progress, stop))
+        } else {
+            self.solve_inner(config, progress, stop)
         }
     }
 }
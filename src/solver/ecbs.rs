@@ -1,126 +1,424 @@
-use super::comm::HighLevelOpenNode;
-use super::Solver;
-use crate::common::{Agent, Solution};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{admit_node, with_capped_thread_pool, SolveProgress, Solver, StopFlag};
+use crate::common::{
+    select_prioritized_conflict, trivial_cost_lower_bounds, Agent, HighLevelFocalNode,
+    HighLevelOpenNode, PathCache, Solution,
+};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
 
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeSet, HashMap};
 use std::time::Instant;
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+
+/// Caps `open` (and, symmetrically, `focal`) to the best `beam_width` nodes
+/// by cost, dropping the worst-cost tail of the `BTreeSet`. Once a prune
+/// actually occurs, ECBS's bounded-suboptimality guarantee no longer holds
+/// for this run; `stats.high_level_pruned_nodes` records how many nodes were
+/// dropped so callers can tell a beam-induced failure from a genuinely
+/// unsolvable instance. See `HBCBS`/`BCBS`'s identically-named helper.
+fn apply_beam_width(
+    open: &mut BTreeSet<HighLevelOpenNode>,
+    focal: &mut BTreeSet<HighLevelFocalNode>,
+    beam_width: usize,
+    stats: &mut Stats,
+    config: &Config,
+) {
+    while open.len() > beam_width {
+        let Some(worst) = open.pop_last() else {
+            break;
+        };
+        focal.remove(&worst.to_focal_node(config));
+        stats.high_level_pruned_nodes += 1;
+    }
+}
+
+/// Expansions-per-unit scale factor for `RestartScheduler`: a raw Luby/
+/// geometric unit of "1" would restart almost every expansion, which suits
+/// SAT's per-conflict restarts but is far too eager for a tree search where
+/// a single expansion is much more expensive; this stretches one schedule
+/// unit out to a few dozen high-level expansions.
+const RESTART_UNIT: usize = 50;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RestartKind {
+    None,
+    Luby,
+    Geometric,
+}
+
+/// Restart-and-rephase schedule for ECBS's high-level search, modeled on
+/// CDCL SAT restarts: once `expansions_since_restart` reaches `threshold`,
+/// `focal` is cleared and fully re-seeded from `open` (`reseed_focal`),
+/// escaping whatever subtree the search has stagnated in. `open`/`closed`
+/// are left untouched, so every previously-expanded node and its children
+/// stay reachable; nothing about ECBS's bounded-suboptimality guarantee
+/// depends on which nodes happen to be in `focal` at a given moment, only
+/// on the `cost <= subopt_factor * f_min` membership test `reseed_focal`
+/// re-applies. "Rephasing" (biasing tie-breaks toward the incumbent's
+/// routes) falls out of the existing split structure for free: every child
+/// node is only a single-agent replan of its parent (`update_constraint`
+/// only touches the conflicting agent), so every node reachable from
+/// `open` already carries forward the rest of the incumbent's routes
+/// unchanged — there is no separate low-level re-derivation to bias.
+struct RestartScheduler {
+    kind: RestartKind,
+    restart_index: u64,
+    expansions_since_restart: usize,
+    threshold: usize,
+}
+
+impl RestartScheduler {
+    fn new(schedule: &str) -> Self {
+        let kind = match schedule {
+            "luby" => RestartKind::Luby,
+            "geometric" => RestartKind::Geometric,
+            _ => RestartKind::None,
+        };
+        let mut scheduler = RestartScheduler {
+            kind,
+            restart_index: 0,
+            expansions_since_restart: 0,
+            threshold: 0,
+        };
+        scheduler.threshold = scheduler.next_threshold();
+        scheduler
+    }
+
+    fn next_threshold(&self) -> usize {
+        match self.kind {
+            RestartKind::None => usize::MAX,
+            RestartKind::Luby => luby(self.restart_index + 1) as usize * RESTART_UNIT,
+            RestartKind::Geometric => RESTART_UNIT * (1usize << self.restart_index.min(32)),
+        }
+    }
+
+    fn record_expansion(&mut self) {
+        self.expansions_since_restart += 1;
+    }
+
+    /// Returns whether the schedule triggers now; if so, resets the
+    /// counter and advances to the next threshold. `none` never triggers.
+    fn should_restart(&mut self) -> bool {
+        if self.kind == RestartKind::None || self.expansions_since_restart < self.threshold {
+            return false;
+        }
+        self.expansions_since_restart = 0;
+        self.restart_index += 1;
+        self.threshold = self.next_threshold();
+        true
+    }
+}
+
+/// The `i`-th (1-indexed) term of the Luby sequence:
+/// 1,1,2,1,1,2,4,1,1,2,1,1,2,4,8,... the standard restart spacing used by
+/// CDCL SAT solvers, here repurposed to count high-level expansions instead
+/// of conflicts.
+fn luby(i: u64) -> u64 {
+    let mut k = 1;
+    while (1u64 << k) - 1 < i {
+        k += 1;
+    }
+    if i == (1u64 << k) - 1 {
+        1u64 << (k - 1)
+    } else {
+        luby(i - (1u64 << (k - 1)) + 1)
+    }
+}
+
+/// Clears `focal` and fully recomputes it from `open`: every node whose
+/// cost is within `subopt_factor` of the current best `low_level_f_min`
+/// sum is admitted. This is the same membership test the incremental
+/// "maintain the focal list" step below applies on an `f_min` increase,
+/// just run unconditionally as the re-seed half of a restart.
+fn reseed_focal(
+    open: &BTreeSet<HighLevelOpenNode>,
+    focal: &mut BTreeSet<HighLevelFocalNode>,
+    subopt_factor: f64,
+    config: &Config,
+) {
+    focal.clear();
+    if let Some(best) = open.first() {
+        let f_min: usize = best.low_level_f_min_agents.iter().sum();
+        for node in open {
+            if node.cost as f64 <= subopt_factor * f_min as f64 {
+                focal.insert(node.to_focal_node(config));
+            }
+        }
+    }
+}
+
 pub struct ECBS {
     agents: Vec<Agent>,
     map: Map,
     stats: Stats,
-    low_level_subopt_factor: Option<f64>, // The lattar one should be always none for HBCBS
 }
 
 impl ECBS {
-    pub fn new(agents: Vec<Agent>, map: &Map, subopt_factor: (Option<f64>, Option<f64>)) -> Self {
+    pub fn new(agents: Vec<Agent>, map: &Map) -> Self {
         ECBS {
             agents,
             map: map.clone(),
             stats: Stats::default(),
-            low_level_subopt_factor: subopt_factor.1,
         }
     }
-}
 
-impl Solver for ECBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
+        let subopt_factor = config.sub_optimal.1.unwrap();
+        let mut global_high_level_node_id = 0;
 
         let mut open = BTreeSet::new();
         let mut focal = BTreeSet::new();
-        let mut closed = HashSet::new();
-
-        if let Some(root) = HighLevelOpenNode::new(
-            &self.agents,
-            &self.map,
-            self.low_level_subopt_factor,
-            &mut self.stats,
-        ) {
-            open.insert(root.clone());
-            focal.insert(root.to_focal_node());
-            while let Some(current_focal_node) = focal.pop_first() {
-                let current_open_node = current_focal_node.to_open_node();
-                let old_f_min: usize = current_open_node.low_level_f_min_agents.iter().sum();
-
-                open.remove(&current_open_node);
-                closed.insert(current_open_node.clone());
-                if let Some(conflict) = current_open_node.conflicts.first() {
-                    if let Some(child_1) = current_open_node.update_constraint(
-                        conflict,
-                        true,
-                        &self.map,
-                        self.low_level_subopt_factor,
-                        &mut self.stats,
-                    ) {
-                        if !closed.contains(&child_1) {
-                            open.insert(child_1.clone());
-                            self.stats.high_level_expand_nodes += 1;
+        let mut closed: BTreeSet<HighLevelOpenNode> = BTreeSet::new();
+        let mut duplicate_signatures: HashMap<u64, usize> = HashMap::new();
+        let mut restarter = RestartScheduler::new(&config.restart_schedule);
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
-                            if child_1.cost as f64
-                                <= (old_f_min as f64 * self.low_level_subopt_factor.unwrap())
-                            {
-                                focal.insert(child_1.to_focal_node());
-                            }
-                        }
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
+
+        let result = 'search: {
+            if let Some(root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "ecbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                open.insert(root.clone());
+                focal.insert(root.to_focal_node(config));
+
+                while let Some(current_focal_node) = focal.pop_first() {
+                    if is_stopped(&stop) {
+                        break 'search None;
                     }
+                    ticker.maybe_emit(|| SolveProgress {
+                        high_level_expanded: self.stats.high_level_expand_nodes,
+                        best_cost: current_focal_node.cost,
+                        lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                        open_len: open.len(),
+                        focal_len: focal.len(),
+                        ..Default::default()
+                    });
 
-                    if let Some(child_2) = current_open_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        self.low_level_subopt_factor,
-                        &mut self.stats,
-                    ) {
-                        if !closed.contains(&child_2) {
-                            open.insert(child_2.clone());
-                            self.stats.high_level_expand_nodes += 1;
+                    let current_open_node = current_focal_node.to_open_node();
+                    let old_f_min: usize =
+                        open.first().unwrap().low_level_f_min_agents.iter().sum();
+
+                    open.remove(&current_open_node);
+                    closed.insert(current_open_node.clone());
+
+                    let conflict = if config.op_prioritize_conflicts {
+                        select_prioritized_conflict(
+                            &current_open_node.conflicts,
+                            current_open_node.agents.len(),
+                        )
+                        .or_else(|| current_open_node.conflicts.first())
+                    } else {
+                        current_open_node.conflicts.first()
+                    };
 
-                            if child_2.cost as f64
-                                <= (old_f_min as f64 * self.low_level_subopt_factor.unwrap())
+                    if let Some(conflict) = conflict {
+                        debug!("conflict: {conflict:?}");
+
+                        global_high_level_node_id += 1;
+                        let child_1_id = global_high_level_node_id;
+                        global_high_level_node_id += 1;
+                        let child_2_id = global_high_level_node_id;
+
+                        // Under `config.op_parallel_expansion` the two
+                        // `update_constraint` replans run concurrently via
+                        // `rayon::join`, each against its own `Stats`/
+                        // `PathCache` clone folded back in afterwards (see
+                        // `CBS`/`DECBS`'s identical split). The per-agent
+                        // low-level searches inside `HighLevelOpenNode::new`
+                        // stay sequential for ECBS even so: `"ecbs"` threads
+                        // each agent's `&paths` so far into the focal
+                        // heuristic, so root construction has a genuine
+                        // agent-to-agent dependency that `"cbs"`/`"hbcbs"`
+                        // (the only solvers root-parallelized there) don't.
+                        let (child_1, child_2) = if config.op_parallel_expansion {
+                            let mut stats_1 = Stats::default();
+                            let mut stats_2 = Stats::default();
+                            let mut cache_1 = cache.clone();
+                            let mut cache_2 = cache.clone();
+                            let (child_1, child_2) = rayon::join(
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        true,
+                                        &self.map,
+                                        config,
+                                        child_1_id,
+                                        &mut stats_1,
+                                        map_fingerprint,
+                                        &mut cache_1,
+                                    )
+                                },
+                                || {
+                                    current_open_node.update_constraint(
+                                        conflict,
+                                        false,
+                                        &self.map,
+                                        config,
+                                        child_2_id,
+                                        &mut stats_2,
+                                        map_fingerprint,
+                                        &mut cache_2,
+                                    )
+                                },
+                            );
+                            self.stats.merge(&stats_1);
+                            self.stats.merge(&stats_2);
+                            cache.merge(cache_1);
+                            cache.merge(cache_2);
+                            (child_1, child_2)
+                        } else {
+                            let child_1 = current_open_node.update_constraint(
+                                conflict,
+                                true,
+                                &self.map,
+                                config,
+                                child_1_id,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            let child_2 = current_open_node.update_constraint(
+                                conflict,
+                                false,
+                                &self.map,
+                                config,
+                                child_2_id,
+                                &mut self.stats,
+                                map_fingerprint,
+                                &mut cache,
+                            );
+                            (child_1, child_2)
+                        };
+
+                        // Duplicate detection compares against `child.cost`
+                        // (the admissible f), never `focal`: `focal`'s
+                        // conflict-count-based ordering has nothing to do
+                        // with solution quality, so admitting/pruning by it
+                        // would silently break ECBS's bounded-suboptimality
+                        // guarantee. Comparing costs keeps a dominated node
+                        // pruned only when an equivalent-or-better one (by
+                        // the same metric `open`/`focal` are bounded by) was
+                        // already expanded.
+                        if let Some(child) = child_1 {
+                            if !closed.contains(&child)
+                                && admit_node(config, &mut duplicate_signatures, &child)
                             {
-                                focal.insert(child_2.to_focal_node());
+                                if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                    focal.insert(child.to_focal_node(config));
+                                }
+                                open.insert(child);
                             }
+                            self.stats.high_level_expand_nodes += 1;
+                            restarter.record_expansion();
                         }
-                    }
-                } else {
-                    // No conflicts, return solution
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_open_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_open_node.paths,
-                    });
-                }
 
-                // Maintain the focal list
-                if !open.is_empty() {
-                    let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
-                    if old_f_min < new_f_min {
-                        open.iter().for_each(|node| {
-                            let node_cost: usize = node.low_level_f_min_agents.iter().sum();
-                            if node_cost as f64
-                                > self.low_level_subopt_factor.unwrap() * old_f_min as f64
-                                && node_cost as f64
-                                    <= self.low_level_subopt_factor.unwrap() * new_f_min as f64
+                        if let Some(child) = child_2 {
+                            if !closed.contains(&child)
+                                && admit_node(config, &mut duplicate_signatures, &child)
                             {
-                                focal.insert(node.to_focal_node());
+                                if child.cost as f64 <= (old_f_min as f64 * subopt_factor) {
+                                    focal.insert(child.to_focal_node(config));
+                                }
+                                open.insert(child);
                             }
+                            self.stats.high_level_expand_nodes += 1;
+                            restarter.record_expansion();
+                        }
+                    } else {
+                        // No conflicts, return solution
+                        debug!("Find solution");
+                        let total_solve_time = total_solve_start_time.elapsed();
+                        self.stats.time_ms = total_solve_time.as_micros() as usize;
+                        self.stats.costs = current_open_node.cost;
+                        let (soc_lb, _) = trivial_cost_lower_bounds(&self.agents, &self.map);
+                        self.stats.soc_lb = Some(soc_lb);
+
+                        self.stats.print(config);
+                        break 'search Some(Solution {
+                            paths: current_open_node.paths,
+                            ..Default::default()
                         });
                     }
+
+                    // Maintain the focal list
+                    if !open.is_empty() {
+                        let new_f_min = open.first().unwrap().low_level_f_min_agents.iter().sum();
+                        if old_f_min < new_f_min {
+                            open.iter().for_each(|node| {
+                                if node.cost as f64 > subopt_factor * old_f_min as f64
+                                    && node.cost as f64 <= subopt_factor * new_f_min as f64
+                                {
+                                    focal.insert(node.to_focal_node(config));
+                                }
+                            });
+                        }
+                    }
+
+                    if restarter.should_restart() {
+                        reseed_focal(&open, &mut focal, subopt_factor, config);
+                        self.stats.high_level_restarts += 1;
+                    }
+
+                    if let Some(beam_width) = config.beam_width {
+                        apply_beam_width(&mut open, &mut focal, beam_width, &mut self.stats, config);
+                    }
                 }
             }
 
             None
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
+            }
+        }
+
+        result
+    }
+}
+
+impl Solver for ECBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, None, None))
         } else {
-            None
+            self.solve_inner(config, None, None)
+        }
+    }
+
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        if config.op_parallel_expansion {
+            with_capped_thread_pool(config, || self.solve_inner(config, progress, stop))
+        } else {
+            self.solve_inner(config, progress, stop)
         }
     }
 }
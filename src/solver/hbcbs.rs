@@ -1,5 +1,9 @@
-use super::Solver;
-use crate::common::{Agent, HighLevelOpenNode, Solution};
+use super::progress::{is_stopped, ProgressTicker};
+use super::{AnytimeCallback, SolveProgress, Solver, StopFlag};
+use crate::common::{
+    select_prioritized_conflict, Agent, Conflict, HighLevelFocalNode, HighLevelOpenNode,
+    PathCache, Solution,
+};
 use crate::config::Config;
 use crate::map::Map;
 use crate::stat::Stats;
@@ -8,6 +12,31 @@ use std::collections::BTreeSet;
 use std::time::Instant;
 use tracing::debug;
 
+use crossbeam_channel::Sender;
+use rayon::prelude::*;
+
+/// Caps `open` (and, symmetrically, `focal`) to the best `beam_width` nodes
+/// by cost, dropping the worst-cost tail of the `BTreeSet`. Once a prune
+/// actually occurs, HBCBS's suboptimality guarantee no longer holds for
+/// this run; `stats.high_level_pruned_nodes` records how many nodes were
+/// dropped so callers can tell a beam-induced failure from a genuinely
+/// unsolvable instance.
+fn apply_beam_width(
+    open: &mut BTreeSet<HighLevelOpenNode>,
+    focal: &mut BTreeSet<HighLevelFocalNode>,
+    beam_width: usize,
+    stats: &mut Stats,
+    config: &Config,
+) {
+    while open.len() > beam_width {
+        let Some(worst) = open.pop_last() else {
+            break;
+        };
+        focal.remove(&worst.to_focal_node(config));
+        stats.high_level_pruned_nodes += 1;
+    }
+}
+
 pub struct HBCBS {
     agents: Vec<Agent>,
     map: Map,
@@ -22,127 +51,316 @@ impl HBCBS {
             stats: Stats::default(),
         }
     }
-}
 
-impl Solver for HBCBS {
-    fn solve(&mut self, config: &Config) -> Option<Solution> {
+    /// Expands a batch of frontier nodes, one already-picked conflict each.
+    /// When `config.num_threads > 1`, the batch is expanded concurrently via
+    /// a rayon parallel iterator, with each worker operating on its own
+    /// `Stats`/`PathCache` clone; these are folded back into `self.stats`
+    /// and `cache` once the batch completes. With a single thread this
+    /// collapses to a plain sequential map, so `num_threads == 1` behaves
+    /// exactly as before.
+    fn expand_batch(
+        &mut self,
+        jobs: &[(HighLevelOpenNode, Conflict)],
+        config: &Config,
+        map_fingerprint: u64,
+        cache: &mut PathCache,
+    ) -> Vec<(Option<HighLevelOpenNode>, Option<HighLevelOpenNode>)> {
+        let expand_one = |(node, conflict): &(HighLevelOpenNode, Conflict)| {
+            let mut stats_1 = Stats::default();
+            let mut stats_2 = Stats::default();
+            let mut cache_1 = cache.clone();
+            let mut cache_2 = cache.clone();
+            let child_1 = node.update_constraint(
+                conflict,
+                true,
+                &self.map,
+                config,
+                &mut stats_1,
+                map_fingerprint,
+                &mut cache_1,
+            );
+            let child_2 = node.update_constraint(
+                conflict,
+                false,
+                &self.map,
+                config,
+                &mut stats_2,
+                map_fingerprint,
+                &mut cache_2,
+            );
+            (child_1, child_2, stats_1, stats_2, cache_1, cache_2)
+        };
+
+        let results: Vec<_> = if config.num_threads > 1 {
+            jobs.par_iter().map(expand_one).collect()
+        } else {
+            jobs.iter().map(expand_one).collect()
+        };
+
+        results
+            .into_iter()
+            .map(|(child_1, child_2, stats_1, stats_2, cache_1, cache_2)| {
+                self.stats.merge(&stats_1);
+                self.stats.merge(&stats_2);
+                cache.merge(cache_1);
+                cache.merge(cache_2);
+                (child_1, child_2)
+            })
+            .collect()
+    }
+
+    fn solve_inner(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
         let total_solve_start_time = Instant::now();
         let high_level_subopt_factor = config.sub_optimal.0.unwrap();
 
         let mut open = BTreeSet::new();
         let mut focal = BTreeSet::new();
+        let mut ticker = ProgressTicker::new(progress, config.progress_interval_ms);
 
-        if let Some(root) =
-            HighLevelOpenNode::new(&self.agents, &self.map, config, "hbcbs", &mut self.stats)
-        {
-            open.insert(root.clone());
-            focal.insert(root.to_focal_node());
-
-            while let Some(current_focal_node) = focal.pop_first() {
-                let current_open_node = current_focal_node.to_open_node();
-                let old_f_min = open.first().unwrap().cost;
-
-                open.remove(&current_open_node);
-
-                if let Some(conflict) = current_open_node.conflicts.first() {
-                    debug!("conflict: {conflict:?}");
-
-                    let child_1 = current_open_node.update_constraint(
-                        conflict,
-                        true,
-                        &self.map,
-                        config,
-                        &mut self.stats,
-                    );
-
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_1 {
-                            if child.cost == current_open_node.cost
-                                && child.conflicts.len() < current_open_node.conflicts.len()
-                            {
-                                open.insert(current_open_node.update_bypass_path(
-                                    child.paths[conflict.agent_1].clone(),
-                                    child.conflicts.clone(),
-                                    conflict.agent_1,
-                                ));
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                continue;
-                            }
+        let map_fingerprint = self.map.fingerprint();
+        let mut cache = config
+            .low_level_cache_path
+            .as_deref()
+            .map(PathCache::load)
+            .unwrap_or_default();
+
+        let result = 'search: {
+            if let Some(root) = HighLevelOpenNode::new(
+                &self.agents,
+                &self.map,
+                config,
+                "hbcbs",
+                &mut self.stats,
+                map_fingerprint,
+                &mut cache,
+            ) {
+                open.insert(root.clone());
+                focal.insert(root.to_focal_node(config));
+
+                while !focal.is_empty() {
+                    if is_stopped(&stop) {
+                        break 'search None;
+                    }
+
+                    // Drain up to `config.num_threads` frontier nodes to expand together.
+                    let batch_size = config.num_threads.max(1);
+                    let mut batch = Vec::with_capacity(batch_size);
+                    while batch.len() < batch_size {
+                        match focal.pop_first() {
+                            Some(node) => batch.push(node),
+                            None => break,
                         }
                     }
 
-                    let child_2 = current_open_node.update_constraint(
-                        conflict,
-                        false,
-                        &self.map,
-                        config,
-                        &mut self.stats,
-                    );
-
-                    if config.op_bypass_conflicts {
-                        if let Some(ref child) = child_2 {
-                            if child.cost <= current_open_node.cost
-                                && child.conflicts.len() < current_open_node.conflicts.len()
-                            {
-                                open.insert(current_open_node.update_bypass_path(
-                                    child.paths[conflict.agent_2].clone(),
-                                    child.conflicts.clone(),
-                                    conflict.agent_2,
-                                ));
-                                focal.insert(child.to_focal_node());
-                                self.stats.high_level_expand_nodes += 1;
-                                continue;
-                            }
+                    let old_f_min = open.first().unwrap().cost;
+
+                    let mut jobs = Vec::with_capacity(batch.len());
+                    for current_focal_node in &batch {
+                        ticker.maybe_emit(|| SolveProgress {
+                            high_level_expanded: self.stats.high_level_expand_nodes,
+                            best_cost: current_focal_node.cost,
+                            lower_bound: current_focal_node.low_level_f_min_agents.iter().sum(),
+                            open_len: open.len(),
+                            focal_len: focal.len(),
+                            ..Default::default()
+                        });
+
+                        let current_open_node = current_focal_node.to_open_node();
+                        open.remove(&current_open_node);
+
+                        let conflict = if config.op_prioritize_conflicts {
+                            select_prioritized_conflict(
+                                &current_open_node.conflicts,
+                                current_open_node.agents.len(),
+                            )
+                            .or_else(|| current_open_node.conflicts.first())
+                        } else {
+                            current_open_node.conflicts.first()
+                        };
+
+                        if let Some(conflict) = conflict {
+                            debug!("conflict: {conflict:?}");
+                            jobs.push((current_open_node, conflict.clone()));
+                        } else {
+                            // No conflicts, return solution
+                            debug!("Find solution");
+                            let total_solve_time = total_solve_start_time.elapsed();
+                            self.stats.time_ms = total_solve_time.as_micros() as usize;
+                            self.stats.costs = current_focal_node.cost;
+
+                            self.stats.print(config);
+                            break 'search Some(Solution {
+                                paths: current_focal_node.paths.clone(),
+                                ..Default::default()
+                            });
                         }
                     }
 
-                    if let Some(child) = child_1 {
-                        if child.cost as f64 <= (old_f_min as f64 * high_level_subopt_factor) {
-                            focal.insert(child.to_focal_node());
+                    let children = self.expand_batch(&jobs, config, map_fingerprint, &mut cache);
+
+                    for ((current_open_node, conflict), (child_1, child_2)) in
+                        jobs.iter().zip(children)
+                    {
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_1 {
+                                if child.cost == current_open_node.cost
+                                    && child.conflicts.len() < current_open_node.conflicts.len()
+                                {
+                                    open.insert(current_open_node.update_bypass_path(
+                                        child.paths[conflict.agent_1].clone(),
+                                        child.conflicts.clone(),
+                                        conflict.agent_1,
+                                    ));
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    continue;
+                                }
+                            }
+                        }
+
+                        if config.op_bypass_conflicts {
+                            if let Some(ref child) = child_2 {
+                                if child.cost <= current_open_node.cost
+                                    && child.conflicts.len() < current_open_node.conflicts.len()
+                                {
+                                    open.insert(current_open_node.update_bypass_path(
+                                        child.paths[conflict.agent_2].clone(),
+                                        child.conflicts.clone(),
+                                        conflict.agent_2,
+                                    ));
+                                    focal.insert(child.to_focal_node(config));
+                                    self.stats.high_level_expand_nodes += 1;
+                                    continue;
+                                }
+                            }
+                        }
+
+                        if let Some(child) = child_1 {
+                            if child.cost as f64 <= (old_f_min as f64 * high_level_subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
+                        }
+
+                        if let Some(child) = child_2 {
+                            if child.cost as f64 <= (old_f_min as f64 * high_level_subopt_factor) {
+                                focal.insert(child.to_focal_node(config));
+                            }
+                            open.insert(child);
+                            self.stats.high_level_expand_nodes += 1;
                         }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
                     }
 
-                    if let Some(child) = child_2 {
-                        if child.cost as f64 <= (old_f_min as f64 * high_level_subopt_factor) {
-                            focal.insert(child.to_focal_node());
+                    // Maintain the focal list
+                    if !open.is_empty() {
+                        let new_f_min = open.first().unwrap().cost;
+                        if old_f_min < new_f_min {
+                            open.iter().for_each(|node| {
+                                if node.cost as f64 > high_level_subopt_factor * old_f_min as f64
+                                    && node.cost as f64
+                                        <= high_level_subopt_factor * new_f_min as f64
+                                {
+                                    focal.insert(node.to_focal_node(config));
+                                }
+                            });
                         }
-                        open.insert(child);
-                        self.stats.high_level_expand_nodes += 1;
                     }
-                } else {
-                    // No conflicts, return solution
-                    debug!("Find solution");
-                    let total_solve_time = total_solve_start_time.elapsed();
-                    self.stats.time_ms = total_solve_time.as_micros() as usize;
-                    self.stats.costs = current_focal_node.cost;
-
-                    self.stats.print(config);
-                    return Some(Solution {
-                        paths: current_focal_node.paths,
-                    });
-                }
 
-                // Maintain the focal list
-                if !open.is_empty() {
-                    let new_f_min = open.first().unwrap().cost;
-                    if old_f_min < new_f_min {
-                        open.iter().for_each(|node| {
-                            if node.cost as f64 > high_level_subopt_factor * old_f_min as f64
-                                && node.cost as f64 <= high_level_subopt_factor * new_f_min as f64
-                            {
-                                focal.insert(node.to_focal_node());
-                            }
-                        });
+                    if let Some(beam_width) = config.beam_width {
+                        apply_beam_width(&mut open, &mut focal, beam_width, &mut self.stats, config);
                     }
                 }
             }
 
             None
-        } else {
-            None
+        };
+
+        if let Some(path) = &config.low_level_cache_path {
+            if let Err(e) = cache.save(path) {
+                tracing::warn!("failed to persist low-level path cache to {path}: {e}");
+            }
         }
+
+        result
+    }
+}
+
+impl Solver for HBCBS {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        self.solve_inner(config, None, None)
+    }
+
+    fn solve_with_progress(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+    ) -> Option<Solution> {
+        self.solve_inner(config, progress, stop)
+    }
+
+    /// Re-solves with a high-level bound that shrinks towards 1.0 by
+    /// `config.anytime_decay` each round, reporting every improved solution
+    /// to `on_improved`. Each round re-expands the high-level tree from its
+    /// root, but `config.low_level_cache_path` lets rounds share the
+    /// low-level path cache across that round-trip (set it to get real
+    /// reuse; otherwise each round repeats the low-level searches too).
+    ///
+    /// When `config.beam_width` is set, a round finding no solution at all
+    /// doesn't end the search: `apply_beam_width` may simply have pruned
+    /// away the node that would have led to one, which isn't proof the
+    /// instance is unsolvable. Such a round instead doubles the beam width
+    /// and retries, recording the attempt in `stats.beam_widen_rounds`,
+    /// until a round succeeds, `stop`/`config.deadline` fires, or (with no
+    /// beam width to widen) it gives up like plain CBS would.
+    fn solve_anytime(
+        &mut self,
+        config: &Config,
+        progress: Option<Sender<SolveProgress>>,
+        stop: Option<StopFlag>,
+        mut on_improved: AnytimeCallback,
+    ) -> Option<Solution> {
+        let start = Instant::now();
+        let mut round_config = config.clone();
+        let mut best = None;
+
+        loop {
+            let Some(solution) =
+                self.solve_inner(&round_config, progress.clone(), stop.clone())
+            else {
+                let Some(beam_width) = round_config.beam_width else {
+                    break;
+                };
+                if is_stopped(&stop) || config.deadline.is_some_and(|d| start.elapsed() >= d) {
+                    break;
+                }
+                self.stats.beam_widen_rounds += 1;
+                round_config.beam_width = Some(beam_width.saturating_mul(2));
+                continue;
+            };
+            on_improved(&solution, &self.stats);
+            best = Some(solution);
+
+            let Some(decay) = config.anytime_decay else {
+                break;
+            };
+            let bound = round_config.sub_optimal.0.unwrap();
+            if bound <= 1.0 + f64::EPSILON
+                || is_stopped(&stop)
+                || config.deadline.is_some_and(|d| start.elapsed() >= d)
+            {
+                break;
+            }
+            round_config.sub_optimal.0 = Some((1.0 + (bound - 1.0) * decay).max(1.0));
+        }
+
+        best
     }
 }
@@ -0,0 +1,250 @@
+mod sat;
+
+use sat::{solve as sat_solve, CnfFormula};
+
+use super::Solver;
+use crate::algorithm::{a_star_search, construct_mdd};
+use crate::common::{Agent, Mdd, NodeId, SearchResult, Solution};
+use crate::config::Config;
+use crate::map::Map;
+use crate::stat::Stats;
+
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::debug;
+
+/// An optimal solver that, instead of splitting constraints CBS-style,
+/// encodes the problem at a fixed sum-of-costs budget as CNF and hands it to
+/// a small embedded DPLL SAT engine with unit propagation (see `mddsat::sat`). On UNSAT the cost
+/// budget is incremented and the instance is re-encoded, which is the
+/// standard increasing-cost loop used by MDD-SAT / SAT-MAPF solvers.
+pub struct MddSat {
+    agents: Vec<Agent>,
+    map: Map,
+    stats: Stats,
+}
+
+/// A Boolean variable stands for "agent `agent_id` occupies `position` at
+/// `time_step`"; variables are allocated densely per agent's MDD so the CNF
+/// stays small even though the search space is not.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+struct NodeVar {
+    agent_id: usize,
+    position: (usize, usize),
+    time_step: usize,
+}
+
+impl MddSat {
+    pub fn new(agents: Vec<Agent>, map: &Map) -> Self {
+        MddSat {
+            agents,
+            map: map.clone(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Builds each agent's MDD at the given per-agent cost, returning `None`
+    /// if some agent cannot reach its goal within that many steps.
+    fn build_mdds(&mut self, budgets: &[usize]) -> Option<Vec<Mdd>> {
+        let mut mdds = Vec::with_capacity(self.agents.len());
+        for (agent, &budget) in self.agents.iter().zip(budgets.iter()) {
+            match a_star_search(
+                &self.map,
+                agent,
+                &Default::default(),
+                0,
+                true,
+                "astar",
+                None,
+                None,
+                &mut self.stats,
+            ) {
+                SearchResult::WithMDD(Some((_, f_min, _))) if f_min > budget => return None,
+                SearchResult::WithMDD(Some(_)) => {
+                    mdds.push(construct_mdd(&self.map, agent, &Default::default(), budget))
+                }
+                _ => return None,
+            }
+        }
+        Some(mdds)
+    }
+
+    /// Encodes the per-agent MDDs plus pairwise conflict clauses as CNF and
+    /// solves it; `Some(paths)` on SAT, `None` on UNSAT at this budget.
+    fn encode_and_solve(&self, mdds: &[Mdd]) -> Option<Vec<Vec<(usize, usize)>>> {
+        let mut var_ids: HashMap<NodeVar, i64> = HashMap::new();
+        let mut next_var = 1i64;
+        let mut var_of = |agent_id: usize, position: (usize, usize), time_step: usize| {
+            *var_ids
+                .entry(NodeVar {
+                    agent_id,
+                    position,
+                    time_step,
+                })
+                .or_insert_with(|| {
+                    let id = next_var;
+                    next_var += 1;
+                    id
+                })
+        };
+
+        let mut clauses = Vec::new();
+
+        for (agent_id, mdd) in mdds.iter().enumerate() {
+            // Exactly-one-position-per-layer, plus transition clauses linking
+            // a node to at least one of its MDD children.
+            for time_step in 0..mdd.len() {
+                let layer = mdd.layer(time_step);
+                let layer_vars: Vec<i64> = layer
+                    .iter()
+                    .map(|node| var_of(agent_id, node.position, time_step))
+                    .collect();
+                if !layer_vars.is_empty() {
+                    clauses.push(layer_vars.clone());
+                }
+                for i in 0..layer_vars.len() {
+                    for j in (i + 1)..layer_vars.len() {
+                        clauses.push(vec![-layer_vars[i], -layer_vars[j]]);
+                    }
+                }
+
+                if time_step + 1 < mdd.len() {
+                    let range = mdd.layers[time_step].clone();
+                    for offset in range {
+                        let node_id = NodeId(offset);
+                        let node = mdd.node(node_id);
+                        let here = var_of(agent_id, node.position, time_step);
+                        let mut children = mdd.children(node_id).peekable();
+                        if children.peek().is_none() {
+                            clauses.push(vec![-here]);
+                            continue;
+                        }
+                        let mut clause = vec![-here];
+                        clause.extend(
+                            children.map(|child| var_of(agent_id, mdd.node(child).position, time_step + 1)),
+                        );
+                        clauses.push(clause);
+                    }
+                }
+            }
+        }
+
+        // Vertex and swap/edge conflict clauses between every agent pair.
+        // Layer bitsets are built once per agent pair and time step so membership
+        // tests below are a word lookup instead of a linear scan over that
+        // layer's nodes.
+        let linearize = |position: (usize, usize)| position.0 * self.map.width + position.1;
+        for a in 0..mdds.len() {
+            for b in (a + 1)..mdds.len() {
+                let max_len = mdds[a].len().max(mdds[b].len());
+                for t in 0..max_len {
+                    let bitset_b_t = mdds[b].layer_bitset(t, &self.map);
+                    for node in mdds[a].layer(t) {
+                        if bitset_b_t.test(linearize(node.position)) {
+                            clauses.push(vec![-var_of(a, node.position, t), -var_of(b, node.position, t)]);
+                        }
+                    }
+                    if t + 1 >= max_len {
+                        continue;
+                    }
+                    let bitset_b_t1 = mdds[b].layer_bitset(t + 1, &self.map);
+                    for from_a in mdds[a].layer(t) {
+                        for to_a in mdds[a].layer(t + 1) {
+                            if bitset_b_t1.test(linearize(from_a.position))
+                                && bitset_b_t.test(linearize(to_a.position))
+                            {
+                                // Structural reachability alone doesn't mean
+                                // `b` actually swaps here in this assignment,
+                                // so the clause must also negate `b`'s own
+                                // literals for the mirrored move rather than
+                                // outright forbidding `a`'s edge: it's only
+                                // violated when both agents pick their half
+                                // of the swap.
+                                clauses.push(vec![
+                                    -var_of(a, from_a.position, t),
+                                    -var_of(a, to_a.position, t + 1),
+                                    -var_of(b, to_a.position, t),
+                                    -var_of(b, from_a.position, t + 1),
+                                ]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut formula = CnfFormula::new((next_var - 1).max(0) as usize);
+        for clause in clauses {
+            formula.add_clause(clause);
+        }
+
+        let assignment = sat_solve(&formula)?;
+
+        let mut paths = Vec::with_capacity(mdds.len());
+        for (agent_id, mdd) in mdds.iter().enumerate() {
+            let mut path = Vec::with_capacity(mdd.len());
+            for time_step in 0..mdd.len() {
+                let chosen = mdd
+                    .layer(time_step)
+                    .iter()
+                    .find(|node| {
+                        let var = var_ids[&NodeVar {
+                            agent_id,
+                            position: node.position,
+                            time_step,
+                        }];
+                        assignment[(var - 1) as usize]
+                    })
+                    .map(|node| node.position)?;
+                path.push(chosen);
+            }
+            paths.push(path);
+        }
+        Some(paths)
+    }
+}
+
+impl Solver for MddSat {
+    fn solve(&mut self, config: &Config) -> Option<Solution> {
+        let total_solve_start_time = Instant::now();
+
+        let mut budgets: Vec<usize> = self
+            .agents
+            .iter()
+            .map(|agent| self.map.heuristic[agent.id].get(agent.start))
+            .collect();
+
+        loop {
+            debug!("MDD-SAT trying budgets: {budgets:?}");
+            let mdds = match self.build_mdds(&budgets) {
+                Some(mdds) => mdds,
+                None => {
+                    // Some agent cannot reach its goal at all under these
+                    // budgets (not merely blocked by conflicts): infeasible.
+                    return None;
+                }
+            };
+
+            if let Some(paths) = self.encode_and_solve(&mdds) {
+                let total_solve_time = total_solve_start_time.elapsed();
+                self.stats.time_ms = total_solve_time.as_micros() as usize;
+                self.stats.costs = paths.iter().map(|p| p.len() - 1).sum();
+                self.stats.print(config);
+                return Some(Solution {
+                    paths,
+                    ..Default::default()
+                });
+            }
+
+            // UNSAT at this sum-of-costs budget: raise the cheapest agent by
+            // one and re-encode.
+            if let Some((idx, _)) = budgets
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, &cost)| cost)
+            {
+                budgets[idx] += 1;
+            }
+        }
+    }
+}
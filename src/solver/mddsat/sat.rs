@@ -0,0 +1,133 @@
+//! A small, self-contained CNF-SAT engine used by the `MddSat` solver.
+//!
+//! Literals are signed `i64`s (1-indexed), where `-l` is the negation of `l`.
+//! The solver performs unit propagation on top of plain DPLL backtracking (no
+//! pure-literal elimination, no clause learning/CDCL); it is not a complete
+//! modern SAT engine, but it is sufficient for the small, densely-constrained
+//! formulas produced by the bounded-cost MDD encoding.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub(crate) struct CnfFormula {
+    pub(crate) num_vars: usize,
+    pub(crate) clauses: Vec<Vec<i64>>,
+}
+
+impl CnfFormula {
+    pub(crate) fn new(num_vars: usize) -> Self {
+        CnfFormula {
+            num_vars,
+            clauses: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_clause(&mut self, clause: Vec<i64>) {
+        self.clauses.push(clause);
+    }
+}
+
+/// Attempts to find a satisfying assignment. Returns `assignment[var - 1]` on
+/// success (`true`/`false` per variable), `None` if the formula is UNSAT.
+pub(crate) fn solve(formula: &CnfFormula) -> Option<Vec<bool>> {
+    let mut assignment: HashMap<i64, bool> = HashMap::new();
+    if dpll(&formula.clauses, &mut assignment) {
+        let mut result = vec![false; formula.num_vars];
+        for (var, value) in assignment {
+            if var > 0 {
+                result[(var - 1) as usize] = value;
+            }
+        }
+        Some(result)
+    } else {
+        None
+    }
+}
+
+fn dpll(clauses: &[Vec<i64>], assignment: &mut HashMap<i64, bool>) -> bool {
+    let simplified = match unit_propagate(clauses, assignment) {
+        Some(clauses) => clauses,
+        None => return false, // empty clause derived: conflict
+    };
+
+    if simplified.is_empty() {
+        return true; // all clauses satisfied
+    }
+
+    // Pick the first unassigned literal appearing in the remaining clauses.
+    let decision_var = simplified[0]
+        .iter()
+        .find(|lit| !assignment.contains_key(&lit.abs()))
+        .copied()
+        .unwrap_or(simplified[0][0]);
+    let var = decision_var.abs();
+
+    for &value in &[true, false] {
+        let mut trial = assignment.clone();
+        trial.insert(var, value);
+        if dpll(&simplified, &mut trial) {
+            *assignment = trial;
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Repeatedly resolves unit clauses against the assignment, returning the
+/// remaining (non-trivially-satisfied) clauses, or `None` on a derived
+/// empty clause (a conflict).
+fn unit_propagate(
+    clauses: &[Vec<i64>],
+    assignment: &mut HashMap<i64, bool>,
+) -> Option<Vec<Vec<i64>>> {
+    let mut remaining: Vec<Vec<i64>> = clauses.to_vec();
+
+    loop {
+        let mut unit = None;
+        let mut next_remaining = Vec::with_capacity(remaining.len());
+        let mut satisfied_any = false;
+
+        for clause in &remaining {
+            let mut alive = Vec::with_capacity(clause.len());
+            let mut satisfied = false;
+
+            for &lit in clause {
+                match assignment.get(&lit.abs()) {
+                    Some(&value) if value == (lit > 0) => {
+                        satisfied = true;
+                        break;
+                    }
+                    Some(_) => continue, // literal falsified, drop it
+                    None => alive.push(lit),
+                }
+            }
+
+            if satisfied {
+                satisfied_any = true;
+                continue;
+            }
+            if alive.is_empty() {
+                return None; // conflict: every literal falsified
+            }
+            if alive.len() == 1 && unit.is_none() {
+                unit = Some(alive[0]);
+            }
+            next_remaining.push(alive);
+        }
+
+        remaining = next_remaining;
+
+        match unit {
+            Some(lit) => {
+                assignment.insert(lit.abs(), lit > 0);
+            }
+            None => {
+                if satisfied_any || !remaining.is_empty() {
+                    return Some(remaining);
+                }
+                return Some(remaining);
+            }
+        }
+    }
+}
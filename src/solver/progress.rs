@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+
+/// A snapshot of high-level search progress, emitted periodically so a
+/// caller can monitor a long-running solve without waiting for it to finish.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SolveProgress {
+    pub high_level_expanded: usize,
+    pub best_cost: usize,
+    pub lower_bound: usize,
+    pub open_len: usize,
+    pub focal_len: usize,
+    /// Low-level open+focal node expansions so far. Only populated by `CBS`
+    /// (see `CBS::solve_inner`) for now; other solvers leave this at 0
+    /// rather than claim a number they don't track at the call site.
+    pub low_level_expanded: usize,
+    /// Milliseconds elapsed since this solve began. Only populated by `CBS`
+    /// for now, like `low_level_expanded` above.
+    pub elapsed_ms: usize,
+}
+
+/// Shared cooperative-cancellation flag: solvers check this at the top of
+/// their high-level loop and stop instead of continuing once it is set.
+/// Most return `None` (CBS has no usable incumbent until the root is
+/// conflict-free); ACBS's anytime search returns its incumbent if it has one.
+pub type StopFlag = Arc<AtomicBool>;
+
+/// Throttles progress emission to roughly once per `interval`, so a tight
+/// `while let` loop isn't sending on every single high-level expansion.
+pub(crate) struct ProgressTicker {
+    sender: Option<Sender<SolveProgress>>,
+    last_emit: Instant,
+    interval: Duration,
+}
+
+impl ProgressTicker {
+    pub(crate) fn new(sender: Option<Sender<SolveProgress>>, interval_ms: u64) -> Self {
+        ProgressTicker {
+            sender,
+            last_emit: Instant::now(),
+            interval: Duration::from_millis(interval_ms),
+        }
+    }
+
+    /// Calls `snapshot` and sends its result if the interval has elapsed
+    /// since the last emission; skips building the snapshot entirely when
+    /// there is no sender or the interval hasn't elapsed yet. Returns `true`
+    /// once the receiver has been dropped, so a caller that wants to let a
+    /// caller-side cancellation (dropping its end of the channel) stop the
+    /// search can check the result; callers that don't care (everyone but
+    /// `CBS::solve_inner` today) just discard it as before.
+    pub(crate) fn maybe_emit(&mut self, snapshot: impl FnOnce() -> SolveProgress) -> bool {
+        if let Some(sender) = &self.sender {
+            if self.last_emit.elapsed() >= self.interval {
+                self.last_emit = Instant::now();
+                if let Err(crossbeam_channel::TrySendError::Disconnected(_)) =
+                    sender.try_send(snapshot())
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Checks a cooperative stop flag, treating the absence of one as "never
+/// stop" so callers can pass `None` unconditionally.
+pub(crate) fn is_stopped(stop: &Option<StopFlag>) -> bool {
+    stop.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+}
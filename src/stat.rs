@@ -1,8 +1,7 @@
-use std::fs::OpenOptions;
-use std::io::Write;
 use tracing::{debug, error};
 
 use crate::config::Config;
+use crate::output;
 
 #[derive(Debug, Clone, Default)]
 pub(crate) struct Stats {
@@ -11,47 +10,69 @@ pub(crate) struct Stats {
     pub(crate) low_level_expand_open_nodes: usize,
     pub(crate) low_level_expand_focal_nodes: usize,
     pub(crate) high_level_expand_nodes: usize,
+    pub(crate) low_level_cache_hits: usize,
+    pub(crate) low_level_cache_misses: usize,
+    /// Number of high-level nodes dropped by `config.beam_width` pruning.
+    /// Nonzero means the suboptimality guarantee no longer holds for this
+    /// run: a `None` result may be a beam-induced failure rather than a
+    /// genuinely unsolvable instance.
+    pub(crate) high_level_pruned_nodes: usize,
+    /// Trivial sum-of-costs lower bound (each agent's own optimal length,
+    /// ignoring every other agent; see `common::trivial_cost_lower_bounds`),
+    /// set by bounded-suboptimal solvers (ECBS, BCBS) once a solution is
+    /// found so `print` can report the realized suboptimality ratio
+    /// `costs / soc_lb` alongside the configured bound.
+    pub(crate) soc_lb: Option<usize>,
+    /// Number of times the high-level search cleared and re-seeded `focal`
+    /// under `config.restart_schedule`. See `RestartScheduler` in
+    /// `solver/ecbs.rs`.
+    pub(crate) high_level_restarts: usize,
+    /// Number of times `solve_anytime` doubled `config.beam_width` and
+    /// retried after a round found no solution at the current width. A
+    /// beam-pruned round returning `None` isn't proof the instance is
+    /// unsolvable, so `HBCBS`/`BCBS` widen and retry instead of giving up;
+    /// this counts how many widenings it took.
+    pub(crate) beam_widen_rounds: usize,
+    /// Number of low-level open/focal nodes dropped by
+    /// `config.low_level_beam_width` pruning. Nonzero means the solver's
+    /// suboptimality guarantee no longer holds for this run: a node within
+    /// the bound may have been discarded before it got a chance to expand.
+    pub(crate) low_level_pruned_nodes: usize,
+    /// Set when `config.time_limit_ms`/`config.high_level_node_limit` cut
+    /// the high-level search short (see `CBS::solve_inner`). The returned
+    /// `Solution` is then the lowest-cost node found so far rather than a
+    /// verified-optimal, conflict-free one.
+    pub(crate) budget_exhausted: bool,
 }
 
 impl Stats {
-    pub(crate) fn print(&self, config: &Config) {
-        if config.output_path.is_none() {
-            return;
-        }
-        let output_path = config.output_path.as_ref().unwrap().clone();
-        let mut file = OpenOptions::new()
-            .append(true)
-            .open(&output_path)
-            .unwrap();
-
-        let file_content = format!(
-            "{},{},{},{:?},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
-            config.map_path,
-            config.yaml_path,
-            config.num_agents,
-            config.agents_dist,
-            config.seed,
-            config.solver,
-            config.sub_optimal.0.unwrap_or(f64::NAN),
-            config.sub_optimal.1.unwrap_or(f64::NAN),
-            config.op_prioritize_conflicts,
-            config.op_bypass_conflicts,
-            config.op_target_reasoning,
-            self.costs,
-            self.time_ms,
-            self.high_level_expand_nodes,
-            self.low_level_expand_open_nodes,
-            self.low_level_expand_focal_nodes,
-            self.low_level_expand_focal_nodes + self.low_level_expand_open_nodes
-        );
+    /// Fold the counters of a stats accumulator produced by a concurrently
+    /// executed branch (e.g. a parallel child expansion) into this one.
+    pub(crate) fn merge(&mut self, other: &Stats) {
+        self.low_level_expand_open_nodes += other.low_level_expand_open_nodes;
+        self.low_level_expand_focal_nodes += other.low_level_expand_focal_nodes;
+        self.high_level_expand_nodes += other.high_level_expand_nodes;
+        self.low_level_cache_hits += other.low_level_cache_hits;
+        self.low_level_cache_misses += other.low_level_cache_misses;
+        self.high_level_pruned_nodes += other.high_level_pruned_nodes;
+        self.high_level_restarts += other.high_level_restarts;
+        self.beam_widen_rounds += other.beam_widen_rounds;
+        self.low_level_pruned_nodes += other.low_level_pruned_nodes;
+        self.budget_exhausted |= other.budget_exhausted;
+    }
 
+    pub(crate) fn print(&self, config: &Config) {
         debug!(
             "{:?} Cost {:?} Time {:?}(microseconds) High level expand nodes number: {:?} Low level expand nodes number {:?}", config.solver,
-            self.costs, self.time_ms, self.high_level_expand_nodes, self.low_level_expand_focal_nodes + self.low_level_expand_open_nodes 
+            self.costs, self.time_ms, self.high_level_expand_nodes, self.low_level_expand_focal_nodes + self.low_level_expand_open_nodes
         );
 
-        if let Err(e) = file.write_all(file_content.as_bytes()) {
-            error!("Failed to write to file '{output_path}': {e}");
+        if config.output_path.is_none() {
+            return;
+        }
+
+        if let Err(e) = output::write_record(config, self) {
+            error!("Failed to write stats record: {e}");
         }
     }
 }
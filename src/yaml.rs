@@ -136,11 +136,7 @@ impl Scenario {
                 .ok_or_else(|| "Failed to choose a random route".to_string())?;
 
             let route = &bucket[*route_index];
-            agents.push(Agent {
-                id: agent_id,
-                start: (route.start_x, route.start_y),
-                goal: (route.goal_x, route.goal_y),
-            });
+            agents.push(Agent::new(agent_id, (route.start_x, route.start_y), (route.goal_x, route.goal_y)));
 
             // Mark this route as used
             used_routes
@@ -182,11 +178,7 @@ impl Scenario {
                 .pop()
                 .ok_or("Ran out of routes unexpectedly")?;
 
-            agents.push(Agent {
-                id: agent_id,
-                start: (route.start_x, route.start_y),
-                goal: (route.goal_x, route.goal_y),
-            });
+            agents.push(Agent::new(agent_id, (route.start_x, route.start_y), (route.goal_x, route.goal_y)));
 
             // Mark this route as used
             used_routes.insert(route);
@@ -219,16 +211,8 @@ mod tests {
             .generate_agents_by_buckets(num_agents, agent_buckets, &mut rng)
             .unwrap();
         let answer = [
-            Agent {
-                id: 0,
-                start: (30, 23),
-                goal: (29, 20),
-            },
-            Agent {
-                id: 1,
-                start: (13, 26),
-                goal: (11, 22),
-            },
+            Agent::new(0, (30, 23), (29, 20)),
+            Agent::new(1, (13, 26), (11, 22)),
         ];
         assert_eq!(agents, answer);
     }